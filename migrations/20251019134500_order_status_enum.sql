@@ -0,0 +1,36 @@
+-- Add migration script here
+CREATE TYPE order_status AS ENUM (
+    'cart',
+    'placed',
+    'paid',
+    'shipped',
+    'delivered',
+    'cancelled',
+    'refunded'
+);
+
+-- unknown free-form values become 'placed', every order in the table was already submitted
+ALTER TABLE orders
+    ALTER COLUMN status TYPE order_status USING (
+        CASE
+            WHEN lower(status) IN ('cart', 'placed', 'paid', 'shipped', 'delivered', 'cancelled', 'refunded')
+                THEN lower(status)::order_status
+            ELSE 'placed'::order_status
+        END
+    );
+
+ALTER TABLE orders
+    ALTER COLUMN status SET DEFAULT 'cart',
+    ALTER COLUMN status SET NOT NULL;
+
+CREATE TABLE order_status_history (
+    id BIGSERIAL PRIMARY KEY,
+    order_id UUID NOT NULL,
+    from_status order_status,
+    to_status order_status NOT NULL,
+    actor VARCHAR(64) NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    FOREIGN KEY (order_id) REFERENCES orders (id)
+);
+
+CREATE INDEX order_status_history_order_id_idx ON order_status_history (order_id);
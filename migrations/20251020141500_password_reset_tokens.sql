@@ -0,0 +1,13 @@
+-- Add migration script here
+CREATE TABLE password_reset_tokens (
+    id BIGSERIAL PRIMARY KEY,
+    customer_id BIGINT NOT NULL,
+    token_hash VARCHAR(64) NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMP NOT NULL,
+    used_at TIMESTAMP,
+    CONSTRAINT password_reset_tokens_token_hash_uq UNIQUE (token_hash),
+    FOREIGN KEY (customer_id) REFERENCES customers (id) ON DELETE CASCADE
+);
+
+CREATE INDEX password_reset_tokens_customer_id_idx ON password_reset_tokens (customer_id);
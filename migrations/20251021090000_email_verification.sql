@@ -0,0 +1,17 @@
+-- Add migration script here
+ALTER TABLE customers ADD COLUMN email_verified_at TIMESTAMP;
+
+-- `email` is the address the link was sent to, the link does nothing once the customer changes it
+CREATE TABLE email_verification_tokens (
+    id BIGSERIAL PRIMARY KEY,
+    customer_id BIGINT NOT NULL,
+    email VARCHAR(255) NOT NULL,
+    token_hash VARCHAR(64) NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMP NOT NULL,
+    used_at TIMESTAMP,
+    CONSTRAINT email_verification_tokens_token_hash_uq UNIQUE (token_hash),
+    FOREIGN KEY (customer_id) REFERENCES customers (id) ON DELETE CASCADE
+);
+
+CREATE INDEX email_verification_tokens_customer_id_idx ON email_verification_tokens (customer_id);
@@ -0,0 +1,21 @@
+-- Add migration script here
+CREATE TYPE login_outcome AS ENUM (
+    'success',
+    'invalid_credentials',
+    'blocked'
+);
+
+-- audit of every login form submission, also the source for throttling
+CREATE TABLE login_attempts (
+    id BIGSERIAL PRIMARY KEY,
+    email VARCHAR(255) NOT NULL,
+    ip_address VARCHAR(64),
+    user_agent TEXT,
+    customer_id BIGINT,
+    outcome login_outcome NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    FOREIGN KEY (customer_id) REFERENCES customers (id) ON DELETE SET NULL
+);
+
+CREATE INDEX login_attempts_email_created_at_idx ON login_attempts (email, created_at);
+CREATE INDEX login_attempts_ip_address_created_at_idx ON login_attempts (ip_address, created_at);
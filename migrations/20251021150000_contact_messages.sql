@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE contact_messages (
+    id BIGSERIAL PRIMARY KEY,
+    customer_id BIGINT,
+    name VARCHAR(255) NOT NULL,
+    email VARCHAR(255) NOT NULL,
+    message TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    FOREIGN KEY (customer_id) REFERENCES customers (id) ON DELETE SET NULL
+);
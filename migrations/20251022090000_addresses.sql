@@ -0,0 +1,26 @@
+-- Add migration script here
+CREATE TABLE addresses (
+    id BIGSERIAL PRIMARY KEY,
+    customer_id BIGINT NOT NULL,
+    label VARCHAR(255) NOT NULL,
+    full_name VARCHAR(255) NOT NULL,
+    line1 VARCHAR(255) NOT NULL,
+    line2 VARCHAR(255),
+    city VARCHAR(255) NOT NULL,
+    postal_code VARCHAR(32) NOT NULL,
+    country VARCHAR(255) NOT NULL,
+    phone VARCHAR(255),
+    is_default_shipping BOOLEAN NOT NULL DEFAULT FALSE,
+    is_default_billing BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    FOREIGN KEY (customer_id) REFERENCES customers (id) ON DELETE CASCADE
+);
+
+CREATE INDEX addresses_customer_id_idx ON addresses (customer_id);
+CREATE UNIQUE INDEX addresses_default_shipping_uq ON addresses (customer_id) WHERE is_default_shipping;
+CREATE UNIQUE INDEX addresses_default_billing_uq ON addresses (customer_id) WHERE is_default_billing;
+
+-- copies of the addresses at checkout time, editing or deleting an address doesn't touch placed orders
+ALTER TABLE orders ADD COLUMN shipping_address JSONB;
+ALTER TABLE orders ADD COLUMN billing_address JSONB;
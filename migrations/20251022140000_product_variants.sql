@@ -0,0 +1,37 @@
+-- Add migration script here
+CREATE TABLE product_variants (
+    id SERIAL PRIMARY KEY,
+    product_id INTEGER NOT NULL,
+    sku VARCHAR(64) NOT NULL,
+    size VARCHAR(16) NOT NULL,
+    colour VARCHAR(64) NOT NULL,
+    price DECIMAL(10,2), -- NULL: the product price applies
+    stock INTEGER NOT NULL DEFAULT 0 CHECK (stock >= 0),
+    is_active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    CONSTRAINT product_variants_sku_uq UNIQUE (sku),
+    CONSTRAINT product_variants_product_size_colour_uq UNIQUE (product_id, size, colour),
+    FOREIGN KEY (product_id) REFERENCES products (id) ON DELETE CASCADE
+);
+
+CREATE INDEX product_variants_product_id_idx ON product_variants (product_id);
+
+-- existing products get a single variant so they stay listed, real sizes are entered afterwards.
+-- opening stock is deliberately 0: the shop never counted its goods and a made up number would oversell,
+-- so these variants show as sold out until a stock take is entered with `adjust-stock <sku> =<count>`
+INSERT INTO product_variants (product_id, sku, size, colour)
+SELECT id, COALESCE(code, 'P' || id), 'one size', 'standard' FROM products;
+
+-- every product has exactly one variant so far, cart lines map onto it
+ALTER TABLE cart_items ADD COLUMN variant_id INTEGER REFERENCES product_variants (id);
+UPDATE cart_items ci
+SET variant_id = v.id
+FROM product_variants v
+WHERE v.product_id = ci.product_id;
+ALTER TABLE cart_items ALTER COLUMN variant_id SET NOT NULL;
+ALTER TABLE cart_items DROP CONSTRAINT cart_items_cart_product_uq;
+ALTER TABLE cart_items ADD CONSTRAINT cart_items_cart_variant_uq UNIQUE (cart_id, variant_id);
+
+-- NULL for lines ordered before variants existed
+ALTER TABLE orders_product ADD COLUMN variant_id INTEGER REFERENCES product_variants (id);
@@ -0,0 +1,35 @@
+-- Add migration script here
+CREATE TYPE stock_movement_reason AS ENUM (
+    'adjustment',
+    'reservation',
+    'release'
+);
+
+-- every change of product_variants.stock, the sum per variant equals its stock
+CREATE TABLE stock_movements (
+    id BIGSERIAL PRIMARY KEY,
+    variant_id INTEGER NOT NULL,
+    quantity INTEGER NOT NULL, -- negative when stock goes out
+    reason stock_movement_reason NOT NULL,
+    order_id UUID,
+    note TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    FOREIGN KEY (variant_id) REFERENCES product_variants (id),
+    FOREIGN KEY (order_id) REFERENCES orders (id)
+);
+
+CREATE INDEX stock_movements_variant_id_idx ON stock_movements (variant_id);
+CREATE INDEX stock_movements_order_id_idx ON stock_movements (order_id);
+
+INSERT INTO stock_movements (variant_id, quantity, reason, note)
+SELECT id, stock, 'adjustment', 'opening balance' FROM product_variants WHERE stock > 0;
+
+DO $$
+DECLARE
+    uncounted INTEGER;
+BEGIN
+    SELECT count(*) INTO uncounted FROM product_variants WHERE stock = 0 AND is_active;
+    IF uncounted > 0 THEN
+        RAISE NOTICE '% active variants have no stock and can''t be bought until counted with adjust-stock', uncounted;
+    END IF;
+END $$;
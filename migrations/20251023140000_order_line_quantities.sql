@@ -0,0 +1,33 @@
+-- Add migration script here
+ALTER TABLE orders_product
+    ADD COLUMN quantity INTEGER NOT NULL DEFAULT 1 CHECK (quantity > 0),
+    ADD COLUMN unit_price INTEGER,
+    ADD COLUMN line_total INTEGER;
+
+-- lines used to be stored as one row per unit: fold identical rows into the oldest one
+UPDATE orders_product op
+SET quantity = d.quantity
+FROM (SELECT min(id) AS keep_id, count(*)::int AS quantity
+      FROM orders_product
+      GROUP BY order_id, product_id, variant_id, sum) d
+WHERE op.id = d.keep_id;
+
+DELETE FROM orders_product op
+WHERE EXISTS (SELECT 1
+              FROM orders_product k
+              WHERE k.order_id IS NOT DISTINCT FROM op.order_id
+                AND k.product_id IS NOT DISTINCT FROM op.product_id
+                AND k.variant_id IS NOT DISTINCT FROM op.variant_id
+                AND k.sum IS NOT DISTINCT FROM op.sum
+                AND k.id < op.id);
+
+UPDATE orders_product
+SET unit_price = coalesce(sum, 0),
+    line_total = coalesce(sum, 0) * quantity;
+
+ALTER TABLE orders_product
+    ALTER COLUMN quantity DROP DEFAULT,
+    ALTER COLUMN unit_price SET NOT NULL,
+    ALTER COLUMN line_total SET NOT NULL,
+    DROP COLUMN sum,
+    ADD CONSTRAINT orders_product_order_variant_key UNIQUE (order_id, variant_id);
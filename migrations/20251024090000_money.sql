@@ -0,0 +1,21 @@
+-- Add migration script here
+-- amount in minor units (cents) plus ISO 4217 code, decoded into models::money::Money
+CREATE TYPE money_amount AS (
+    amount BIGINT,
+    currency TEXT
+);
+
+ALTER TABLE products
+    ALTER COLUMN price TYPE money_amount
+        USING ROW(round(price * 100)::bigint, 'USD')::money_amount;
+
+ALTER TABLE product_variants
+    ALTER COLUMN price TYPE money_amount
+        USING CASE WHEN price IS NULL THEN NULL ELSE ROW(round(price * 100)::bigint, 'USD')::money_amount END;
+
+-- order lines were stored in whole dollars
+ALTER TABLE orders_product
+    ALTER COLUMN unit_price TYPE money_amount
+        USING ROW(unit_price::bigint * 100, 'USD')::money_amount,
+    ALTER COLUMN line_total TYPE money_amount
+        USING ROW(line_total::bigint * 100, 'USD')::money_amount;
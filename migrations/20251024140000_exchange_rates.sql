@@ -0,0 +1,12 @@
+-- Add migration script here
+-- how much of `currency` one unit of the base currency (USD) buys, imported from CSV
+CREATE TABLE exchange_rates (
+    currency TEXT PRIMARY KEY,
+    rate NUMERIC(18, 8) NOT NULL CHECK (rate > 0),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- order lines are stored in the order currency, converted from USD at exchange_rate
+ALTER TABLE orders
+    ADD COLUMN currency TEXT NOT NULL DEFAULT 'USD',
+    ADD COLUMN exchange_rate NUMERIC(18, 8) NOT NULL DEFAULT 1;
@@ -0,0 +1,42 @@
+-- Add migration script here
+CREATE TYPE discount_kind AS ENUM ('percentage', 'fixed_amount');
+
+CREATE TABLE promotions (
+    id SERIAL PRIMARY KEY,
+    code TEXT NOT NULL UNIQUE, -- uppercase, what customers type at checkout
+    description TEXT,
+    kind discount_kind NOT NULL,
+    percent_off INTEGER CHECK (percent_off BETWEEN 1 AND 100),
+    amount_off money_amount,
+    min_order_total money_amount,
+    -- at most one scope, none: the whole order
+    category_id INTEGER REFERENCES categories (id),
+    product_id INTEGER REFERENCES products (id),
+    max_uses INTEGER CHECK (max_uses > 0),
+    max_uses_per_customer INTEGER CHECK (max_uses_per_customer > 0),
+    starts_at TIMESTAMP,
+    ends_at TIMESTAMP,
+    is_active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    CHECK ((kind = 'percentage' AND percent_off IS NOT NULL AND amount_off IS NULL)
+        OR (kind = 'fixed_amount' AND amount_off IS NOT NULL AND percent_off IS NULL)),
+    CHECK (category_id IS NULL OR product_id IS NULL),
+    CHECK (code = upper(code))
+);
+
+-- one row per order a code was used on, counted for the usage limits
+CREATE TABLE promotion_redemptions (
+    id BIGSERIAL PRIMARY KEY,
+    promotion_id INTEGER NOT NULL REFERENCES promotions (id),
+    order_id UUID NOT NULL UNIQUE REFERENCES orders (id),
+    customer_id BIGINT NOT NULL REFERENCES customers (id),
+    discount money_amount NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX promotion_redemptions_promotion_id_idx ON promotion_redemptions (promotion_id, customer_id);
+
+ALTER TABLE orders
+    ADD COLUMN promotion_code TEXT,
+    ADD COLUMN discount money_amount;
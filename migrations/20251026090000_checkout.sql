@@ -0,0 +1,28 @@
+-- Add migration script here
+CREATE TABLE shipping_rates (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    description TEXT,
+    countries TEXT[], -- upper case, as typed in addresses; NULL ships everywhere
+    price money_amount NOT NULL,
+    free_over money_amount, -- goods total (after discounts) from which shipping is free
+    min_days INTEGER CHECK (min_days >= 0),
+    max_days INTEGER CHECK (max_days >= min_days),
+    sort_order INTEGER NOT NULL DEFAULT 0,
+    is_active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+INSERT INTO shipping_rates (name, description, price, free_over, min_days, max_days, sort_order) VALUES
+    ('Standard', 'Tracked parcel', ROW(495, 'USD'), ROW(10000, 'USD'), 3, 5, 1),
+    ('Express', 'Next day delivery on weekdays', ROW(1495, 'USD'), NULL, 1, 2, 2);
+
+-- a checkout is a draft order (status 'cart', not confirmed) until the customer confirms it
+ALTER TABLE orders
+    ADD COLUMN shipping_rate_id INTEGER REFERENCES shipping_rates (id),
+    ADD COLUMN shipping_method TEXT,
+    ADD COLUMN shipping_cost money_amount,
+    ADD COLUMN tax money_amount;
+
+CREATE INDEX orders_customer_id_status_idx ON orders (customer_id, status);
@@ -0,0 +1,32 @@
+-- Add migration script here
+-- a declined or failed payment, the customer can try again until the order is cancelled
+ALTER TYPE order_status ADD VALUE 'payment_failed' AFTER 'placed';
+
+CREATE TYPE payment_status AS ENUM ('pending', 'authorized', 'captured', 'failed', 'refunded');
+
+-- one row per attempt to pay an order
+CREATE TABLE payments (
+    id BIGSERIAL PRIMARY KEY,
+    order_id UUID NOT NULL REFERENCES orders (id),
+    provider TEXT NOT NULL,  -- `PaymentGateway::name`
+    reference TEXT,          -- the provider's id of the payment, NULL until it answered
+    amount money_amount NOT NULL,
+    status payment_status NOT NULL DEFAULT 'pending',
+    failure_reason TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (provider, reference)
+);
+
+CREATE INDEX payments_order_id_idx ON payments (order_id);
+
+-- webhook events already handled, providers deliver the same event more than once
+CREATE TABLE payment_events (
+    id BIGSERIAL PRIMARY KEY,
+    provider TEXT NOT NULL,
+    event_id TEXT NOT NULL,
+    payment_id BIGINT NOT NULL REFERENCES payments (id),
+    kind TEXT NOT NULL,
+    received_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (provider, event_id)
+);
@@ -0,0 +1,54 @@
+-- Add migration script here
+-- staff review return requests; granted with `grant-staff <email>`
+ALTER TABLE customers ADD COLUMN is_staff BOOLEAN NOT NULL DEFAULT false;
+
+-- returned goods that went back on the shelf, positive quantity
+ALTER TYPE stock_movement_reason ADD VALUE 'return';
+
+CREATE TYPE return_status AS ENUM ('requested', 'approved', 'rejected');
+
+CREATE TYPE return_reason AS ENUM (
+    'too_small',
+    'too_large',
+    'damaged',
+    'wrong_item',
+    'not_as_described',
+    'changed_mind'
+);
+
+CREATE TABLE return_requests (
+    id BIGSERIAL PRIMARY KEY,
+    order_id UUID NOT NULL REFERENCES orders (id),
+    customer_id BIGINT NOT NULL REFERENCES customers (id),
+    status return_status NOT NULL DEFAULT 'requested',
+    comment TEXT,
+    staff_note TEXT,
+    decided_by BIGINT REFERENCES customers (id),
+    decided_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX return_requests_order_id_idx ON return_requests (order_id);
+CREATE INDEX return_requests_status_idx ON return_requests (status, created_at);
+
+CREATE TABLE return_request_lines (
+    id BIGSERIAL PRIMARY KEY,
+    return_request_id BIGINT NOT NULL REFERENCES return_requests (id),
+    order_line_id INTEGER NOT NULL REFERENCES orders_product (id),
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    reason return_reason NOT NULL,
+    UNIQUE (return_request_id, order_line_id)
+);
+
+-- money sent back for a captured payment, a payment can be refunded in several parts
+CREATE TABLE refunds (
+    id BIGSERIAL PRIMARY KEY,
+    payment_id BIGINT NOT NULL REFERENCES payments (id),
+    return_request_id BIGINT REFERENCES return_requests (id), -- NULL for cancellations
+    reference TEXT NOT NULL, -- the provider's id of the refund
+    amount money_amount NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX refunds_payment_id_idx ON refunds (payment_id);
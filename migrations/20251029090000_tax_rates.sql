@@ -0,0 +1,28 @@
+-- Add migration script here
+-- VAT / sales tax in percent by country; the row without a category is the standard rate, rows
+-- with a category override it for the products in it (reduced rates)
+CREATE TABLE tax_rates (
+    id SERIAL PRIMARY KEY,
+    country TEXT NOT NULL CHECK (country ~ '^[A-Z]{2}$'), -- ISO 3166-1 alpha-2
+    category_id INTEGER REFERENCES categories (id) ON DELETE CASCADE,
+    name TEXT NOT NULL DEFAULT 'VAT',
+    rate NUMERIC(5, 2) NOT NULL CHECK (rate >= 0 AND rate < 100),
+    UNIQUE NULLS NOT DISTINCT (country, category_id)
+);
+
+INSERT INTO tax_rates (country, rate) VALUES
+    ('AT', 20), ('BE', 21), ('DE', 19), ('ES', 21), ('FR', 20), ('GB', 20),
+    ('IE', 23), ('IT', 22), ('NL', 21), ('PL', 23), ('PT', 23), ('SE', 25);
+
+-- children's shoes are zero-rated in the UK
+INSERT INTO tax_rates (country, category_id, rate)
+SELECT 'GB', id, 0 FROM categories WHERE name = 'kids';
+
+-- what was charged when the order was placed, rates may change later
+ALTER TABLE orders_product
+    ADD COLUMN tax_rate NUMERIC(5, 2),
+    ADD COLUMN tax money_amount;
+
+ALTER TABLE orders
+    ADD COLUMN tax_country TEXT,
+    ADD COLUMN shipping_tax money_amount;
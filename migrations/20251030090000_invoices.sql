@@ -0,0 +1,13 @@
+-- Add migration script here
+-- one invoice per paid order, numbered without gaps per calendar year: 2026-000001, 2026-000002, ...
+CREATE TABLE invoice_counters (
+    year INTEGER PRIMARY KEY,
+    last_number INTEGER NOT NULL
+);
+
+CREATE TABLE invoices (
+    id BIGSERIAL PRIMARY KEY,
+    number TEXT NOT NULL UNIQUE,
+    order_id UUID NOT NULL UNIQUE REFERENCES orders (id),
+    issued_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,28 @@
+-- Add migration script here
+-- logins compare the lowercased address, emails stored before registration lowercased them are
+-- brought in line. Where several accounts differ only by case, the one already stored in lower
+-- case or else the oldest gets the address; the others keep theirs as typed (they can't sign in
+-- until support merges them) and are listed here
+CREATE TEMPORARY TABLE email_owners ON COMMIT DROP AS
+SELECT id,
+       email,
+       row_number() OVER (
+           PARTITION BY lower(trim(email))
+           ORDER BY email = lower(trim(email)) DESC, id
+       ) AS rank
+FROM customers;
+
+DO $$
+DECLARE
+    duplicate RECORD;
+BEGIN
+    FOR duplicate IN SELECT id, email FROM email_owners WHERE rank > 1 ORDER BY id LOOP
+        RAISE NOTICE 'customer % keeps email % (another account has it in another case)',
+            duplicate.id, duplicate.email;
+    END LOOP;
+END $$;
+
+UPDATE customers c
+SET email = lower(trim(c.email)), updated_at = NOW()
+FROM email_owners o
+WHERE o.id = c.id AND o.rank = 1 AND c.email <> lower(trim(c.email));
@@ -0,0 +1,13 @@
+-- Add migration script here
+-- refunds are written as pending together with the cancellation or return decision and sent to
+-- the provider once that transaction committed; the ones already made were sent before
+CREATE TYPE refund_status AS ENUM ('pending', 'succeeded');
+
+ALTER TABLE refunds
+    ADD COLUMN status refund_status NOT NULL DEFAULT 'succeeded',
+    ADD COLUMN failure TEXT, -- the provider's answer to the last failed attempt
+    ADD COLUMN updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    ALTER COLUMN reference DROP NOT NULL; -- known once the provider accepted it
+ALTER TABLE refunds ALTER COLUMN status SET DEFAULT 'pending';
+
+CREATE INDEX refunds_pending_idx ON refunds (id) WHERE status = 'pending';
@@ -0,0 +1,23 @@
+-- Add migration script here
+-- countries are ISO 3166-1 alpha-2 codes from now on, like tax_rates.country; codes typed in
+-- lower case are fixed here, names ("Germany") are left for the customer to pick again
+UPDATE addresses SET country = upper(trim(country)) WHERE trim(country) ~* '^[a-z]{2}$';
+UPDATE customers SET country = upper(trim(country)) WHERE trim(country) ~* '^[a-z]{2}$';
+
+-- NOT VALID: checked for new and changed rows, the old free text stays readable
+ALTER TABLE addresses
+    ADD CONSTRAINT addresses_country_code_check CHECK (country ~ '^[A-Z]{2}$') NOT VALID;
+ALTER TABLE customers
+    ADD CONSTRAINT customers_country_code_check CHECK (country ~ '^[A-Z]{2}$') NOT VALID;
+
+-- checkout refuses shipping countries without a tax_rates row, a country we deliver to without
+-- charging tax needs a standard row with rate 0
+DO $$
+DECLARE
+    unknown INTEGER;
+BEGIN
+    SELECT count(*) INTO unknown FROM addresses WHERE country !~ '^[A-Z]{2}$';
+    IF unknown > 0 THEN
+        RAISE NOTICE '% addresses have a country name instead of a code and can''t be shipped to until edited', unknown;
+    END IF;
+END $$;
@@ -0,0 +1,18 @@
+-- Add migration script here
+-- lines ordered before variants existed point at the single variant their product got then, the
+-- oldest one; orders_product_order_variant_key allows one line per variant and order, so when a
+-- product was bought at two prices only its first line is mapped and the others stay NULL
+UPDATE orders_product op
+SET variant_id = m.variant_id
+FROM (SELECT DISTINCT ON (op.order_id, v.variant_id) op.id, v.variant_id
+      FROM orders_product op
+           JOIN (SELECT product_id, min(id) AS variant_id
+                 FROM product_variants
+                 GROUP BY product_id) v ON v.product_id = op.product_id
+      WHERE op.variant_id IS NULL
+        AND NOT EXISTS (SELECT 1
+                        FROM orders_product k
+                        WHERE k.order_id = op.order_id
+                          AND k.variant_id = v.variant_id)
+      ORDER BY op.order_id, v.variant_id, op.id) m
+WHERE op.id = m.id;
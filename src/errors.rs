@@ -0,0 +1,227 @@
+use crate::models::money::CurrencyMismatch;
+use crate::repository::customer_repository::CustomerError;
+use crate::repository::order_repository::OrderError;
+use crate::services::invoices::InvoiceError;
+use crate::services::payments::PaymentError;
+use crate::services::promotions::PromotionError;
+use crate::services::refunds::RefundError;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+
+#[derive(Debug)]
+pub enum AppError {
+    NotFound,
+    Forbidden,
+    Validation(String),
+    Database(sqlx::Error),
+    Template(minijinja::Error),
+    Customer(CustomerError),
+    Order(OrderError),
+    Promotion(PromotionError),
+    Payment(PaymentError),
+    Refund(RefundError),
+    Invoice(InvoiceError),
+    Currency(CurrencyMismatch),
+}
+
+// attached to error responses, `render_error_page` middleware turns it into 404.html / 500.html
+#[derive(Debug, Clone)]
+pub struct ErrorPage {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+impl AppError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::NotFound => StatusCode::NOT_FOUND,
+            AppError::Forbidden => StatusCode::FORBIDDEN,
+            AppError::Validation(_) => StatusCode::BAD_REQUEST,
+            AppError::Order(OrderError::NotFound) => StatusCode::NOT_FOUND,
+            AppError::Customer(CustomerError::NotFound) => StatusCode::NOT_FOUND,
+            AppError::Customer(CustomerError::ConnectionFailure) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Order(OrderError::IllegalTransition { .. }) => StatusCode::BAD_REQUEST,
+            AppError::Order(OrderError::OutOfStock { .. }) => StatusCode::CONFLICT,
+            AppError::Promotion(PromotionError::Database(_) | PromotionError::Currency(_)) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            AppError::Promotion(_) => StatusCode::CONFLICT,
+            AppError::Payment(PaymentError::OrderNotFound | PaymentError::UnknownPayment) => {
+                StatusCode::NOT_FOUND
+            }
+            AppError::Payment(
+                PaymentError::NotPayable
+                | PaymentError::InProgress
+                | PaymentError::RefundTooLarge { .. },
+            ) => StatusCode::CONFLICT,
+            AppError::Payment(PaymentError::Declined(_)) => StatusCode::PAYMENT_REQUIRED,
+            AppError::Payment(PaymentError::InvalidSignature | PaymentError::InvalidEvent(_)) => {
+                StatusCode::BAD_REQUEST
+            }
+            AppError::Payment(PaymentError::Provider(_)) => StatusCode::BAD_GATEWAY,
+            AppError::Payment(PaymentError::Database(_) | PaymentError::Currency(_)) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            AppError::Refund(RefundError::OrderNotFound) => StatusCode::NOT_FOUND,
+            AppError::Refund(RefundError::InvalidReturn(_)) => StatusCode::BAD_REQUEST,
+            AppError::Refund(
+                RefundError::NotCancellable(_)
+                | RefundError::NotReturnable(_)
+                | RefundError::AlreadyDecided(_),
+            ) => StatusCode::CONFLICT,
+            AppError::Refund(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Invoice(InvoiceError::OrderNotFound) => StatusCode::NOT_FOUND,
+            AppError::Invoice(InvoiceError::NotInvoiceable(_)) => StatusCode::CONFLICT,
+            AppError::Invoice(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Database(_)
+            | AppError::Template(_)
+            | AppError::Customer(_)
+            | AppError::Order(_)
+            | AppError::Currency(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    // text shown to the customer, internal details stay in the logs
+    pub fn public_message(&self) -> String {
+        match self {
+            AppError::NotFound
+            | AppError::Order(OrderError::NotFound)
+            | AppError::Payment(PaymentError::OrderNotFound)
+            | AppError::Refund(RefundError::OrderNotFound)
+            | AppError::Invoice(InvoiceError::OrderNotFound)
+            | AppError::Customer(CustomerError::NotFound) => {
+                "The page you are looking for does not exist.".to_string()
+            }
+            AppError::Forbidden => {
+                "Your form has expired or was sent from another site. Reload the page and try again."
+                    .to_string()
+            }
+            AppError::Validation(message) => message.clone(),
+            AppError::Order(
+                e @ (OrderError::IllegalTransition { .. } | OrderError::OutOfStock { .. }),
+            ) => e.to_string(),
+            AppError::Promotion(e)
+                if !matches!(e, PromotionError::Database(_) | PromotionError::Currency(_)) =>
+            {
+                e.to_string()
+            }
+            // the webhook errors go back to the provider
+            AppError::Payment(
+                e @ (PaymentError::NotPayable
+                | PaymentError::InProgress
+                | PaymentError::RefundTooLarge { .. }
+                | PaymentError::Declined(_)
+                | PaymentError::InvalidSignature
+                | PaymentError::InvalidEvent(_)
+                | PaymentError::UnknownPayment),
+            ) => e.to_string(),
+            AppError::Refund(
+                e @ (RefundError::NotCancellable(_)
+                | RefundError::NotReturnable(_)
+                | RefundError::InvalidReturn(_)
+                | RefundError::AlreadyDecided(_)),
+            ) => e.to_string(),
+            AppError::Invoice(e @ InvoiceError::NotInvoiceable(_)) => e.to_string(),
+            _ => "Something went wrong on our side. Send this problem to the support or try again later."
+                .to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AppError::NotFound => write!(f, "Not found"),
+            AppError::Forbidden => write!(f, "Forbidden"),
+            AppError::Validation(message) => write!(f, "Validation error: {}", message),
+            AppError::Database(e) => write!(f, "Database error: {}", e),
+            AppError::Template(e) => write!(f, "Template error: {}", e),
+            AppError::Customer(e) => write!(f, "Customer error: {}", e),
+            AppError::Order(e) => write!(f, "Order error: {}", e),
+            AppError::Promotion(e) => write!(f, "Promotion error: {}", e),
+            AppError::Payment(e) => write!(f, "Payment error: {}", e),
+            AppError::Refund(e) => write!(f, "Refund error: {}", e),
+            AppError::Invoice(e) => write!(f, "Invoice error: {}", e),
+            AppError::Currency(e) => write!(f, "Currency error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::RowNotFound => AppError::NotFound,
+            e => AppError::Database(e),
+        }
+    }
+}
+
+impl From<minijinja::Error> for AppError {
+    fn from(e: minijinja::Error) -> Self {
+        AppError::Template(e)
+    }
+}
+
+impl From<CustomerError> for AppError {
+    fn from(e: CustomerError) -> Self {
+        AppError::Customer(e)
+    }
+}
+
+impl From<OrderError> for AppError {
+    fn from(e: OrderError) -> Self {
+        AppError::Order(e)
+    }
+}
+
+impl From<PromotionError> for AppError {
+    fn from(e: PromotionError) -> Self {
+        AppError::Promotion(e)
+    }
+}
+
+impl From<PaymentError> for AppError {
+    fn from(e: PaymentError) -> Self {
+        AppError::Payment(e)
+    }
+}
+
+impl From<RefundError> for AppError {
+    fn from(e: RefundError) -> Self {
+        match e {
+            RefundError::Order(e) => AppError::Order(e),
+            RefundError::Payment(e) => AppError::Payment(e),
+            e => AppError::Refund(e),
+        }
+    }
+}
+
+impl From<InvoiceError> for AppError {
+    fn from(e: InvoiceError) -> Self {
+        AppError::Invoice(e)
+    }
+}
+
+impl From<CurrencyMismatch> for AppError {
+    fn from(e: CurrencyMismatch) -> Self {
+        AppError::Currency(e)
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status_code();
+        if status.is_server_error() {
+            tracing::error!("{}", self);
+        } else {
+            tracing::info!("{}", self);
+        }
+
+        let message = self.public_message();
+        let mut resp = (status, message.clone()).into_response();
+        resp.extensions_mut().insert(ErrorPage { status, message });
+        resp
+    }
+}
@@ -1,5 +1,6 @@
 mod config;
 mod db;
+mod errors;
 mod middlewares;
 mod models;
 mod repository;
@@ -8,9 +9,24 @@ mod services;
 mod views;
 
 use crate::config::config;
+use crate::middlewares::{csrf_token, currency_choice};
+use crate::models::country::COUNTRIES;
+use crate::models::inventory::{StockAdjustment, StockChange};
+use crate::models::money::Money;
+use crate::models::state::AppState;
+use crate::models::tax::TaxDisplay;
+use crate::repository::customer_repository::CustomerRepository;
+use crate::repository::inventory_repository::InventoryRepository;
+use crate::repository::payment_repository::PaymentRepository;
 use crate::router::create_router;
+use crate::services::exchange_rates::ExchangeRateImport;
+use crate::services::mailer::FileMailer;
+use crate::services::payments::{MOCK_SIGNATURE_HEADER, MockGateway, Payments};
 use minijinja::Environment;
+use minijinja::value::{Value, ViaDeserialize};
+use rust_decimal::Decimal;
 use simple_cookie::SigningKey;
+use std::net::{IpAddr, SocketAddr};
 
 // #[tokio::main(flavor = "multi_thread", worker_threads = 10)]
 #[tokio::main]
@@ -19,17 +35,200 @@ async fn main() {
 
     let (port, addr, pool, static_files) = config().await;
 
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("import-exchange-rates") {
+        let Some(path) = args.get(2) else {
+            eprintln!("usage: {} import-exchange-rates <file.csv>", args[0]);
+            std::process::exit(2);
+        };
+        match ExchangeRateImport::import_file(&pool, path).await {
+            Ok(count) => println!("Imported {} exchange rates", count),
+            Err(e) => {
+                eprintln!("Exchange rate import failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("grant-staff") {
+        let Some(email) = args.get(2) else {
+            eprintln!("usage: {} grant-staff <email>", args[0]);
+            std::process::exit(2);
+        };
+        match CustomerRepository::grant_staff(&pool, &email.trim().to_lowercase()).await {
+            Ok(true) => println!("{} can review return requests now", email),
+            Ok(false) => {
+                eprintln!("No customer with email {}", email);
+                std::process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("Granting staff access failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if args.get(1).map(String::as_str) == Some("adjust-stock") {
+        let (Some(sku), Some(change)) =
+            (args.get(2), args.get(3).and_then(|v| StockChange::parse(v)))
+        else {
+            eprintln!(
+                "usage: {} adjust-stock <sku> <+received|-removed|=counted> [note]",
+                args[0]
+            );
+            std::process::exit(2);
+        };
+        let note = args
+            .get(4)
+            .map(String::as_str)
+            .unwrap_or("stock adjustment");
+        match InventoryRepository::adjust_stock(&pool, sku, change, note).await {
+            Ok(StockAdjustment::Adjusted { stock }) => println!("{} has {} in stock", sku, stock),
+            Ok(StockAdjustment::UnknownSku) => {
+                eprintln!("No variant with sku {}", sku);
+                std::process::exit(1);
+            }
+            Ok(StockAdjustment::Insufficient { stock }) => {
+                eprintln!("{} only has {} in stock", sku, stock);
+                std::process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("Stock adjustment failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
     let mut env = Environment::new();
     minijinja_embed::load_templates!(&mut env);
+    env.add_function("csrf_token", csrf_token);
+    env.add_function("currency_choice", currency_choice);
+    // (code, name) pairs for includes/country-select.html
+    env.add_global("countries", Value::from_serialize(COUNTRIES));
+    // {{ product.price | money }}, a template can ask for another locale: money("de-DE")
+    let locale = std::env::var("LOCALE").unwrap_or_else(|_| "en-US".to_string());
+    let filter_locale = locale.clone();
+    env.add_filter(
+        "money",
+        move |money: ViaDeserialize<Money>, other_locale: Option<String>| {
+            money.format(other_locale.as_deref().unwrap_or(&filter_locale))
+        },
+    );
+    // {{ line.tax_rate | percent }}: 19%, 7.5%
+    env.add_filter("percent", |rate: ViaDeserialize<Decimal>| {
+        format!("{}%", rate.normalize())
+    });
 
     let signing_key = std::env::var("SIGNING_KEY")
         .map(|s| s.into_bytes())
         .unwrap_or_else(|_| vec![0; 32]);
 
+    let base_url = std::env::var("BASE_URL").unwrap_or_else(|_| format!("http://{addr}:{port}"));
+    let mail_dir = std::env::var("MAIL_DIR").unwrap_or_else(|_| "./mail".to_string());
+    let require_verified_email = std::env::var("REQUIRE_VERIFIED_EMAIL")
+        .map(|value| value == "true" || value == "1")
+        .unwrap_or(false);
+    let tax_display = std::env::var("PRICE_DISPLAY")
+        .ok()
+        .and_then(|value| TaxDisplay::parse(&value))
+        .unwrap_or(TaxDisplay::Exclusive);
+    let tax_country = std::env::var("TAX_COUNTRY")
+        .ok()
+        .map(|value| value.trim().to_ascii_uppercase())
+        .filter(|value| !value.is_empty());
+    // "127.0.0.1,10.0.0.2": the reverse proxies in front of the app, empty when it faces clients
+    let trusted_proxies = std::env::var("TRUSTED_PROXIES")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(|value| {
+            value.parse::<IpAddr>().unwrap_or_else(|_| {
+                eprintln!("TRUSTED_PROXIES: {} is not an IP address", value);
+                std::process::exit(2);
+            })
+        })
+        .collect();
+    // "Sneakers Shop|Main Street 1|10115 Berlin|VAT ID DE123456789", one line per part
+    let invoice_issuer = std::env::var("INVOICE_ISSUER")
+        .unwrap_or_else(|_| "Sneakers Shop".to_string())
+        .split('|')
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect();
+    let payments = MockGateway::new(
+        std::env::var("PAYMENT_WEBHOOK_SECRET").unwrap_or_else(|_| "dev-webhook-secret".to_string()),
+    );
+
+    // prints a signed webhook call of the mock provider, to try out asynchronous payment results
+    if args.get(1).map(String::as_str) == Some("mock-payment-event") {
+        let (Some(reference), Some(kind)) = (args.get(2), args.get(3)) else {
+            eprintln!(
+                "usage: {} mock-payment-event <reference> <payment.succeeded|payment.failed> [reason]",
+                args[0]
+            );
+            std::process::exit(2);
+        };
+        let mut body = form_urlencoded::Serializer::new(String::new());
+        body.append_pair("id", &format!("evt_{}", hex::encode(rand::random::<[u8; 8]>())))
+            .append_pair("type", kind)
+            .append_pair("reference", reference);
+        if let Some(reason) = args.get(4) {
+            body.append_pair("reason", reason);
+        }
+        let body = body.finish();
+        println!(
+            "curl -X POST {}/payments/webhook -H '{}: {}' --data '{}'",
+            base_url,
+            MOCK_SIGNATURE_HEADER,
+            payments.sign(body.as_bytes()),
+            body
+        );
+        return;
+    }
+
+    // refunds the provider refused or never got (the process stopped right after the commit)
+    if args.get(1).map(String::as_str) == Some("send-pending-refunds") {
+        let result = match PaymentRepository::list_pending_refunds(&pool).await {
+            Ok(refunds) => Payments::send_refunds(&pool, &payments, &refunds)
+                .await
+                .map(|sent| (sent, refunds.len())),
+            Err(e) => Err(e.into()),
+        };
+        match result {
+            Ok((sent, pending)) => {
+                println!("Sent {} of {} pending refunds", sent, pending);
+                if sent < pending {
+                    std::process::exit(1);
+                }
+            }
+            Err(e) => {
+                eprintln!("Sending pending refunds failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let state = AppState {
+        tpl_env: env,
+        mailer: Box::new(FileMailer::new(mail_dir)),
+        payments: Box::new(payments),
+        base_url,
+        require_verified_email,
+        tax_display,
+        tax_country,
+        trusted_proxies,
+        locale,
+        invoice_issuer,
+    };
+
     let app_router = create_router(
         pool,
         static_files,
-        env,
+        state,
         SigningKey::try_from(signing_key).unwrap(),
     );
 
@@ -37,7 +236,10 @@ async fn main() {
         .await
         .unwrap();
     tracing::info!("Starting server on {}:{}", addr, port);
-    axum::serve(tcp_listener, app_router.into_make_service())
-        .await
-        .unwrap();
+    axum::serve(
+        tcp_listener,
+        app_router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
@@ -1,13 +1,84 @@
-use crate::models::customer::{Customer, ProfileCustomer};
-use crate::repository::customer_repository::CustomerRepository;
+use crate::errors::{AppError, ErrorPage};
+use crate::models::customer::ProfileCustomer;
+use crate::models::money::{BASE_CURRENCY, Currency, ExchangeRate};
+use crate::models::session::CurrentSession;
+use crate::models::state::AppState;
+use crate::repository::customer_repository::{CustomerError, CustomerRepository};
+use crate::repository::exchange_rate_repository::ExchangeRateRepository;
+use crate::repository::session_repository::SessionRepository;
 use crate::services::auth::AuthService;
 use axum::Extension;
-use axum::extract::Request;
+use axum::body::{Body, to_bytes};
+use axum::extract::{ConnectInfo, FromRequestParts, Request, State};
+use axum::http::request::Parts;
 use axum::http::{HeaderMap, StatusCode};
 use axum::middleware::Next;
-use axum::response::Response;
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use minijinja::context;
+use serde::Serialize;
 use simple_cookie::SigningKey;
 use sqlx::PgPool;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+// who is on the other side of the request, stored with sessions
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl FromRequestParts<Arc<AppState>> for ClientInfo {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        let user_agent = parts
+            .headers
+            .get("user-agent")
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
+
+        Ok(ClientInfo {
+            ip_address: peer
+                .map(|peer| client_ip(peer, &parts.headers, &state.trusted_proxies).to_string()),
+            user_agent,
+        })
+    }
+}
+
+// behind nginx on production the peer address is the proxy; X-Forwarded-For is only believed
+// when a trusted proxy (TRUSTED_PROXIES) sent it, and read from the right, since every hop
+// appends the address it saw and anything left of our own proxies can be made up by the client
+fn client_ip(peer: IpAddr, headers: &HeaderMap, trusted_proxies: &[IpAddr]) -> IpAddr {
+    let mut client = peer;
+    if !trusted_proxies.contains(&peer) {
+        return client;
+    }
+    let forwarded: Vec<&str> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect();
+    for hop in forwarded.into_iter().rev() {
+        let Ok(ip) = hop.trim().parse::<IpAddr>() else {
+            break;
+        };
+        client = ip;
+        if !trusted_proxies.contains(&ip) {
+            break;
+        }
+    }
+    client
+}
 
 pub async fn optional_customer(
     headers: HeaderMap,
@@ -16,15 +87,33 @@ pub async fn optional_customer(
     mut req: Request,
     next: Next,
 ) -> Result<Response, (StatusCode, String)> {
-    match extract_user_id(&headers, signing_key) {
-        Ok(customer) => match CustomerRepository::verify_customer(&pool, customer.id).await {
-            Ok(customer) => {
-                req.extensions_mut().insert(customer);
-                Ok(next.run(req).await)
-            }
-            Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, format!("{:?}", e))),
+    let session = match extract_session_token(&headers, signing_key) {
+        Ok(token) => match SessionRepository::touch_active_session(&pool, &token).await {
+            Ok(session) => session,
+            Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("{:?}", e))),
         },
-        Err((_, _)) => {
+        Err((_, _)) => None,
+    };
+
+    let verified = match &session {
+        Some(session) => {
+            match CustomerRepository::verify_customer(&pool, session.customer_id).await {
+                Ok(customer) => Some(customer),
+                // session of a deleted customer, browse as a guest
+                Err(CustomerError::NotFound) => None,
+                Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("{:?}", e))),
+            }
+        }
+        None => None,
+    };
+
+    match (verified, session) {
+        (Some(customer), Some(session)) => {
+            req.extensions_mut().insert(customer);
+            req.extensions_mut().insert(session);
+            Ok(next.run(req).await)
+        }
+        _ => {
             // No Errors because we sent empty non-authed ProfileCustomer
             req.extensions_mut().insert(ProfileCustomer {
                 is_authenticated: false,
@@ -36,61 +125,228 @@ pub async fn optional_customer(
                 phone: "".to_string(),
                 city: "".to_string(),
                 country: "".to_string(),
+                is_email_verified: false,
+                is_staff: false,
             });
             Ok(next.run(req).await)
         }
     }
 }
 
-pub async fn redirect_if_authed(
-    headers: HeaderMap,
+const MAX_FORM_BYTES: usize = 64 * 1024;
+
+tokio::task_local! {
+    // token of the request being handled, read by the `csrf_token()` template function
+    static CSRF_TOKEN: String;
+}
+
+// registered as a minijinja global, empty outside of `csrf_protect`
+pub fn csrf_token() -> String {
+    CSRF_TOKEN.try_with(Clone::clone).unwrap_or_default()
+}
+
+// every form posts the token back as `csrf_token`; signed in it is derived from the session
+// (see `AuthService::session_csrf_token`), guests double submit one kept in a signed cookie
+pub async fn csrf_protect(
     Extension(signing_key): Extension<SigningKey>,
     req: Request,
     next: Next,
 ) -> Response {
-    let result = extract_user_id(&headers, signing_key);
-    match result {
-        Ok(_) => {
-            let mut res = next.run(req).await;
-            res.headers_mut().insert("Location", "/".parse().unwrap());
-            *res.status_mut() = StatusCode::PERMANENT_REDIRECT;
-            res
+    let session_token = req
+        .extensions()
+        .get::<CurrentSession>()
+        .map(|session| AuthService::session_csrf_token(session.id, &signing_key));
+    let cookie_token = req
+        .headers()
+        .get("cookie")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| AuthService::parse_csrf_cookie_value(value, signing_key).ok());
+    let (token, new_cookie) = match (session_token, cookie_token) {
+        (Some(token), _) => (token, None),
+        (None, Some(token)) => (token, None),
+        (None, None) => {
+            let token = AuthService::generate_token();
+            let cookie = AuthService::create_csrf_cookie_header(&token, &signing_key);
+            (token, Some(cookie))
+        }
+    };
+
+    let req = if req.method().is_safe() {
+        req
+    } else {
+        let (parts, body) = req.into_parts();
+        let Ok(bytes) = to_bytes(body, MAX_FORM_BYTES).await else {
+            return AppError::Validation("The form is too large".to_string()).into_response();
+        };
+        let submitted = parts
+            .headers
+            .get("x-csrf-token")
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string())
+            .or_else(|| {
+                form_urlencoded::parse(&bytes)
+                    .find(|(name, _)| name == "csrf_token")
+                    .map(|(_, value)| value.into_owned())
+            });
+        let is_valid = new_cookie.is_none()
+            && submitted.is_some_and(|submitted| AuthService::tokens_match(&submitted, &token));
+        if !is_valid {
+            return AppError::Forbidden.into_response();
         }
-        Err(_) => next.run(req).await,
+        Request::from_parts(parts, Body::from(bytes))
+    };
+
+    let mut resp = CSRF_TOKEN.scope(token, next.run(req)).await;
+    if let Some(cookie) = new_cookie {
+        resp.headers_mut()
+            .append("Set-Cookie", cookie.parse().unwrap());
     }
+    resp
 }
 
-pub async fn extract_user_id_from_cookie(
+// what the currency selector in the header shows
+#[derive(Debug, Clone, Serialize)]
+pub struct CurrencyChoice {
+    pub selected: Currency,
+    pub available: Vec<Currency>,
+    pub return_to: String, // path of the current page, the selector posts it back as `next`
+}
+
+tokio::task_local! {
+    static CURRENCY_CHOICE: CurrencyChoice;
+}
+
+// registered as a minijinja global, the base currency alone outside of `select_currency`
+pub fn currency_choice() -> minijinja::Value {
+    let choice = CURRENCY_CHOICE
+        .try_with(Clone::clone)
+        .unwrap_or_else(|_| CurrencyChoice {
+            selected: BASE_CURRENCY,
+            available: vec![BASE_CURRENCY],
+            return_to: "/".to_string(),
+        });
+    minijinja::Value::from_serialize(choice)
+}
+
+// the rate of the currency picked in the header goes into the request as `ExchangeRate`,
+// prices stay in the base currency when it is not (or no longer) in exchange_rates
+pub async fn select_currency(
     headers: HeaderMap,
     Extension(signing_key): Extension<SigningKey>,
     Extension(pool): Extension<PgPool>,
     mut req: Request,
     next: Next,
-) -> Result<Response, (StatusCode, String)> {
-    match extract_user_id(&headers, signing_key) {
-        Ok(customer) => match CustomerRepository::verify_customer(&pool, customer.id).await {
-            Ok(customer) => {
-                req.extensions_mut().insert(customer);
-                Ok(next.run(req).await)
-            }
-            Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, format!("{:?}", e))),
-        },
-        Err((status, _)) => Err((
-            status,
+) -> Response {
+    let requested = headers
+        .get("cookie")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| AuthService::parse_currency_cookie_value(value, signing_key).ok())
+        .and_then(|code| Currency::from_code(&code));
+    let rates = match ExchangeRateRepository::list_rates(&pool).await {
+        Ok(rates) => rates,
+        Err(e) => {
+            tracing::error!("Error loading exchange rates: {:?}", e);
+            Vec::new()
+        }
+    };
+
+    let rate = rates
+        .iter()
+        .find(|rate| Some(rate.currency) == requested)
+        .copied()
+        .unwrap_or_else(ExchangeRate::base);
+    let choice = CurrencyChoice {
+        selected: rate.currency,
+        available: std::iter::once(BASE_CURRENCY)
+            .chain(rates.iter().map(|rate| rate.currency))
+            .collect(),
+        return_to: req
+            .uri()
+            .path_and_query()
+            .map(|path| path.as_str().to_string())
+            .unwrap_or_else(|| "/".to_string()),
+    };
+    req.extensions_mut().insert(rate);
+    CURRENCY_CHOICE.scope(choice, next.run(req)).await
+}
+
+// both middlewares below run inside `optional_customer`, so the session is already validated
+pub async fn redirect_if_authed(req: Request, next: Next) -> Response {
+    if req.extensions().get::<CurrentSession>().is_some() {
+        return Redirect::to("/").into_response();
+    }
+    next.run(req).await
+}
+
+pub async fn require_customer(req: Request, next: Next) -> Result<Response, (StatusCode, String)> {
+    if req.extensions().get::<CurrentSession>().is_none() {
+        return Err((
+            StatusCode::UNAUTHORIZED,
             String::from("Failed to extract user id from cookie"),
-        )),
+        ));
+    }
+    Ok(next.run(req).await)
+}
+
+// the staff pages look like missing pages to everyone else
+pub async fn require_staff(req: Request, next: Next) -> Result<Response, AppError> {
+    let is_staff = req
+        .extensions()
+        .get::<ProfileCustomer>()
+        .is_some_and(|customer| customer.is_authenticated && customer.is_staff);
+    if !is_staff {
+        return Err(AppError::NotFound);
+    }
+    Ok(next.run(req).await)
+}
+
+pub async fn render_error_page(
+    State(state): State<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let customer_user = req.extensions().get::<ProfileCustomer>().cloned();
+    let res = next.run(req).await;
+
+    let Some(error_page) = res.extensions().get::<ErrorPage>().cloned() else {
+        return res;
+    };
+
+    let template_name = match error_page.status {
+        StatusCode::NOT_FOUND => "404.html",
+        StatusCode::FORBIDDEN => "403.html",
+        _ => "500.html",
+    };
+    let rendered = state
+        .tpl_env
+        .get_template(template_name)
+        .and_then(|template| {
+            template.render(context!(
+                customer_user => customer_user,
+                status => error_page.status.as_u16(),
+                message => error_page.message,
+            ))
+        });
+
+    match rendered {
+        Ok(r) => (error_page.status, Html(r)).into_response(),
+        Err(e) => {
+            // keep the plain-text response if even the error page can't be rendered
+            tracing::error!("Error rendering {}: {:?}", template_name, e);
+            res
+        }
     }
 }
 
-fn extract_user_id(
+fn extract_session_token(
     header_map: &HeaderMap,
     signing_key: SigningKey,
-) -> Result<Customer, (StatusCode, String)> {
+) -> Result<String, (StatusCode, String)> {
     if let Some(cookie_headers) = header_map.get("cookie") {
         let ch = cookie_headers.to_str();
         match ch {
             Ok(ch) => match AuthService::parse_cookie_value(ch, signing_key) {
-                Ok(id) => Ok(Customer { id }),
+                Ok(token) => Ok(token),
                 Err(e) => Err((
                     StatusCode::UNAUTHORIZED,
                     format!("Failed to parse cookie header: {}", e),
@@ -0,0 +1,83 @@
+use crate::models::customer::{AcceptEnum, default_accept_enum};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Address {
+    pub(crate) id: i64,
+    pub(crate) label: String, // "Home", "Office", ...
+    pub(crate) full_name: String,
+    pub(crate) line1: String,
+    pub(crate) line2: Option<String>,
+    pub(crate) city: String,
+    pub(crate) postal_code: String,
+    pub(crate) country: String,
+    pub(crate) phone: Option<String>,
+    pub(crate) is_default_shipping: bool,
+    pub(crate) is_default_billing: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct AddressForm {
+    // raw form, everything is a string so bad input can be shown back to the user
+    #[serde(default)]
+    pub(crate) label: String,
+    #[serde(default)]
+    pub(crate) full_name: String,
+    #[serde(default)]
+    pub(crate) line1: String,
+    #[serde(default)]
+    pub(crate) line2: String,
+    #[serde(default)]
+    pub(crate) city: String,
+    #[serde(default)]
+    pub(crate) postal_code: String,
+    #[serde(default)]
+    pub(crate) country: String,
+    #[serde(default)]
+    pub(crate) phone: String,
+    #[serde(default = "default_accept_enum")]
+    pub(crate) is_default_shipping: AcceptEnum,
+    #[serde(default = "default_accept_enum")]
+    pub(crate) is_default_billing: AcceptEnum,
+}
+
+#[derive(Debug)]
+pub struct NewAddress {
+    // validated address form, see `AddressValidator::validate_address`
+    pub(crate) label: String,
+    pub(crate) full_name: String,
+    pub(crate) line1: String,
+    pub(crate) line2: Option<String>,
+    pub(crate) city: String,
+    pub(crate) postal_code: String,
+    pub(crate) country: String,
+    pub(crate) phone: Option<String>,
+    pub(crate) is_default_shipping: bool,
+    pub(crate) is_default_billing: bool,
+}
+
+// what gets copied onto an order (`orders.shipping_address` / `orders.billing_address`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddressSnapshot {
+    pub(crate) full_name: String,
+    pub(crate) line1: String,
+    pub(crate) line2: Option<String>,
+    pub(crate) city: String,
+    pub(crate) postal_code: String,
+    pub(crate) country: String,
+    pub(crate) phone: Option<String>,
+}
+
+impl From<&Address> for AddressSnapshot {
+    fn from(address: &Address) -> Self {
+        AddressSnapshot {
+            full_name: address.full_name.clone(),
+            line1: address.line1.clone(),
+            line2: address.line2.clone(),
+            city: address.city.clone(),
+            postal_code: address.postal_code.clone(),
+            country: address.country.clone(),
+            phone: address.phone.clone(),
+        }
+    }
+}
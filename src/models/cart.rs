@@ -0,0 +1,65 @@
+use crate::models::money::{Currency, CurrencyMismatch, ExchangeRate, Money};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Cart {
+    pub(crate) id: String, // uuid
+    pub(crate) customer_id: i64,
+    pub(crate) currency: Currency,
+    pub(crate) items: Vec<CartItem>,
+}
+
+impl Cart {
+    pub fn total(&self) -> Result<Money, CurrencyMismatch> {
+        Money::sum(self.currency, self.items.iter().map(|item| item.line_total))
+    }
+
+    // unit prices are converted, line totals follow from them so they add up on the order
+    pub fn convert_prices(&mut self, rate: &ExchangeRate) {
+        self.currency = rate.currency;
+        for item in &mut self.items {
+            item.product_price = rate.convert(item.product_price);
+            item.line_total = item.product_price.times(item.quantity);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CartItem {
+    pub(crate) product_id: i32,
+    pub(crate) product_name: String,
+    pub(crate) product_code: String,
+    pub(crate) variant_id: i32,
+    pub(crate) sku: String,
+    pub(crate) size: String,
+    pub(crate) colour: String,
+    pub(crate) product_price: Money, // variant price
+    pub(crate) images: Json<Vec<String>>,
+    pub(crate) quantity: i32,
+    pub(crate) line_total: Money,
+    pub(crate) stock: i32, // left on the shelf, checkout fails above it
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateCartItemForm {
+    pub(crate) variant_id: i32,
+    pub(crate) quantity: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RemoveCartItemForm {
+    pub(crate) variant_id: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CheckoutForm {
+    #[serde(default)]
+    pub(crate) shipping_address_id: Option<i64>,
+    #[serde(default)]
+    pub(crate) billing_address_id: Option<i64>, // same as shipping when not given
+}
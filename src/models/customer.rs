@@ -7,29 +7,47 @@ pub enum AcceptEnum {
     On,
     Off,
 }
-fn default_accept_enum() -> AcceptEnum {
+pub(crate) fn default_accept_enum() -> AcceptEnum {
     AcceptEnum::Off
 }
 
 #[derive(Deserialize, Serialize, Debug)]
-pub struct NewCustomer {
+pub struct NewCustomerForm {
+    // raw registration form, everything is a string so bad input can be shown back to the user
+    #[serde(default)]
     pub(crate) email: String,
+    #[serde(default)]
     pub(crate) first_name: String,
+    #[serde(default)]
     pub(crate) last_name: String,
-    pub(crate) date_birth: NaiveDate,
+    #[serde(default)]
+    pub(crate) date_birth: String,
+    #[serde(default)]
     pub(crate) phone: String,
+    #[serde(default)]
     pub(crate) city: String,
+    #[serde(default)]
     pub(crate) country: String,
+    #[serde(default, skip_serializing)]
     pub(crate) password: String,
+    #[serde(default, skip_serializing)]
     pub(crate) confirm_password: String,
 
     #[serde(default = "default_accept_enum")]
     pub(crate) accept_all: AcceptEnum,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
-pub struct Customer {
-    pub(crate) id: i64,
+#[derive(Debug)]
+pub struct NewCustomer {
+    // validated registration data, see `CustomerValidator::validate_new_customer`
+    pub(crate) email: String,
+    pub(crate) first_name: String,
+    pub(crate) last_name: String,
+    pub(crate) date_birth: NaiveDate,
+    pub(crate) phone: String,
+    pub(crate) city: String,
+    pub(crate) country: String,
+    pub(crate) password: String,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -39,6 +57,62 @@ pub struct CustomerLoginPostForm {
     pub(crate) confirm_password: String,
 }
 
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ForgotPasswordForm {
+    pub(crate) email: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ResetPasswordForm {
+    pub(crate) password: String,
+    pub(crate) confirm_password: String,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct UpdateProfileForm {
+    #[serde(default)]
+    pub(crate) first_name: String,
+    #[serde(default)]
+    pub(crate) last_name: String,
+    #[serde(default)]
+    pub(crate) date_birth: String,
+    #[serde(default)]
+    pub(crate) phone: String,
+    #[serde(default)]
+    pub(crate) city: String,
+    #[serde(default)]
+    pub(crate) country: String,
+}
+
+#[derive(Debug)]
+pub struct ProfileUpdate {
+    // validated profile form, see `CustomerValidator::validate_profile_update`
+    pub(crate) first_name: String,
+    pub(crate) last_name: String,
+    pub(crate) date_birth: NaiveDate,
+    pub(crate) phone: String,
+    pub(crate) city: String,
+    pub(crate) country: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ChangePasswordForm {
+    #[serde(default)]
+    pub(crate) current_password: String,
+    #[serde(default)]
+    pub(crate) password: String,
+    #[serde(default)]
+    pub(crate) confirm_password: String,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ChangeEmailForm {
+    #[serde(default)]
+    pub(crate) email: String,
+    #[serde(default, skip_serializing)]
+    pub(crate) current_password: String,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ProfileCustomer {
     pub(crate) is_authenticated: bool,
@@ -50,4 +124,6 @@ pub struct ProfileCustomer {
     pub(crate) phone: String,
     pub(crate) city: String,
     pub(crate) country: String,
+    pub(crate) is_email_verified: bool,
+    pub(crate) is_staff: bool,
 }
@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+
+// why product_variants.stock changed, one stock_movements row per change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "stock_movement_reason", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum StockMovementReason {
+    Adjustment,  // restock, stocktake corrections
+    Reservation, // order placed, negative quantity
+    Release,     // reserved order cancelled, positive quantity
+    Return,      // returned goods back on the shelf, positive quantity
+}
+
+// `adjust-stock` quantity: "+5" / "-2" move the count, "=12" sets it after a stock take
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StockChange {
+    By(i32),
+    To(i32),
+}
+
+impl StockChange {
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if let Some(count) = value.strip_prefix('=') {
+            return count.parse().ok().filter(|count| *count >= 0).map(Self::To);
+        }
+        value.parse().ok().map(Self::By)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StockAdjustment {
+    Adjusted { stock: i32 },
+    UnknownSku,
+    // the change would take the shelf below zero
+    Insufficient { stock: i32 },
+}
@@ -0,0 +1,10 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct Invoice {
+    pub(crate) id: i64,
+    pub(crate) number: String, // "2026-000042"
+    pub(crate) order_id: String,
+    pub(crate) issued_at: NaiveDateTime,
+}
@@ -1,4 +1,17 @@
 pub mod state;
 pub mod products;
 pub mod customer;
-pub mod order;
\ No newline at end of file
+pub mod order;
+pub mod cart;
+pub mod session;
+pub mod contact;
+pub mod address;
+pub mod inventory;
+pub mod money;
+pub mod promotion;
+pub mod shipping;
+pub mod payment;
+pub mod returns;
+pub mod tax;
+pub mod invoice;
+pub mod country;
\ No newline at end of file
@@ -0,0 +1,438 @@
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::postgres::{PgArgumentBuffer, PgTypeInfo, PgValueRef};
+use sqlx::{Decode, Encode, Postgres, Type};
+
+// prices in the catalog are kept in this currency
+pub const BASE_CURRENCY: Currency = Currency::Usd;
+
+// ISO 4217, stored as the code (money_amount.currency)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Currency {
+    Usd,
+    Eur,
+    Gbp,
+}
+
+impl Currency {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Currency::Usd => "USD",
+            Currency::Eur => "EUR",
+            Currency::Gbp => "GBP",
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Currency::Usd => "$",
+            Currency::Eur => "€",
+            Currency::Gbp => "£",
+        }
+    }
+
+    // digits after the decimal point, 2 for every currency we sell in
+    pub fn minor_units(&self) -> u32 {
+        2
+    }
+
+    pub fn from_code(code: &str) -> Option<Currency> {
+        match code.trim().to_ascii_uppercase().as_str() {
+            "USD" => Some(Currency::Usd),
+            "EUR" => Some(Currency::Eur),
+            "GBP" => Some(Currency::Gbp),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Currency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+impl Type<Postgres> for Currency {
+    fn type_info() -> PgTypeInfo {
+        <&str as Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <&str as Type<Postgres>>::compatible(ty)
+    }
+}
+
+impl Encode<'_, Postgres> for Currency {
+    fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> Result<IsNull, BoxDynError> {
+        <&str as Encode<Postgres>>::encode(self.code(), buf)
+    }
+}
+
+impl<'r> Decode<'r, Postgres> for Currency {
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        let code = <&str as Decode<Postgres>>::decode(value)?;
+        Currency::from_code(code).ok_or_else(|| format!("unknown currency {:?}", code).into())
+    }
+}
+
+// an amount in minor units (cents) of a currency, the `money_amount` composite in Postgres
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "money_amount")]
+pub struct Money {
+    pub(crate) amount: i64,
+    pub(crate) currency: Currency,
+}
+
+impl Money {
+    pub fn new(amount: i64, currency: Currency) -> Self {
+        Money { amount, currency }
+    }
+
+    pub fn zero(currency: Currency) -> Self {
+        Money::new(0, currency)
+    }
+
+    pub fn times(&self, quantity: i32) -> Money {
+        Money::new(self.amount * quantity as i64, self.currency)
+    }
+
+    // amounts of different currencies can't be added, see `CurrencyMismatch`
+    pub fn checked_add(self, other: Money) -> Result<Money, CurrencyMismatch> {
+        if self.currency != other.currency {
+            return Err(CurrencyMismatch(self.currency, other.currency));
+        }
+        Ok(Money::new(self.amount + other.amount, self.currency))
+    }
+
+    pub fn checked_sub(self, other: Money) -> Result<Money, CurrencyMismatch> {
+        self.checked_add(-other)
+    }
+
+    // zero for no amounts
+    pub fn sum(
+        currency: Currency,
+        amounts: impl IntoIterator<Item = Money>,
+    ) -> Result<Money, CurrencyMismatch> {
+        amounts
+            .into_iter()
+            .try_fold(Money::zero(currency), Money::checked_add)
+    }
+
+    // rounded to the minor unit, half away from zero
+    pub fn percent(&self, percent: Decimal) -> Money {
+        let value = Decimal::from(self.amount) * percent / Decimal::ONE_HUNDRED;
+        Money::new(round_to_minor_unit(value), self.currency)
+    }
+
+    // the amount with `percent` on top, net price to gross
+    pub fn plus_percent(&self, percent: Decimal) -> Money {
+        Money::new(self.amount + self.percent(percent).amount, self.currency)
+    }
+
+    // `part / whole` of the amount, rounded like `percent`
+    pub fn share(&self, part: i64, whole: i64) -> Money {
+        if whole == 0 {
+            return Money::zero(self.currency);
+        }
+        let share = self.amount as i128 * part as i128;
+        let rounded = (share + share.signum() * (whole.abs() as i128) / 2) / whole as i128;
+        Money::new(rounded as i64, self.currency)
+    }
+
+    // "12", "12.5" or "12,50" in major units of `currency`, for amounts typed into forms
+    pub fn parse(input: &str, currency: Currency) -> Option<Money> {
+        let input = input.trim().replace(',', ".");
+        let (units, fraction) = input.split_once('.').unwrap_or((&input, ""));
+        let digits = currency.minor_units() as usize;
+        if units.is_empty()
+            || fraction.len() > digits
+            || !units
+                .bytes()
+                .chain(fraction.bytes())
+                .all(|b| b.is_ascii_digit())
+        {
+            return None;
+        }
+        let units: i64 = units.parse().ok()?;
+        let fraction: i64 = format!("{:0<digits$}", fraction).parse().unwrap_or(0);
+        let amount = units
+            .checked_mul(10i64.pow(digits as u32))?
+            .checked_add(fraction)?;
+        Some(Money::new(amount, currency))
+    }
+
+    // "1234.50", the counterpart of `parse` to fill in form fields
+    pub fn decimal(&self) -> String {
+        let digits = self.currency.minor_units();
+        let scale = 10i64.pow(digits);
+        let sign = if self.amount < 0 { "-" } else { "" };
+        let (units, fraction) = (
+            (self.amount / scale).unsigned_abs(),
+            (self.amount % scale).unsigned_abs(),
+        );
+        if digits == 0 {
+            return format!("{}{}", sign, units);
+        }
+        format!(
+            "{}{}.{:0width$}",
+            sign,
+            units,
+            fraction,
+            width = digits as usize
+        )
+    }
+
+    // "$1,234.50" for en, "1.234,50 €" for de, the language part of the locale picks the style
+    pub fn format(&self, locale: &str) -> String {
+        let language = locale
+            .split(['-', '_'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let (group, decimal, symbol_first) = match language.as_str() {
+            "de" | "es" | "it" | "nl" | "pt" => (".", ",", false),
+            "fr" => ("\u{202f}", ",", false),
+            _ => (",", ".", true),
+        };
+
+        let scale = 10i64.pow(self.currency.minor_units());
+        let units = (self.amount / scale).unsigned_abs().to_string();
+        let mut grouped = String::with_capacity(units.len() + units.len() / 3);
+        for (i, digit) in units.chars().enumerate() {
+            if i > 0 && (units.len() - i).is_multiple_of(3) {
+                grouped.push_str(group);
+            }
+            grouped.push(digit);
+        }
+        let number = format!(
+            "{}{}{:0width$}",
+            grouped,
+            decimal,
+            (self.amount % scale).unsigned_abs(),
+            width = self.currency.minor_units() as usize
+        );
+
+        let sign = if self.amount < 0 { "-" } else { "" };
+        if symbol_first {
+            format!("{}{}{}", sign, self.currency.symbol(), number)
+        } else {
+            format!("{}{}\u{a0}{}", sign, number, self.currency.symbol())
+        }
+    }
+}
+
+impl std::ops::Neg for Money {
+    type Output = Money;
+
+    fn neg(self) -> Money {
+        Money::new(-self.amount, self.currency)
+    }
+}
+
+impl std::fmt::Display for Money {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.format("en"))
+    }
+}
+
+// amounts of different currencies never meet in one cart or order, adding them anyway is a bug
+// that fails the request with this instead of panicking
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CurrencyMismatch(pub Currency, pub Currency);
+
+impl std::fmt::Display for CurrencyMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} and {} amounts can't be added", self.0, self.1)
+    }
+}
+
+impl std::error::Error for CurrencyMismatch {}
+
+// price of one unit of BASE_CURRENCY in `currency`, exact like exchange_rates.rate NUMERIC(18, 8)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ExchangeRate {
+    pub(crate) currency: Currency,
+    pub(crate) rate: Decimal,
+}
+
+impl ExchangeRate {
+    pub fn base() -> Self {
+        ExchangeRate {
+            currency: BASE_CURRENCY,
+            rate: Decimal::ONE,
+        }
+    }
+
+    // catalog prices are in BASE_CURRENCY, anything already converted is returned as is
+    pub fn convert(&self, money: Money) -> Money {
+        if money.currency != BASE_CURRENCY || self.currency == BASE_CURRENCY {
+            return money;
+        }
+        let converted = Decimal::from(money.amount) * self.rate;
+        let converted =
+            match self.currency.minor_units() as i32 - BASE_CURRENCY.minor_units() as i32 {
+                shift if shift >= 0 => converted * Decimal::from(10i64.pow(shift as u32)),
+                shift => converted / Decimal::from(10i64.pow(shift.unsigned_abs())),
+            };
+        Money::new(round_to_minor_unit(converted), self.currency)
+    }
+}
+
+// half away from zero, saturating at the i64 bounds
+fn round_to_minor_unit(value: Decimal) -> i64 {
+    value
+        .round_dp_with_strategy(0, RoundingStrategy::MidpointAwayFromZero)
+        .to_i64()
+        .unwrap_or(if value.is_sign_negative() {
+            i64::MIN
+        } else {
+            i64::MAX
+        })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CurrencyForm {
+    pub(crate) currency: String,
+    #[serde(default)]
+    pub(crate) next: String, // page to go back to
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usd(amount: i64) -> Money {
+        Money::new(amount, Currency::Usd)
+    }
+
+    fn eur(amount: i64) -> Money {
+        Money::new(amount, Currency::Eur)
+    }
+
+    #[test]
+    fn format_groups_thousands() {
+        assert_eq!(usd(5).format("en-US"), "$0.05");
+        assert_eq!(usd(99_999).format("en-US"), "$999.99");
+        assert_eq!(usd(123_450).format("en-US"), "$1,234.50");
+        assert_eq!(usd(12_345_678_900).format("en"), "$123,456,789.00");
+    }
+
+    #[test]
+    fn format_negative_amounts() {
+        assert_eq!(usd(-123_450).format("en-US"), "-$1,234.50");
+        assert_eq!(eur(-5).format("de-DE"), "-0,05\u{a0}€");
+    }
+
+    #[test]
+    fn format_follows_the_locale_language() {
+        assert_eq!(eur(123_450).format("de-DE"), "1.234,50\u{a0}€");
+        assert_eq!(eur(123_450).format("de_AT"), "1.234,50\u{a0}€");
+        assert_eq!(eur(123_450).format("fr-FR"), "1\u{202f}234,50\u{a0}€");
+        assert_eq!(
+            Money::new(123_450, Currency::Gbp).format("en-GB"),
+            "£1,234.50"
+        );
+        // unknown languages get the english layout
+        assert_eq!(eur(123_450).format("xx"), "€1,234.50");
+    }
+
+    #[test]
+    fn parse_accepts_dot_and_comma() {
+        assert_eq!(Money::parse("12", Currency::Usd), Some(usd(1200)));
+        assert_eq!(Money::parse("12.5", Currency::Usd), Some(usd(1250)));
+        assert_eq!(Money::parse(" 12,50 ", Currency::Eur), Some(eur(1250)));
+        assert_eq!(Money::parse("0.07", Currency::Usd), Some(usd(7)));
+    }
+
+    #[test]
+    fn parse_rejects_malformed_amounts() {
+        for input in [
+            "", ".50", "12.505", "-1", "1e3", "12.5.0", "abc", "1,234.50",
+        ] {
+            assert_eq!(Money::parse(input, Currency::Usd), None, "{:?}", input);
+        }
+        assert_eq!(Money::parse("99999999999999999999", Currency::Usd), None);
+    }
+
+    #[test]
+    fn decimal_round_trips_through_parse() {
+        assert_eq!(usd(123_450).decimal(), "1234.50");
+        assert_eq!(usd(5).decimal(), "0.05");
+        assert_eq!(usd(-5).decimal(), "-0.05");
+        for amount in [0, 1, 99, 100, 123_456] {
+            assert_eq!(
+                Money::parse(&usd(amount).decimal(), Currency::Usd),
+                Some(usd(amount))
+            );
+        }
+    }
+
+    #[test]
+    fn share_rounds_half_away_from_zero() {
+        assert_eq!(usd(100).share(1, 3), usd(33));
+        assert_eq!(usd(200).share(1, 3), usd(67));
+        assert_eq!(usd(1).share(1, 2), usd(1));
+        assert_eq!(usd(-1).share(1, 2), usd(-1));
+        assert_eq!(usd(-200).share(1, 3), usd(-67));
+        assert_eq!(usd(1000).share(3, 3), usd(1000));
+        assert_eq!(usd(1000).share(1, 0), usd(0));
+    }
+
+    #[test]
+    fn percent_rounds_to_the_cent() {
+        let rate = |rate: &str| rate.parse::<Decimal>().unwrap();
+        assert_eq!(usd(1999).percent(rate("19")), usd(380));
+        assert_eq!(usd(1000).percent(rate("7.5")), usd(75));
+        assert_eq!(usd(1999).plus_percent(rate("19.00")), usd(2379));
+        // exactly half a cent, which f64 computes as 160.49999999999997
+        assert_eq!(usd(1500).percent(rate("10.7")), usd(161));
+        assert_eq!(usd(-1500).percent(rate("10.7")), usd(-161));
+    }
+
+    #[test]
+    fn adding_checks_the_currency() {
+        assert_eq!(usd(150).checked_add(usd(250)), Ok(usd(400)));
+        assert_eq!(usd(150).checked_sub(usd(250)), Ok(usd(-100)));
+        assert_eq!(
+            usd(150).checked_add(eur(250)),
+            Err(CurrencyMismatch(Currency::Usd, Currency::Eur))
+        );
+        assert_eq!(
+            Money::sum(Currency::Usd, [usd(1), usd(2), usd(3)]),
+            Ok(usd(6))
+        );
+        assert_eq!(Money::sum(Currency::Eur, []), Ok(eur(0)));
+        assert!(Money::sum(Currency::Usd, [usd(1), eur(2)]).is_err());
+    }
+
+    #[test]
+    fn convert_uses_the_exact_rate() {
+        let rate = ExchangeRate {
+            currency: Currency::Eur,
+            rate: "0.92150000".parse().unwrap(),
+        };
+        assert_eq!(rate.convert(usd(1999)), eur(1842));
+        assert_eq!(rate.convert(usd(-1999)), eur(-1842));
+        let half = ExchangeRate {
+            currency: Currency::Eur,
+            rate: "0.5".parse().unwrap(),
+        };
+        assert_eq!(half.convert(usd(1)), eur(1));
+        assert_eq!(half.convert(usd(-1)), eur(-1));
+        let rate = ExchangeRate {
+            currency: Currency::Gbp,
+            rate: "1.00000005".parse().unwrap(),
+        };
+        assert_eq!(
+            rate.convert(usd(10_000_000_000)),
+            Money::new(10_000_000_500, Currency::Gbp)
+        );
+        // already converted amounts stay as they are
+        assert_eq!(rate.convert(eur(100)), eur(100));
+        assert_eq!(ExchangeRate::base().convert(usd(100)), usd(100));
+    }
+}
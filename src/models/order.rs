@@ -1,24 +1,224 @@
+use crate::models::address::AddressSnapshot;
+use crate::models::money::{Currency, CurrencyMismatch, ExchangeRate, Money};
 use chrono::NaiveDateTime;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct NewOrderForm {
-    pub(crate) product_id: i32, // uuid
+    pub(crate) variant_id: i32,
     pub(crate) quantity: i32,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "order_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum OrderStatus {
+    Cart,
+    Placed,
+    #[serde(rename = "payment_failed")]
+    #[sqlx(rename = "payment_failed")]
+    PaymentFailed,
+    Paid,
+    Shipped,
+    Delivered,
+    Cancelled,
+    Refunded,
+}
+
+impl OrderStatus {
+    pub fn can_transition_to(self, next: OrderStatus) -> bool {
+        use OrderStatus::*;
+        matches!(
+            (self, next),
+            (Cart, Placed)
+                | (Cart, Cancelled)
+                | (Placed, Paid)
+                | (Placed, PaymentFailed)
+                | (Placed, Cancelled)
+                | (PaymentFailed, Paid)
+                | (PaymentFailed, Cancelled)
+                | (Paid, Shipped)
+                | (Paid, Cancelled)
+                | (Paid, Refunded)
+                | (Shipped, Delivered)
+                | (Delivered, Refunded)
+        )
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderStatus::Cart => "cart",
+            OrderStatus::Placed => "placed",
+            OrderStatus::PaymentFailed => "payment_failed",
+            OrderStatus::Paid => "paid",
+            OrderStatus::Shipped => "shipped",
+            OrderStatus::Delivered => "delivered",
+            OrderStatus::Cancelled => "cancelled",
+            OrderStatus::Refunded => "refunded",
+        }
+    }
+}
+
+impl std::fmt::Display for OrderStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+// who moved the order to a new status, stored as text in order_status_history.actor
+#[derive(Debug, Clone)]
+pub enum OrderActor {
+    Customer(i64),
+    PaymentProvider(String), // `PaymentGateway::name`
+    Staff(i64),
+}
+
+impl std::fmt::Display for OrderActor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrderActor::Customer(id) => write!(f, "customer:{}", id),
+            OrderActor::PaymentProvider(name) => write!(f, "payment:{}", name),
+            OrderActor::Staff(id) => write!(f, "staff:{}", id),
+        }
+    }
+}
+
 // update by order_id
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreatedOrder {
     pub(crate) order_id: String, // uuid
-    pub(crate) product_ids: Vec<i32>,
+    pub(crate) status: OrderStatus,
+    pub(crate) product_ids: Vec<i32>, // one per order line
+    pub(crate) total: Money,
+    pub(crate) created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrderDetails {
+    // single order page + receipt
+    pub(crate) order_id: String, // uuid
+    pub(crate) status: OrderStatus,
+    pub(crate) is_confirmed: bool,
     pub(crate) created_at: NaiveDateTime,
+    pub(crate) shipping_address: Option<AddressSnapshot>, // None for orders placed before addresses existed
+    pub(crate) billing_address: Option<AddressSnapshot>,
+    pub(crate) lines: Vec<OrderLine>,
+    pub(crate) currency: Currency,
+    pub(crate) exchange_rate: Decimal, // units of `currency` per unit of the base currency
+    pub(crate) subtotal: Money,        // sum of the lines
+    pub(crate) promotion_code: Option<String>,
+    pub(crate) discount: Option<Money>,
+    pub(crate) shipping_rate_id: Option<i32>,
+    pub(crate) shipping_method: Option<String>,
+    pub(crate) shipping_cost: Option<Money>, // None before checkout had shipping, and on drafts
+    pub(crate) tax: Option<Money>,           // lines and shipping
+    pub(crate) tax_country: Option<String>,
+    pub(crate) shipping_tax: Option<Money>,
+    pub(crate) total: Money, // see `update_total`
+    pub(crate) status_history: Vec<OrderStatusChange>,
+}
+
+impl OrderDetails {
+    // the rate the order was priced at
+    pub fn exchange_rate(&self) -> ExchangeRate {
+        ExchangeRate {
+            currency: self.currency,
+            rate: self.exchange_rate,
+        }
+    }
+
+    // what the goods cost after the coupon
+    pub fn goods_total(&self) -> Result<Money, CurrencyMismatch> {
+        self.discount.map_or(Ok(self.subtotal), |discount| {
+            self.subtotal.checked_sub(discount)
+        })
+    }
+
+    pub fn update_total(&mut self) -> Result<(), CurrencyMismatch> {
+        self.total = [self.shipping_cost, self.tax]
+            .into_iter()
+            .flatten()
+            .try_fold(self.goods_total()?, Money::checked_add)?;
+        Ok(())
+    }
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrderLine {
+    pub(crate) id: i32, // orders_product.id
+    pub(crate) product_id: i32,
+    pub(crate) product_name: String,
+    pub(crate) product_code: String,
+    pub(crate) variant_id: Option<i32>, // None for old lines not mapped to a variant, see order_line_variants.sql
+    pub(crate) sku: Option<String>,
+    pub(crate) size: Option<String>,
+    pub(crate) colour: Option<String>,
+    pub(crate) unit_price: Money,
+    pub(crate) quantity: i32,
+    pub(crate) line_total: Money,
+    pub(crate) tax_rate: Option<Decimal>, // percent, None on drafts and orders placed before per line tax
+    pub(crate) tax: Option<Money>,        // on the line total after its share of the coupon
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrderStatusChange {
+    pub(crate) from_status: Option<OrderStatus>,
+    pub(crate) to_status: OrderStatus,
+    pub(crate) actor: String,
+    pub(crate) created_at: NaiveDateTime,
+}
+
+// an order on the staff fulfilment list, paid and waiting to go out or on its way
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FulfilmentOrder {
+    pub(crate) order_id: String, // uuid
+    pub(crate) customer_email: String,
+    pub(crate) status: OrderStatus,
+    pub(crate) created_at: NaiveDateTime,
+    pub(crate) shipping_address: Option<AddressSnapshot>,
+    pub(crate) shipping_method: Option<String>,
+    pub(crate) lines: Vec<FulfilmentLine>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FulfilmentLine {
+    pub(crate) product_name: String,
+    pub(crate) sku: Option<String>,
+    pub(crate) size: Option<String>,
+    pub(crate) colour: Option<String>,
+    pub(crate) quantity: i32,
+}
+
+// lines for the services' unit tests
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::OrderLine;
+    use crate::models::money::Money;
+
+    // one unit of product `product_id`, the line and variant ids are the same number
+    pub(crate) fn order_line(product_id: i32, line_total: Money) -> OrderLine {
+        OrderLine {
+            id: product_id,
+            product_id,
+            product_name: format!("Product {}", product_id),
+            product_code: format!("P{}", product_id),
+            variant_id: Some(product_id),
+            sku: None,
+            size: None,
+            colour: None,
+            unit_price: line_total,
+            quantity: 1,
+            line_total,
+            tax_rate: None,
+            tax: None,
+        }
+    }
+}
 
 // #[derive(Debug, Serialize, Deserialize)]
 // pub struct Order {
 //     pub(crate) order_id: String, // uuid
 //     pub(crate) product_ids: Vec<i32>,
-// }
\ No newline at end of file
+// }
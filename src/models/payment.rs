@@ -0,0 +1,77 @@
+use crate::models::money::{CurrencyMismatch, Money};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "payment_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum PaymentStatus {
+    Pending,
+    Authorized,
+    Captured,
+    Failed,
+    Refunded,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Payment {
+    pub(crate) id: i64,
+    pub(crate) order_id: String, // uuid
+    pub(crate) provider: String,
+    pub(crate) reference: Option<String>,
+    pub(crate) amount: Money,
+    pub(crate) status: PaymentStatus,
+    pub(crate) failure_reason: Option<String>,
+    pub(crate) created_at: NaiveDateTime,
+}
+
+// pending from the cancellation or return decision until the provider accepted it
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "refund_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum RefundStatus {
+    Pending,
+    Succeeded,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Refund {
+    pub(crate) id: i64,
+    pub(crate) payment_id: i64,
+    pub(crate) return_request_id: Option<i64>, // None for cancellations
+    pub(crate) reference: Option<String>,      // None while pending
+    pub(crate) amount: Money,
+    pub(crate) status: RefundStatus,
+    pub(crate) failure: Option<String>,
+    pub(crate) created_at: NaiveDateTime,
+}
+
+// a refund written in the order's transaction, for the provider once it committed
+#[derive(Debug)]
+pub struct PendingRefund {
+    pub(crate) id: i64,
+    pub(crate) payment_id: i64,
+    pub(crate) payment_reference: String,
+    pub(crate) amount: Money,
+}
+
+// a captured payment and how much of it went back already
+#[derive(Debug)]
+pub struct RefundablePayment {
+    pub(crate) id: i64,
+    pub(crate) reference: String,
+    pub(crate) amount: Money,
+    pub(crate) refunded: Money,
+}
+
+impl RefundablePayment {
+    pub fn remaining(&self) -> Result<Money, CurrencyMismatch> {
+        self.amount.checked_sub(self.refunded)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PaymentForm {
+    #[serde(default)]
+    pub(crate) card_number: String,
+}
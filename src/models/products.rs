@@ -1,3 +1,6 @@
+use crate::models::money::{ExchangeRate, Money};
+use crate::models::order::OrderStatus;
+use crate::models::tax::{TaxDisplay, TaxRates};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use sqlx::types::Json;
@@ -7,10 +10,23 @@ pub struct ProductsWithCategory {
     // main page (latest products)
     pub(crate) id: i32,
     pub(crate) name: String,
-    pub(crate) price: i32,
+    pub(crate) price: Money,
     pub(crate) images: Json<Vec<String>>,
     pub(crate) rating: i32,
     pub(crate) code: String,
+    pub(crate) is_in_stock: bool,
+}
+
+impl ProductsWithCategory {
+    pub fn convert_prices(&mut self, rate: &ExchangeRate) {
+        self.price = rate.convert(self.price);
+    }
+
+    pub fn apply_tax(&mut self, rates: &TaxRates, display: TaxDisplay) {
+        if display == TaxDisplay::Inclusive {
+            self.price = rates.gross(self.id, self.price);
+        }
+    }
 }
 
 fn default_page() -> i64 {
@@ -33,10 +49,23 @@ pub struct Product {
     // render product in products list
     pub(crate) id: i32,
     pub(crate) name: String,
-    pub(crate) price: i32,
+    pub(crate) price: Money,
     pub(crate) rating: i32,
     pub(crate) code: String,
     pub(crate) images: Json<Vec<String>>,
+    pub(crate) is_in_stock: bool,
+}
+
+impl Product {
+    pub fn convert_prices(&mut self, rate: &ExchangeRate) {
+        self.price = rate.convert(self.price);
+    }
+
+    pub fn apply_tax(&mut self, rates: &TaxRates, display: TaxDisplay) {
+        if display == TaxDisplay::Inclusive {
+            self.price = rates.gross(self.id, self.price);
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -45,25 +74,65 @@ pub struct FullProduct {
     pub(crate) id: i32,
     pub(crate) name: String,
     pub(crate) description: Option<String>,
-    pub(crate) price: i32,
+    pub(crate) price: Money,
     pub(crate) rating: i32,
     pub(crate) code: String,
     pub(crate) images: Json<Vec<String>>,
+    pub(crate) variants: Vec<ProductVariant>,
+    pub(crate) is_in_stock: bool, // any variant has stock
+}
+
+impl FullProduct {
+    pub fn convert_prices(&mut self, rate: &ExchangeRate) {
+        self.price = rate.convert(self.price);
+        for variant in &mut self.variants {
+            variant.price = rate.convert(variant.price);
+        }
+    }
+
+    pub fn apply_tax(&mut self, rates: &TaxRates, display: TaxDisplay) {
+        if display == TaxDisplay::Inclusive {
+            self.price = rates.gross(self.id, self.price);
+            for variant in &mut self.variants {
+                variant.price = rates.gross(self.id, variant.price);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ProductVariant {
+    // size/colour selector on the product page
+    pub(crate) id: i32,
+    pub(crate) sku: String,
+    pub(crate) size: String,
+    pub(crate) colour: String,
+    pub(crate) price: Money, // override or the product price
+    pub(crate) stock: i32,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct SumProduct {
     // render product
     pub(crate) id: i32,
-    pub(crate) price: i32,
+    pub(crate) variant_id: i32,
+    pub(crate) price: Money,
+    pub(crate) stock: i32,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct OrderProductInfo {
     pub(crate) order_id: String,
     pub(crate) product_id: i32,
-    pub(crate) product_price: i32,
     pub(crate) product_name: String,
     pub(crate) product_code: String,
-    pub(crate) order_status: Option<String>,
+    pub(crate) size: Option<String>, // None for old lines not mapped to a variant, see order_line_variants.sql
+    pub(crate) colour: Option<String>,
+    pub(crate) quantity: i32,
+    pub(crate) unit_price: Money,
+    pub(crate) line_total: Money,
+    pub(crate) order_status: OrderStatus,
+    pub(crate) order_discount: Option<Money>, // coupon discount of the whole order
+    pub(crate) order_shipping_cost: Option<Money>,
+    pub(crate) order_tax: Option<Money>,
 }
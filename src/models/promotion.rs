@@ -0,0 +1,47 @@
+use crate::models::money::Money;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "discount_kind", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum DiscountKind {
+    Percentage,
+    FixedAmount,
+}
+
+#[derive(Debug, Clone)]
+pub struct Promotion {
+    pub(crate) id: i32,
+    pub(crate) code: String,
+    pub(crate) kind: DiscountKind,
+    pub(crate) percent_off: Option<i32>,
+    pub(crate) amount_off: Option<Money>,
+    pub(crate) min_order_total: Option<Money>,
+    pub(crate) category_id: Option<i32>,
+    pub(crate) product_id: Option<i32>,
+    pub(crate) max_uses: Option<i32>,
+    pub(crate) max_uses_per_customer: Option<i32>,
+    pub(crate) has_started: bool, // validity window, checked against the database clock
+    pub(crate) has_ended: bool,
+}
+
+// how often a code was used so far, for the usage limits
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PromotionUsage {
+    pub(crate) total: i64,
+    pub(crate) by_customer: i64,
+}
+
+// a code that passed every check, stored on the order
+#[derive(Debug, Clone, Serialize)]
+pub struct AppliedPromotion {
+    pub(crate) promotion_id: i32,
+    pub(crate) code: String,
+    pub(crate) discount: Money,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CouponForm {
+    #[serde(default)]
+    pub(crate) coupon_code: String, // empty removes the code
+}
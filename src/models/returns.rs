@@ -0,0 +1,154 @@
+use crate::models::money::{CurrencyMismatch, Money};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "return_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ReturnStatus {
+    Requested,
+    Approved, // goods restocked (unless damaged) and refunded
+    Rejected,
+}
+
+impl ReturnStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReturnStatus::Requested => "requested",
+            ReturnStatus::Approved => "approved",
+            ReturnStatus::Rejected => "rejected",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<ReturnStatus> {
+        match value {
+            "requested" => Some(ReturnStatus::Requested),
+            "approved" => Some(ReturnStatus::Approved),
+            "rejected" => Some(ReturnStatus::Rejected),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "return_reason", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum ReturnReason {
+    TooSmall,
+    TooLarge,
+    Damaged,
+    WrongItem,
+    NotAsDescribed,
+    ChangedMind,
+}
+
+impl ReturnReason {
+    pub const ALL: [ReturnReason; 6] = [
+        ReturnReason::TooSmall,
+        ReturnReason::TooLarge,
+        ReturnReason::Damaged,
+        ReturnReason::WrongItem,
+        ReturnReason::NotAsDescribed,
+        ReturnReason::ChangedMind,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReturnReason::TooSmall => "too_small",
+            ReturnReason::TooLarge => "too_large",
+            ReturnReason::Damaged => "damaged",
+            ReturnReason::WrongItem => "wrong_item",
+            ReturnReason::NotAsDescribed => "not_as_described",
+            ReturnReason::ChangedMind => "changed_mind",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ReturnReason::TooSmall => "Too small",
+            ReturnReason::TooLarge => "Too large",
+            ReturnReason::Damaged => "Arrived damaged",
+            ReturnReason::WrongItem => "Wrong item sent",
+            ReturnReason::NotAsDescribed => "Not as described",
+            ReturnReason::ChangedMind => "Changed my mind",
+        }
+    }
+
+    // damaged goods can't be sold again, approving their return doesn't put them back in stock
+    pub fn is_restocked(&self) -> bool {
+        !matches!(self, ReturnReason::Damaged)
+    }
+
+    pub fn parse(value: &str) -> Option<ReturnReason> {
+        Self::ALL
+            .into_iter()
+            .find(|reason| reason.as_str() == value)
+    }
+
+    // (value, label) pairs for the select boxes
+    pub fn options() -> Vec<(&'static str, &'static str)> {
+        Self::ALL
+            .iter()
+            .map(|reason| (reason.as_str(), reason.label()))
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReturnRequest {
+    pub(crate) id: i64,
+    pub(crate) order_id: String, // uuid
+    pub(crate) customer_id: i64,
+    pub(crate) customer_email: String,
+    pub(crate) status: ReturnStatus,
+    pub(crate) comment: Option<String>,
+    pub(crate) staff_note: Option<String>,
+    pub(crate) decided_at: Option<NaiveDateTime>,
+    pub(crate) created_at: NaiveDateTime,
+    pub(crate) refunded: Option<Money>, // sum of its refunds, None before approval
+    pub(crate) lines: Vec<ReturnLine>,
+}
+
+impl ReturnRequest {
+    // what the returned goods cost on the order, before coupon and tax
+    pub fn goods_value(&self) -> Result<Option<Money>, CurrencyMismatch> {
+        let mut amounts = self
+            .lines
+            .iter()
+            .map(|line| line.unit_price.times(line.quantity));
+        let Some(first) = amounts.next() else {
+            return Ok(None);
+        };
+        amounts.try_fold(first, Money::checked_add).map(Some)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReturnLine {
+    pub(crate) order_line_id: i32,
+    pub(crate) product_name: String,
+    pub(crate) product_code: String,
+    pub(crate) variant_id: Option<i32>,
+    pub(crate) sku: Option<String>,
+    pub(crate) size: Option<String>,
+    pub(crate) colour: Option<String>,
+    pub(crate) unit_price: Money,
+    pub(crate) quantity: i32,
+    pub(crate) reason: ReturnReason,
+}
+
+// a line the customer picked in the return form
+#[derive(Debug, Clone, Copy)]
+pub struct NewReturnLine {
+    pub(crate) order_line_id: i32,
+    pub(crate) quantity: i32,
+    pub(crate) reason: ReturnReason,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReturnDecisionForm {
+    #[serde(default)]
+    pub(crate) refund_amount: String, // in major units, e.g. "12.50"
+    #[serde(default)]
+    pub(crate) staff_note: String,
+}
@@ -0,0 +1,41 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CurrentSession {
+    // session behind the current request, inserted by `optional_customer`
+    pub(crate) id: i64,
+    pub(crate) customer_id: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ActiveSession {
+    // profile page "devices" list
+    pub(crate) id: i64,
+    pub(crate) user_agent: Option<String>,
+    pub(crate) ip_address: Option<String>,
+    pub(crate) created_at: NaiveDateTime,
+    pub(crate) last_seen_at: NaiveDateTime,
+    pub(crate) expires_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevokeSessionForm {
+    pub(crate) session_id: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "login_outcome", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum LoginOutcome {
+    Success,
+    InvalidCredentials,
+    Blocked, // rejected by throttling before the password was checked
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RecentFailures {
+    // failed attempts inside the throttling window for one email or one ip
+    pub(crate) count: i64,
+    pub(crate) seconds_since_last: Option<f64>,
+}
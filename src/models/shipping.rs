@@ -0,0 +1,42 @@
+use crate::models::money::{ExchangeRate, Money};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShippingRate {
+    pub(crate) id: i32,
+    pub(crate) name: String,
+    pub(crate) description: Option<String>,
+    pub(crate) price: Money, // base currency, like the catalog
+    pub(crate) free_over: Option<Money>,
+    pub(crate) min_days: Option<i32>,
+    pub(crate) max_days: Option<i32>,
+}
+
+impl ShippingRate {
+    // what the customer pays in the order currency, `goods_total` is after discounts
+    pub fn cost(&self, goods_total: Money, rate: &ExchangeRate) -> Money {
+        let is_free = self
+            .free_over
+            .map(|free_over| rate.convert(free_over))
+            .is_some_and(|free_over| {
+                free_over.currency == goods_total.currency && goods_total.amount >= free_over.amount
+            });
+        if is_free {
+            Money::zero(rate.currency)
+        } else {
+            rate.convert(self.price)
+        }
+    }
+}
+
+// a shipping rate with its price for the order at hand, for the shipping step
+#[derive(Debug, Serialize)]
+pub struct ShippingOption {
+    pub(crate) rate: ShippingRate,
+    pub(crate) cost: Money,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShippingMethodForm {
+    pub(crate) shipping_rate_id: i32,
+}
@@ -1,6 +1,19 @@
+use crate::models::tax::TaxDisplay;
+use crate::services::mailer::Mailer;
+use crate::services::payments::PaymentGateway;
 use minijinja::Environment;
+use std::net::IpAddr;
 
 #[derive(Debug)]
 pub struct AppState {
     pub tpl_env: Environment<'static>,
+    pub mailer: Box<dyn Mailer>,
+    pub payments: Box<dyn PaymentGateway>,
+    pub base_url: String,             // absolute links in emails
+    pub require_verified_email: bool, // checkout only for customers who confirmed their email
+    pub tax_display: TaxDisplay,      // catalog prices with or without tax
+    pub tax_country: Option<String>,  // whose tax guests see in the catalog
+    pub trusted_proxies: Vec<IpAddr>, // peers whose X-Forwarded-For is believed
+    pub locale: String,               // amounts outside templates, in invoices
+    pub invoice_issuer: Vec<String>,  // the shop's address block on invoices
 }
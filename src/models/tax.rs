@@ -0,0 +1,58 @@
+use crate::models::money::Money;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::HashMap;
+
+// catalog prices with or without the customer's tax (PRICE_DISPLAY); orders always charge the
+// net price plus tax, so both add up to the same total
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaxDisplay {
+    Inclusive,
+    Exclusive,
+}
+
+impl TaxDisplay {
+    pub fn parse(value: &str) -> Option<TaxDisplay> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "inclusive" => Some(TaxDisplay::Inclusive),
+            "exclusive" => Some(TaxDisplay::Exclusive),
+            _ => None,
+        }
+    }
+}
+
+// the tax of one country, with the rates of the products at hand resolved up front
+#[derive(Debug, Clone, Default)]
+pub struct TaxRates {
+    pub(crate) country: Option<String>, // None when we have no rates for the country
+    pub(crate) name: String,            // "VAT"
+    pub(crate) standard_rate: Decimal, // percent, for shipping and products without a category rate
+    pub(crate) product_rates: HashMap<i32, Decimal>,
+}
+
+impl TaxRates {
+    pub fn rate_for(&self, product_id: i32) -> Decimal {
+        self.product_rates
+            .get(&product_id)
+            .copied()
+            .unwrap_or(self.standard_rate)
+    }
+
+    // net price plus tax
+    pub fn gross(&self, product_id: i32, price: Money) -> Money {
+        price.plus_percent(self.rate_for(product_id))
+    }
+
+    // shown next to catalog prices, None where no tax is charged
+    pub fn price_note(&self, display: TaxDisplay) -> Option<String> {
+        self.country.as_ref()?;
+        if self.standard_rate.is_zero() && self.product_rates.values().all(Decimal::is_zero) {
+            return None;
+        }
+        Some(match display {
+            TaxDisplay::Inclusive => format!("incl. {}", self.name),
+            TaxDisplay::Exclusive => format!("plus {}", self.name),
+        })
+    }
+}
@@ -0,0 +1,219 @@
+use crate::models::address::{Address, NewAddress};
+use sqlx::{Error, PgPool, Postgres, Transaction};
+
+pub struct AddressRepository;
+
+impl AddressRepository {
+    pub async fn list_addresses(pool: &PgPool, customer_id: i64) -> Result<Vec<Address>, Error> {
+        let addresses = sqlx::query_as!(
+            Address,
+            "SELECT id, label, full_name, line1, line2, city, postal_code, country, phone,
+                    is_default_shipping, is_default_billing
+             FROM addresses
+             WHERE customer_id = $1
+             ORDER BY is_default_shipping DESC, is_default_billing DESC, label, id",
+            customer_id
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(addresses)
+    }
+
+    // customer_id in the filter keeps other customers' addresses invisible (RowNotFound)
+    pub async fn get_address(
+        pool: &PgPool,
+        address_id: i64,
+        customer_id: i64,
+    ) -> Result<Address, Error> {
+        let address = sqlx::query_as!(
+            Address,
+            "SELECT id, label, full_name, line1, line2, city, postal_code, country, phone,
+                    is_default_shipping, is_default_billing
+             FROM addresses
+             WHERE id = $1 AND customer_id = $2",
+            address_id,
+            customer_id
+        )
+        .fetch_one(pool)
+        .await?;
+        Ok(address)
+    }
+
+    pub async fn create_address(
+        pool: &PgPool,
+        customer_id: i64,
+        address: &NewAddress,
+    ) -> Result<i64, Error> {
+        let mut tx = pool.begin().await?;
+
+        // the first address becomes the default for both
+        let existing = sqlx::query!(
+            r#"SELECT COUNT(*) AS "count!" FROM addresses WHERE customer_id = $1"#,
+            customer_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        let is_first = existing.count == 0;
+        let is_default_shipping = address.is_default_shipping || is_first;
+        let is_default_billing = address.is_default_billing || is_first;
+        Self::clear_defaults(
+            &mut tx,
+            customer_id,
+            is_default_shipping,
+            is_default_billing,
+        )
+        .await?;
+
+        let result = sqlx::query!(
+            "INSERT INTO addresses (
+                           customer_id,
+                           label,
+                           full_name,
+                           line1,
+                           line2,
+                           city,
+                           postal_code,
+                           country,
+                           phone,
+                           is_default_shipping,
+                           is_default_billing
+                   ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11) RETURNING id",
+            customer_id,
+            address.label,
+            address.full_name,
+            address.line1,
+            address.line2,
+            address.city,
+            address.postal_code,
+            address.country,
+            address.phone,
+            is_default_shipping,
+            is_default_billing
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(result.id)
+    }
+
+    // unticking a default flag keeps it, a customer with addresses always has a default
+    pub async fn update_address(
+        pool: &PgPool,
+        address_id: i64,
+        customer_id: i64,
+        address: &NewAddress,
+    ) -> Result<(), Error> {
+        let mut tx = pool.begin().await?;
+
+        // RowNotFound for somebody else's address
+        sqlx::query!(
+            "SELECT id FROM addresses WHERE id = $1 AND customer_id = $2 FOR UPDATE",
+            address_id,
+            customer_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        Self::clear_defaults(
+            &mut tx,
+            customer_id,
+            address.is_default_shipping,
+            address.is_default_billing,
+        )
+        .await?;
+
+        sqlx::query!(
+            "UPDATE addresses SET
+                    label = $3,
+                    full_name = $4,
+                    line1 = $5,
+                    line2 = $6,
+                    city = $7,
+                    postal_code = $8,
+                    country = $9,
+                    phone = $10,
+                    is_default_shipping = is_default_shipping OR $11,
+                    is_default_billing = is_default_billing OR $12,
+                    updated_at = NOW()
+             WHERE id = $1 AND customer_id = $2",
+            address_id,
+            customer_id,
+            address.label,
+            address.full_name,
+            address.line1,
+            address.line2,
+            address.city,
+            address.postal_code,
+            address.country,
+            address.phone,
+            address.is_default_shipping,
+            address.is_default_billing
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    // a deleted default is handed over to the most recently added remaining address
+    pub async fn delete_address(
+        pool: &PgPool,
+        address_id: i64,
+        customer_id: i64,
+    ) -> Result<(), Error> {
+        let mut tx = pool.begin().await?;
+
+        let deleted = sqlx::query!(
+            "DELETE FROM addresses WHERE id = $1 AND customer_id = $2
+             RETURNING is_default_shipping, is_default_billing",
+            address_id,
+            customer_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        if deleted.is_default_shipping {
+            sqlx::query!(
+                "UPDATE addresses SET is_default_shipping = TRUE, updated_at = NOW()
+                 WHERE id = (SELECT id FROM addresses WHERE customer_id = $1 ORDER BY id DESC LIMIT 1)",
+                customer_id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        if deleted.is_default_billing {
+            sqlx::query!(
+                "UPDATE addresses SET is_default_billing = TRUE, updated_at = NOW()
+                 WHERE id = (SELECT id FROM addresses WHERE customer_id = $1 ORDER BY id DESC LIMIT 1)",
+                customer_id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn clear_defaults(
+        tx: &mut Transaction<'_, Postgres>,
+        customer_id: i64,
+        shipping: bool,
+        billing: bool,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "UPDATE addresses SET
+                    is_default_shipping = is_default_shipping AND NOT $2,
+                    is_default_billing = is_default_billing AND NOT $3,
+                    updated_at = NOW()
+             WHERE customer_id = $1 AND ((is_default_shipping AND $2) OR (is_default_billing AND $3))",
+            customer_id,
+            shipping,
+            billing
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+}
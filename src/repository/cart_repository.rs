@@ -0,0 +1,178 @@
+use crate::models::cart::{Cart, CartItem};
+use crate::models::money::{BASE_CURRENCY, Money};
+use crate::models::order::OrderLine;
+use sqlx::types::Json;
+use sqlx::{Error, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+pub struct CartRepository;
+
+impl CartRepository {
+    pub async fn get_or_create_cart_id(pool: &PgPool, customer_id: i64) -> Result<Uuid, Error> {
+        // one cart per customer, upsert keeps it stable between requests
+        let result = sqlx::query!(
+            "INSERT INTO carts (customer_id) VALUES ($1)
+             ON CONFLICT (customer_id) DO UPDATE SET updated_at = NOW()
+             RETURNING id",
+            customer_id
+        )
+        .fetch_one(pool)
+        .await?;
+        Ok(result.id)
+    }
+
+    pub async fn get_cart(pool: &PgPool, customer_id: i64) -> Result<Cart, Error> {
+        let cart_id = Self::get_or_create_cart_id(pool, customer_id).await?;
+
+        let rows = sqlx::query!(
+            r#"
+select
+    p.id as product_id,
+    p.name as product_name,
+    p.code as "product_code?",
+    v.id as variant_id,
+    v.sku,
+    v.size,
+    v.colour,
+    coalesce(v.price, p.price) as "product_price!: Money",
+    p.images as "images: Json<Vec<String>>",
+    ci.quantity,
+    v.stock
+from cart_items ci
+     join product_variants v on v.id = ci.variant_id
+     join products p on p.id = v.product_id
+where ci.cart_id = $1
+order by ci.created_at, ci.id;"#,
+            cart_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let items = rows
+            .into_iter()
+            .map(|row| CartItem {
+                product_id: row.product_id,
+                product_name: row.product_name,
+                product_code: row.product_code.unwrap_or_default(),
+                variant_id: row.variant_id,
+                sku: row.sku,
+                size: row.size,
+                colour: row.colour,
+                product_price: row.product_price,
+                images: row.images.unwrap_or_else(|| Json(vec![])),
+                quantity: row.quantity,
+                line_total: row.product_price.times(row.quantity),
+                stock: row.stock,
+            })
+            .collect();
+
+        Ok(Cart {
+            id: cart_id.to_string(),
+            customer_id,
+            currency: BASE_CURRENCY,
+            items,
+        })
+    }
+
+    // adds to the line already in the cart; false, and nothing changes, when the summed quantity
+    // would go over `max_quantity`
+    pub async fn add_item(
+        pool: &PgPool,
+        customer_id: i64,
+        product_id: i32,
+        variant_id: i32,
+        quantity: i32,
+        max_quantity: i32,
+    ) -> Result<bool, Error> {
+        let cart_id = Self::get_or_create_cart_id(pool, customer_id).await?;
+        let added = sqlx::query_scalar!(
+            "INSERT INTO cart_items (cart_id, product_id, variant_id, quantity) VALUES ($1, $2, $3, $4)
+             ON CONFLICT (cart_id, variant_id)
+             DO UPDATE SET quantity = cart_items.quantity + EXCLUDED.quantity, updated_at = NOW()
+             WHERE cart_items.quantity + EXCLUDED.quantity <= $5
+             RETURNING quantity",
+            cart_id,
+            product_id,
+            variant_id,
+            quantity,
+            max_quantity
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(added.is_some())
+    }
+
+    pub async fn update_item_quantity(
+        pool: &PgPool,
+        customer_id: i64,
+        variant_id: i32,
+        quantity: i32,
+    ) -> Result<(), Error> {
+        if quantity < 1 {
+            return Self::remove_item(pool, customer_id, variant_id).await;
+        }
+        sqlx::query!(
+            "UPDATE cart_items ci SET quantity = $3, updated_at = NOW()
+             FROM carts c
+             WHERE c.id = ci.cart_id AND c.customer_id = $1 AND ci.variant_id = $2",
+            customer_id,
+            variant_id,
+            quantity
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn remove_item(
+        pool: &PgPool,
+        customer_id: i64,
+        variant_id: i32,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "DELETE FROM cart_items ci
+             USING carts c
+             WHERE c.id = ci.cart_id AND c.customer_id = $1 AND ci.variant_id = $2",
+            customer_id,
+            variant_id
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    // takes what was ordered out of the cart in the checkout transaction; items added in another
+    // tab after checkout started, or more of the same, stay in it
+    pub async fn remove_ordered(
+        tx: &mut Transaction<'_, Postgres>,
+        customer_id: i64,
+        lines: &[OrderLine],
+    ) -> Result<(), Error> {
+        let (variant_ids, quantities): (Vec<i32>, Vec<i32>) = lines
+            .iter()
+            .filter_map(|line| Some((line.variant_id?, line.quantity)))
+            .unzip();
+        sqlx::query!(
+            "DELETE FROM cart_items ci
+             USING carts c, unnest($2::int[], $3::int[]) AS o(variant_id, quantity)
+             WHERE c.id = ci.cart_id AND c.customer_id = $1
+               AND ci.variant_id = o.variant_id AND ci.quantity <= o.quantity",
+            customer_id,
+            &variant_ids,
+            &quantities
+        )
+        .execute(&mut **tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE cart_items ci SET quantity = ci.quantity - o.quantity, updated_at = NOW()
+             FROM carts c, unnest($2::int[], $3::int[]) AS o(variant_id, quantity)
+             WHERE c.id = ci.cart_id AND c.customer_id = $1 AND ci.variant_id = o.variant_id",
+            customer_id,
+            &variant_ids,
+            &quantities
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+}
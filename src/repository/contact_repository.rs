@@ -0,0 +1,24 @@
+use sqlx::{Error, PgPool};
+
+pub struct ContactRepository;
+
+impl ContactRepository {
+    pub async fn create_message(
+        pool: &PgPool,
+        customer_id: Option<i64>,
+        name: &str,
+        email: &str,
+        message: &str,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "INSERT INTO contact_messages (customer_id, name, email, message) VALUES ($1, $2, $3, $4)",
+            customer_id,
+            name,
+            email,
+            message
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
@@ -1,11 +1,20 @@
-use crate::models::customer::{NewCustomer, ProfileCustomer};
+use crate::models::customer::{NewCustomer, ProfileCustomer, ProfileUpdate};
 use bcrypt::{DEFAULT_COST, hash, verify};
 use chrono::{NaiveDate, NaiveTime};
 use serde::Serialize;
 use sqlx::PgPool;
 
+// Postgres SQLSTATE for unique_violation
+const UNIQUE_VIOLATION: &str = "23505";
+const EMAIL_UNIQUE_CONSTRAINT: &str = "customers_pk";
+
 #[derive(Debug, Serialize)]
 pub enum CustomerError {
+    DuplicateEmail,
+    NotFound,
+    InvalidCredentials,
+    PasswordMismatch,
+    ConnectionFailure,
     Database,
     HashingError,
     MissingData(String),
@@ -14,6 +23,11 @@ pub enum CustomerError {
 impl std::fmt::Display for CustomerError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            CustomerError::DuplicateEmail => write!(f, "Customer with this email already exists"),
+            CustomerError::NotFound => write!(f, "Customer not found"),
+            CustomerError::InvalidCredentials => write!(f, "Invalid email or password"),
+            CustomerError::PasswordMismatch => write!(f, "Passwords do not match"),
+            CustomerError::ConnectionFailure => write!(f, "Database connection failed"),
             CustomerError::Database => write!(f, "Database error occurred"),
             CustomerError::HashingError => write!(f, "Password hashing failed"),
             CustomerError::MissingData(field) => write!(f, "Missing required field: {}", field),
@@ -24,8 +38,27 @@ impl std::fmt::Display for CustomerError {
 impl std::error::Error for CustomerError {}
 
 impl From<sqlx::Error> for CustomerError {
-    fn from(_e: sqlx::Error) -> Self {
-        CustomerError::Database
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::RowNotFound => CustomerError::NotFound,
+            sqlx::Error::Database(db_error)
+                if db_error.code().as_deref() == Some(UNIQUE_VIOLATION)
+                    && db_error.constraint() == Some(EMAIL_UNIQUE_CONSTRAINT) =>
+            {
+                CustomerError::DuplicateEmail
+            }
+            sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_)
+            | sqlx::Error::PoolTimedOut
+            | sqlx::Error::PoolClosed => {
+                tracing::error!("Customer database connection failure: {:?}", e);
+                CustomerError::ConnectionFailure
+            }
+            e => {
+                tracing::error!("Customer database error: {:?}", e);
+                CustomerError::Database
+            }
+        }
     }
 }
 
@@ -37,7 +70,9 @@ impl CustomerRepository {
         customer_id: i64,
     ) -> Result<ProfileCustomer, CustomerError> {
         let customer = sqlx::query!(
-            "SELECT id, email, first_name, last_name, date_birth, phone, city, country FROM customers WHERE id = $1", // is_enabled/is_deleted/ or something
+            r#"SELECT id, email, first_name, last_name, date_birth, phone, city, country,
+                email_verified_at IS NOT NULL AS "is_email_verified!", is_staff
+               FROM customers WHERE id = $1"#, // is_enabled/is_deleted/ or something
             customer_id
         )
         .fetch_one(pool)
@@ -60,15 +95,37 @@ impl CustomerRepository {
             city: customer
                 .city
                 .ok_or_else(|| CustomerError::MissingData("city is required".to_string()))?,
-            country: customer
-                .country
-                .ok_or_else(|| CustomerError::MissingData("country is required".to_string()))?,
+            // NULL where the old free text could not be read as a country, the profile asks again
+            country: customer.country.unwrap_or_default(),
+            is_email_verified: customer.is_email_verified,
+            is_staff: customer.is_staff,
         })
     }
 
+    pub async fn find_customer_id_by_email(
+        pool: &PgPool,
+        email: &str,
+    ) -> Result<Option<i64>, CustomerError> {
+        let result = sqlx::query!("SELECT id FROM customers WHERE email = $1", email)
+            .fetch_optional(pool)
+            .await?;
+        Ok(result.map(|row| row.id))
+    }
+
+    // false when no customer has that email
+    pub async fn grant_staff(pool: &PgPool, email: &str) -> Result<bool, CustomerError> {
+        let result = sqlx::query!(
+            "UPDATE customers SET is_staff = true, updated_at = NOW() WHERE email = $1",
+            email
+        )
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
     pub async fn create_customer(
         pool: &PgPool,
-        new_customer: NewCustomer,
+        new_customer: &NewCustomer,
     ) -> Result<i64, CustomerError> {
         let hashed_pwd =
             hash(&new_customer.password, DEFAULT_COST).map_err(|_| CustomerError::HashingError)?;
@@ -102,6 +159,73 @@ impl CustomerRepository {
         Ok(result.id)
     }
 
+    pub async fn update_profile(
+        pool: &PgPool,
+        customer_id: i64,
+        update: &ProfileUpdate,
+    ) -> Result<(), CustomerError> {
+        let date_time = update
+            .date_birth
+            .and_time(NaiveTime::from_hms_opt(0, 0, 0).unwrap());
+
+        let result = sqlx::query!(
+            "UPDATE customers SET
+                    first_name = $2,
+                    last_name = $3,
+                    date_birth = $4,
+                    phone = $5,
+                    city = $6,
+                    country = $7,
+                    updated_at = NOW()
+             WHERE id = $1",
+            customer_id,
+            update.first_name,
+            update.last_name,
+            date_time,
+            update.phone,
+            update.city,
+            update.country
+        )
+        .execute(pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(CustomerError::NotFound);
+        }
+        Ok(())
+    }
+
+    pub async fn check_password(
+        pool: &PgPool,
+        customer_id: i64,
+        password: &str,
+    ) -> Result<(), CustomerError> {
+        let customer = sqlx::query!("SELECT password FROM customers WHERE id = $1", customer_id)
+            .fetch_one(pool)
+            .await?;
+        let stored_password = customer.password.ok_or(CustomerError::InvalidCredentials)?;
+        if !verify(password, &stored_password).map_err(|_| CustomerError::HashingError)? {
+            return Err(CustomerError::InvalidCredentials);
+        }
+        Ok(())
+    }
+
+    pub async fn update_password(
+        pool: &PgPool,
+        customer_id: i64,
+        new_password: &str,
+    ) -> Result<(), CustomerError> {
+        let hashed_pwd =
+            hash(new_password, DEFAULT_COST).map_err(|_| CustomerError::HashingError)?;
+        sqlx::query!(
+            "UPDATE customers SET password = $2, updated_at = NOW() WHERE id = $1",
+            customer_id,
+            hashed_pwd
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
     pub async fn get_user_by_email_password(
         pool: &PgPool,
         email: String,
@@ -109,22 +233,18 @@ impl CustomerRepository {
         confirm_password: String,
     ) -> Result<ProfileCustomer, CustomerError> {
         if password != confirm_password {
-            return Err(CustomerError::MissingData(
-                "Passwords do not match".to_string(),
-            ));
+            return Err(CustomerError::PasswordMismatch);
         }
 
-        let customer = sqlx::query!("SELECT id, email, first_name, last_name, date_birth, phone, city, country, password FROM customers WHERE email = $1", email).fetch_one(pool).await?;
+        let customer = sqlx::query!(r#"SELECT id, email, first_name, last_name, date_birth, phone, city, country, password, email_verified_at IS NOT NULL AS "is_email_verified!", is_staff FROM customers WHERE email = $1"#, email).fetch_one(pool).await?;
 
-        let stored_password = customer
-            .password
-            .ok_or_else(|| CustomerError::MissingData("Password is required".to_string()))?;
+        let stored_password = customer.password.ok_or(CustomerError::InvalidCredentials)?;
 
         let is_same_pwd =
             verify(&password, &stored_password).map_err(|_| CustomerError::HashingError)?;
 
         if !is_same_pwd {
-            return Err(CustomerError::MissingData("Invalid password".to_string()));
+            return Err(CustomerError::InvalidCredentials);
         }
 
         Ok(ProfileCustomer {
@@ -144,9 +264,9 @@ impl CustomerRepository {
             city: customer
                 .city
                 .ok_or_else(|| CustomerError::MissingData("city is required".to_string()))?,
-            country: customer
-                .country
-                .ok_or_else(|| CustomerError::MissingData("country is required".to_string()))?,
+            country: customer.country.unwrap_or_default(),
+            is_email_verified: customer.is_email_verified,
+            is_staff: customer.is_staff,
         })
     }
 }
@@ -0,0 +1,96 @@
+use crate::repository::customer_repository::CustomerError;
+use crate::services::auth::AuthService;
+use sqlx::PgPool;
+
+const VERIFICATION_TOKEN_TTL_SECONDS: f64 = 48.0 * 3600.0;
+
+pub struct EmailVerificationRepository;
+
+impl EmailVerificationRepository {
+    pub async fn create_token(
+        pool: &PgPool,
+        customer_id: i64,
+        email: &str,
+    ) -> Result<String, CustomerError> {
+        let token = AuthService::generate_token();
+        sqlx::query!(
+            "INSERT INTO email_verification_tokens (customer_id, email, token_hash, expires_at)
+             VALUES ($1, $2, $3, NOW() + make_interval(secs => $4))",
+            customer_id,
+            email,
+            AuthService::hash_token(&token),
+            VERIFICATION_TOKEN_TTL_SECONDS
+        )
+        .execute(pool)
+        .await?;
+        Ok(token)
+    }
+
+    // the address waiting for confirmation when it differs from the current one
+    pub async fn pending_email_change(
+        pool: &PgPool,
+        customer_id: i64,
+        current_email: &str,
+    ) -> Result<Option<String>, CustomerError> {
+        let result = sqlx::query!(
+            "SELECT email FROM email_verification_tokens
+             WHERE customer_id = $1 AND email <> $2 AND used_at IS NULL AND expires_at > NOW()
+             ORDER BY created_at DESC
+             LIMIT 1",
+            customer_id,
+            current_email
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(result.map(|row| row.email))
+    }
+
+    // links sent earlier (to the old address or another requested one) stop working
+    pub async fn invalidate_tokens(pool: &PgPool, customer_id: i64) -> Result<(), CustomerError> {
+        sqlx::query!(
+            "UPDATE email_verification_tokens SET used_at = NOW()
+             WHERE customer_id = $1 AND used_at IS NULL",
+            customer_id
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    // consumes the token and makes the address it was sent to the customer's verified email,
+    // for a registration that's the current email, for an email change it's the new one
+    pub async fn verify_email(pool: &PgPool, token: &str) -> Result<i64, CustomerError> {
+        let mut tx = pool.begin().await?;
+
+        // RowNotFound (used, expired or unknown token) -> CustomerError::NotFound
+        let consumed = sqlx::query!(
+            "UPDATE email_verification_tokens SET used_at = NOW()
+             WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+             RETURNING customer_id, email",
+            AuthService::hash_token(token)
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        // unique violation -> CustomerError::DuplicateEmail, somebody registered the address meanwhile
+        sqlx::query!(
+            "UPDATE customers SET email = $2, email_verified_at = NOW(), updated_at = NOW()
+             WHERE id = $1",
+            consumed.customer_id,
+            consumed.email
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "UPDATE email_verification_tokens SET used_at = NOW()
+             WHERE customer_id = $1 AND used_at IS NULL",
+            consumed.customer_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(consumed.customer_id)
+    }
+}
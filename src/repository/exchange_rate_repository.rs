@@ -0,0 +1,40 @@
+use crate::models::money::{Currency, ExchangeRate};
+use sqlx::{Error, PgPool};
+
+pub struct ExchangeRateRepository;
+
+impl ExchangeRateRepository {
+    pub async fn list_rates(pool: &PgPool) -> Result<Vec<ExchangeRate>, Error> {
+        let rates = sqlx::query_as!(
+            ExchangeRate,
+            r#"select currency as "currency: Currency", rate from exchange_rates order by currency"#
+        )
+        .fetch_all(pool)
+        .await?;
+        // NUMERIC(18, 8) comes back as 0.92150000, shown as 0.9215
+        Ok(rates
+            .into_iter()
+            .map(|rate| ExchangeRate {
+                rate: rate.rate.normalize(),
+                ..rate
+            })
+            .collect())
+    }
+
+    // replaces the rates of the given currencies, the others stay as they are
+    pub async fn import_rates(pool: &PgPool, rates: &[ExchangeRate]) -> Result<(), Error> {
+        let mut tx = pool.begin().await?;
+        for rate in rates {
+            sqlx::query!(
+                "INSERT INTO exchange_rates (currency, rate) VALUES ($1, $2)
+                 ON CONFLICT (currency) DO UPDATE SET rate = EXCLUDED.rate, updated_at = NOW()",
+                rate.currency as Currency,
+                rate.rate
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,183 @@
+use crate::models::inventory::{StockAdjustment, StockChange, StockMovementReason};
+use sqlx::{Error, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+pub struct InventoryRepository;
+
+impl InventoryRepository {
+    // takes `quantity` off the shelf for an order, false when there is not enough left
+    pub async fn reserve(
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+        variant_id: i32,
+        quantity: i32,
+    ) -> Result<bool, Error> {
+        // the conditional update locks the row, concurrent checkouts can't oversell
+        let reserved = sqlx::query!(
+            "UPDATE product_variants SET stock = stock - $2, updated_at = NOW()
+             WHERE id = $1 AND is_active = true AND stock >= $2
+             RETURNING id",
+            variant_id,
+            quantity
+        )
+        .fetch_optional(&mut **tx)
+        .await?;
+        if reserved.is_none() {
+            return Ok(false);
+        }
+
+        Self::record_movement(
+            tx,
+            variant_id,
+            -quantity,
+            StockMovementReason::Reservation,
+            Some(order_id),
+            None,
+        )
+        .await?;
+        Ok(true)
+    }
+
+    // puts back whatever the order still holds, running it twice releases nothing the second time
+    pub async fn release_order(
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+    ) -> Result<(), Error> {
+        let outstanding = sqlx::query!(
+            r#"
+select
+    variant_id,
+    (-sum(quantity))::int as "quantity!"
+from stock_movements
+where order_id = $1 and reason in ('reservation', 'release')
+group by variant_id
+having sum(quantity) < 0
+order by variant_id;"#,
+            order_id
+        )
+        .fetch_all(&mut **tx)
+        .await?;
+
+        for row in outstanding {
+            sqlx::query!(
+                "UPDATE product_variants SET stock = stock + $2, updated_at = NOW() WHERE id = $1",
+                row.variant_id,
+                row.quantity
+            )
+            .execute(&mut **tx)
+            .await?;
+            Self::record_movement(
+                tx,
+                row.variant_id,
+                row.quantity,
+                StockMovementReason::Release,
+                Some(order_id),
+                None,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    // returned goods that can be sold again
+    pub async fn restock_return(
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+        variant_id: i32,
+        quantity: i32,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "UPDATE product_variants SET stock = stock + $2, updated_at = NOW() WHERE id = $1",
+            variant_id,
+            quantity
+        )
+        .execute(&mut **tx)
+        .await?;
+        Self::record_movement(
+            tx,
+            variant_id,
+            quantity,
+            StockMovementReason::Return,
+            Some(order_id),
+            None,
+        )
+        .await
+    }
+
+    // deliveries and stock takes, the new count and its movement commit together
+    pub async fn adjust_stock(
+        pool: &PgPool,
+        sku: &str,
+        change: StockChange,
+        note: &str,
+    ) -> Result<StockAdjustment, Error> {
+        let mut tx = pool.begin().await?;
+        // locked so a checkout can't reserve between reading the count and writing the new one
+        let Some(variant) = sqlx::query!(
+            "SELECT id, stock FROM product_variants WHERE sku = $1 FOR UPDATE",
+            sku
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(StockAdjustment::UnknownSku);
+        };
+
+        let quantity = match change {
+            StockChange::By(quantity) => quantity,
+            StockChange::To(count) => count - variant.stock,
+        };
+        let Some(stock) = variant
+            .stock
+            .checked_add(quantity)
+            .filter(|stock| *stock >= 0)
+        else {
+            return Ok(StockAdjustment::Insufficient {
+                stock: variant.stock,
+            });
+        };
+        if quantity == 0 {
+            return Ok(StockAdjustment::Adjusted { stock });
+        }
+
+        sqlx::query!(
+            "UPDATE product_variants SET stock = $2, updated_at = NOW() WHERE id = $1",
+            variant.id,
+            stock
+        )
+        .execute(&mut *tx)
+        .await?;
+        Self::record_movement(
+            &mut tx,
+            variant.id,
+            quantity,
+            StockMovementReason::Adjustment,
+            None,
+            Some(note),
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(StockAdjustment::Adjusted { stock })
+    }
+
+    async fn record_movement(
+        tx: &mut Transaction<'_, Postgres>,
+        variant_id: i32,
+        quantity: i32,
+        reason: StockMovementReason,
+        order_id: Option<Uuid>,
+        note: Option<&str>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "INSERT INTO stock_movements (variant_id, quantity, reason, order_id, note) VALUES ($1, $2, $3, $4, $5)",
+            variant_id,
+            quantity,
+            reason as StockMovementReason,
+            order_id,
+            note
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+use crate::models::invoice::Invoice;
+use sqlx::{Error, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+pub struct InvoiceRepository;
+
+impl InvoiceRepository {
+    pub async fn find_for_order(pool: &PgPool, order_id: Uuid) -> Result<Option<Invoice>, Error> {
+        let invoice = sqlx::query_as!(
+            Invoice,
+            r#"SELECT id, number, order_id::text as "order_id!", issued_at FROM invoices WHERE order_id = $1"#,
+            order_id
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(invoice)
+    }
+
+    // the order row must be locked by the caller, so the check and the insert can't race; the
+    // counter row is locked until the transaction ends, numbers are handed out in commit order
+    pub async fn issue(
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+    ) -> Result<Invoice, Error> {
+        let existing = sqlx::query_as!(
+            Invoice,
+            r#"SELECT id, number, order_id::text as "order_id!", issued_at FROM invoices WHERE order_id = $1"#,
+            order_id
+        )
+        .fetch_optional(&mut **tx)
+        .await?;
+        if let Some(invoice) = existing {
+            return Ok(invoice);
+        }
+
+        let (year, number) = sqlx::query!(
+            r#"
+INSERT INTO invoice_counters (year, last_number)
+VALUES (extract(year from NOW())::integer, 1)
+ON CONFLICT (year) DO UPDATE SET last_number = invoice_counters.last_number + 1
+RETURNING year, last_number;"#
+        )
+        .fetch_one(&mut **tx)
+        .await
+        .map(|row| (row.year, row.last_number))?;
+        let invoice = sqlx::query_as!(
+            Invoice,
+            r#"
+INSERT INTO invoices (number, order_id)
+VALUES ($1, $2)
+RETURNING id, number, order_id::text as "order_id!", issued_at;"#,
+            format!("{}-{:06}", year, number),
+            order_id
+        )
+        .fetch_one(&mut **tx)
+        .await?;
+        Ok(invoice)
+    }
+}
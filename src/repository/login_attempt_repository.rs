@@ -0,0 +1,80 @@
+use crate::models::session::{LoginOutcome, RecentFailures};
+use sqlx::{Error, PgPool};
+
+pub struct LoginAttemptRepository;
+
+impl LoginAttemptRepository {
+    pub async fn record_attempt(
+        pool: &PgPool,
+        email: &str,
+        ip_address: Option<&str>,
+        user_agent: Option<&str>,
+        customer_id: Option<i64>,
+        outcome: LoginOutcome,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "INSERT INTO login_attempts (email, ip_address, user_agent, customer_id, outcome)
+             VALUES ($1, $2, $3, $4, $5)",
+            email,
+            ip_address,
+            user_agent,
+            customer_id,
+            outcome as LoginOutcome
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    // a successful login wipes the slate for the email
+    pub async fn recent_email_failures(
+        pool: &PgPool,
+        email: &str,
+        window_seconds: f64,
+    ) -> Result<RecentFailures, Error> {
+        let row = sqlx::query!(
+            r#"SELECT COUNT(*) AS "count!",
+                      EXTRACT(EPOCH FROM NOW() - MAX(created_at))::float8 AS seconds_since_last
+               FROM login_attempts
+               WHERE email = $1
+                 AND outcome = 'invalid_credentials'
+                 AND created_at > NOW() - make_interval(secs => $2)
+                 AND created_at > COALESCE(
+                     (SELECT MAX(created_at) FROM login_attempts WHERE email = $1 AND outcome = 'success'),
+                     '-infinity'
+                 )"#,
+            email,
+            window_seconds
+        )
+        .fetch_one(pool)
+        .await?;
+        Ok(RecentFailures {
+            count: row.count,
+            seconds_since_last: row.seconds_since_last,
+        })
+    }
+
+    // not reset by a success, otherwise one valid account would unlock guessing others
+    pub async fn recent_ip_failures(
+        pool: &PgPool,
+        ip_address: &str,
+        window_seconds: f64,
+    ) -> Result<RecentFailures, Error> {
+        let row = sqlx::query!(
+            r#"SELECT COUNT(*) AS "count!",
+                      EXTRACT(EPOCH FROM NOW() - MAX(created_at))::float8 AS seconds_since_last
+               FROM login_attempts
+               WHERE ip_address = $1
+                 AND outcome = 'invalid_credentials'
+                 AND created_at > NOW() - make_interval(secs => $2)"#,
+            ip_address,
+            window_seconds
+        )
+        .fetch_one(pool)
+        .await?;
+        Ok(RecentFailures {
+            count: row.count,
+            seconds_since_last: row.seconds_since_last,
+        })
+    }
+}
@@ -1,3 +1,18 @@
 pub mod product_repository;
 pub mod customer_repository;
 pub mod order_repository;
+pub mod cart_repository;
+pub mod session_repository;
+pub mod password_reset_repository;
+pub mod email_verification_repository;
+pub mod login_attempt_repository;
+pub mod contact_repository;
+pub mod address_repository;
+pub mod inventory_repository;
+pub mod exchange_rate_repository;
+pub mod promotion_repository;
+pub mod shipping_repository;
+pub mod payment_repository;
+pub mod return_repository;
+pub mod tax_repository;
+pub mod invoice_repository;
@@ -1,9 +1,75 @@
-use crate::models::order::CreatedOrder;
+use crate::models::address::AddressSnapshot;
+use crate::models::cart::CartItem;
+use crate::models::money::{Currency, CurrencyMismatch, ExchangeRate, Money};
+use crate::models::order::{
+    CreatedOrder, FulfilmentLine, FulfilmentOrder, OrderActor, OrderDetails, OrderLine,
+    OrderStatus, OrderStatusChange,
+};
 use crate::models::products::OrderProductInfo;
-use sqlx::{Error, PgPool};
+use crate::models::promotion::AppliedPromotion;
+use crate::models::shipping::ShippingRate;
+use crate::repository::inventory_repository::InventoryRepository;
+use crate::repository::promotion_repository::PromotionRepository;
+use sqlx::types::Json;
+use sqlx::{Error, PgPool, Postgres, Transaction};
 use std::collections::HashMap;
 use uuid::Uuid;
 
+#[derive(Debug)]
+pub enum OrderError {
+    Database(Error),
+    NotFound,
+    IllegalTransition {
+        from: OrderStatus,
+        to: OrderStatus,
+    },
+    OutOfStock {
+        product_name: String,
+        size: String,
+        colour: String,
+    },
+    Currency(CurrencyMismatch),
+}
+
+impl std::fmt::Display for OrderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrderError::Database(e) => write!(f, "Database error occurred: {}", e),
+            OrderError::NotFound => write!(f, "Order not found"),
+            OrderError::IllegalTransition { from, to } => {
+                write!(f, "Order can not be moved from {} to {}", from, to)
+            }
+            OrderError::OutOfStock {
+                product_name,
+                size,
+                colour,
+            } => write!(
+                f,
+                "Sorry, {} in size {}, {} is sold out or not available in that quantity anymore",
+                product_name, size, colour
+            ),
+            OrderError::Currency(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for OrderError {}
+
+impl From<Error> for OrderError {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::RowNotFound => OrderError::NotFound,
+            e => OrderError::Database(e),
+        }
+    }
+}
+
+impl From<CurrencyMismatch> for OrderError {
+    fn from(e: CurrencyMismatch) -> Self {
+        OrderError::Currency(e)
+    }
+}
+
 pub struct OrderRepository;
 
 impl OrderRepository {
@@ -12,18 +78,27 @@ impl OrderRepository {
         customer_id: i64,
     ) -> Result<HashMap<String, Vec<OrderProductInfo>>, Error> {
         let result = sqlx::query!(
-            "
+            r#"
 select
     o.id as order_id,
     p.id as product_id,
-    op.sum::int as product_price,
     concat(p.name, ' (', p.code, ')')::varchar as product_name,
     p.code as product_code,
-    o.status as order_status
+    v.size as "size?",
+    v.colour as "colour?",
+    op.quantity,
+    op.unit_price as "unit_price: Money",
+    op.line_total as "line_total: Money",
+    o.status as "order_status: OrderStatus",
+    o.discount as "order_discount: Money",
+    o.shipping_cost as "order_shipping_cost: Money",
+    o.tax as "order_tax: Money"
 from orders o
      join orders_product op on o.id = op.order_id
      join products p on p.id = op.product_id
-where o.customer_id = $1;",
+     left join product_variants v on v.id = op.variant_id
+where o.customer_id = $1 and o.status <> 'cart'
+order by o.created_at desc, op.id;"#,
             customer_id
         )
         .fetch_all(pool)
@@ -35,9 +110,16 @@ where o.customer_id = $1;",
                 order_id: order_id.clone(),
                 product_id: row.product_id,
                 product_code: row.product_code.expect("?"),
-                product_price: row.product_price.expect("?"),
                 product_name: row.product_name.expect("?"),
+                size: row.size,
+                colour: row.colour,
+                quantity: row.quantity,
+                unit_price: row.unit_price,
+                line_total: row.line_total,
                 order_status: row.order_status,
+                order_discount: row.order_discount,
+                order_shipping_cost: row.order_shipping_cost,
+                order_tax: row.order_tax,
             };
             if let Some(products) = result_map.get_mut(&order_id) {
                 products.push(product_info);
@@ -47,79 +129,553 @@ where o.customer_id = $1;",
         }
         Ok(result_map)
     }
-    pub async fn create_order(
+    pub async fn get_order_by_uuid_and_customer(
         pool: &PgPool,
+        order_id: Uuid,
         customer_id: i64,
-        product_id: i32,
-        quantity: i32,
-        sum: i32,
-    ) -> Result<Vec<CreatedOrder>, Error> {
-        let order_uuid = Self::create_order_uuid(pool, customer_id).await?;
-        if order_uuid.len() == 0 {
-            panic!("Order uuid is empty");
-        }
+    ) -> Result<OrderDetails, OrderError> {
+        // customer_id in the filter keeps other customers' orders invisible (RowNotFound -> NotFound)
+        let order = sqlx::query!(
+            r#"
+select
+    id,
+    status as "status: OrderStatus",
+    coalesce(is_confirmed, false) as "is_confirmed!",
+    created_at,
+    shipping_address as "shipping_address: Json<AddressSnapshot>",
+    billing_address as "billing_address: Json<AddressSnapshot>",
+    currency as "currency: Currency",
+    exchange_rate,
+    promotion_code,
+    discount as "discount: Money",
+    shipping_rate_id,
+    shipping_method,
+    shipping_cost as "shipping_cost: Money",
+    tax as "tax: Money",
+    tax_country,
+    shipping_tax as "shipping_tax: Money"
+from orders
+where id = $1 and customer_id = $2;"#,
+            order_id,
+            customer_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        let lines = sqlx::query!(
+            r#"
+select
+    op.id,
+    p.id as product_id,
+    p.name as product_name,
+    p.code as "product_code?",
+    v.id as "variant_id?",
+    v.sku as "sku?",
+    v.size as "size?",
+    v.colour as "colour?",
+    op.unit_price as "unit_price: Money",
+    op.quantity,
+    op.line_total as "line_total: Money",
+    op.tax_rate,
+    op.tax as "tax: Money"
+from orders_product op
+     join products p on p.id = op.product_id
+     left join product_variants v on v.id = op.variant_id
+where op.order_id = $1
+order by p.name, v.colour, v.size, op.id;"#,
+            order_id
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| OrderLine {
+            id: row.id,
+            product_id: row.product_id,
+            product_name: row.product_name,
+            product_code: row.product_code.unwrap_or_default(),
+            variant_id: row.variant_id,
+            sku: row.sku,
+            size: row.size,
+            colour: row.colour,
+            unit_price: row.unit_price,
+            quantity: row.quantity,
+            line_total: row.line_total,
+            tax_rate: row.tax_rate.map(|rate| rate.normalize()),
+            tax: row.tax,
+        })
+        .collect::<Vec<_>>();
+
+        let status_history = Self::get_status_history(pool, order_id).await?;
+        let subtotal = Money::sum(order.currency, lines.iter().map(|line| line.line_total))?;
 
-        let order_products = Self::create_order_products_with_order_uuid(
-            pool, order_uuid, product_id, quantity, sum,
+        let mut details = OrderDetails {
+            order_id: order.id.to_string(),
+            status: order.status,
+            is_confirmed: order.is_confirmed,
+            created_at: order.created_at,
+            shipping_address: order.shipping_address.map(|address| address.0),
+            billing_address: order.billing_address.map(|address| address.0),
+            currency: order.currency,
+            exchange_rate: order.exchange_rate.normalize(),
+            subtotal,
+            promotion_code: order.promotion_code,
+            discount: order.discount,
+            shipping_rate_id: order.shipping_rate_id,
+            shipping_method: order.shipping_method,
+            shipping_cost: order.shipping_cost,
+            tax: order.tax,
+            tax_country: order.tax_country,
+            shipping_tax: order.shipping_tax,
+            total: subtotal,
+            lines,
+            status_history,
+        };
+        details.update_total()?;
+        Ok(details)
+    }
+
+    pub async fn get_status_history(
+        pool: &PgPool,
+        order_id: Uuid,
+    ) -> Result<Vec<OrderStatusChange>, Error> {
+        let history = sqlx::query_as!(
+            OrderStatusChange,
+            r#"
+select
+    from_status as "from_status: OrderStatus",
+    to_status as "to_status: OrderStatus",
+    actor,
+    created_at
+from order_status_history
+where order_id = $1
+order by created_at, id;"#,
+            order_id
         )
+        .fetch_all(pool)
+        .await?;
+        Ok(history)
+    }
+
+    // oldest first, the order they should leave the warehouse in
+    pub async fn list_for_fulfilment(
+        pool: &PgPool,
+        status: OrderStatus,
+    ) -> Result<Vec<FulfilmentOrder>, Error> {
+        let rows = sqlx::query!(
+            r#"
+select
+    o.id,
+    c.email as customer_email,
+    o.status as "status: OrderStatus",
+    o.created_at,
+    o.shipping_address as "shipping_address: Json<AddressSnapshot>",
+    o.shipping_method
+from orders o
+     join customers c on c.id = o.customer_id
+where o.status = $1
+order by o.created_at, o.id
+limit 200;"#,
+            status as OrderStatus
+        )
+        .fetch_all(pool)
         .await?;
-        // order_products: [17, 17]
+        let order_ids: Vec<Uuid> = rows.iter().map(|row| row.id).collect();
+
+        let mut lines = sqlx::query!(
+            r#"
+select
+    op.order_id as "order_id!",
+    p.name as product_name,
+    v.sku as "sku?",
+    v.size as "size?",
+    v.colour as "colour?",
+    op.quantity
+from orders_product op
+     join products p on p.id = op.product_id
+     left join product_variants v on v.id = op.variant_id
+where op.order_id = any($1)
+order by p.name, v.colour, v.size, op.id;"#,
+            &order_ids
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .fold(
+            HashMap::<Uuid, Vec<FulfilmentLine>>::new(),
+            |mut lines, row| {
+                lines.entry(row.order_id).or_default().push(FulfilmentLine {
+                    product_name: row.product_name,
+                    sku: row.sku,
+                    size: row.size,
+                    colour: row.colour,
+                    quantity: row.quantity,
+                });
+                lines
+            },
+        );
 
-        // retrieve product info by product_ids
-        let mut result = Vec::with_capacity(order_products.len());
-        for _ in order_products {
-            result.push(CreatedOrder {
-                order_id: "".to_string(),
-                product_ids: vec![],
-                created_at: Default::default(),
+        Ok(rows
+            .into_iter()
+            .map(|row| FulfilmentOrder {
+                order_id: row.id.to_string(),
+                customer_email: row.customer_email,
+                status: row.status,
+                created_at: row.created_at,
+                shipping_address: row.shipping_address.map(|address| address.0),
+                shipping_method: row.shipping_method,
+                lines: lines.remove(&row.id).unwrap_or_default(),
             })
-        }
-        Ok(result)
+            .collect())
     }
-    pub async fn create_order_uuid(pool: &PgPool, customer_id: i64) -> Result<String, Error> {
-        // step 1 - insert into orders_product
-        let result = sqlx::query!(
-            "INSERT INTO orders (customer_id) VALUES ($1) returning id::varchar",
+
+    // staff marking an order shipped or delivered, nothing else changes with it
+    pub async fn fulfil(
+        pool: &PgPool,
+        order_id: Uuid,
+        to: OrderStatus,
+        staff_id: i64,
+    ) -> Result<OrderStatus, OrderError> {
+        let mut tx = pool.begin().await?;
+        let status = Self::transition(&mut tx, order_id, to, &OrderActor::Staff(staff_id)).await?;
+        tx.commit().await?;
+        tracing::info!("Order {} marked {} by staff {}", order_id, to, staff_id);
+        Ok(status)
+    }
+
+    // the checkout in progress: an order in 'cart' status the customer has not confirmed yet
+    pub async fn get_draft_id(pool: &PgPool, customer_id: i64) -> Result<Option<Uuid>, Error> {
+        let draft = sqlx::query_scalar!(
+            "SELECT id FROM orders
+             WHERE customer_id = $1 AND status = 'cart' AND is_confirmed IS NOT TRUE
+             ORDER BY created_at DESC
+             LIMIT 1",
             customer_id
         )
-        .fetch_one(pool)
+        .fetch_optional(pool)
+        .await?;
+        Ok(draft)
+    }
+
+    // same as `get_draft_id`, the row stays locked until the transaction ends
+    pub async fn lock_draft(
+        tx: &mut Transaction<'_, Postgres>,
+        customer_id: i64,
+    ) -> Result<Option<Uuid>, Error> {
+        let draft = sqlx::query_scalar!(
+            "SELECT id FROM orders
+             WHERE customer_id = $1 AND status = 'cart' AND is_confirmed IS NOT TRUE
+             ORDER BY created_at DESC
+             LIMIT 1
+             FOR UPDATE",
+            customer_id
+        )
+        .fetch_optional(&mut **tx)
+        .await?;
+        Ok(draft)
+    }
+
+    // copies the cart into the customer's draft order, a new one when there is none; addresses
+    // already chosen are kept, coupon and shipping have to be picked again for the new lines
+    pub async fn save_draft(
+        tx: &mut Transaction<'_, Postgres>,
+        customer_id: i64,
+        items: &[CartItem],
+        rate: &ExchangeRate,
+        shipping_address: Option<&AddressSnapshot>,
+        billing_address: Option<&AddressSnapshot>,
+    ) -> Result<Uuid, Error> {
+        let order_id = match Self::lock_draft(tx, customer_id).await? {
+            Some(order_id) => {
+                sqlx::query!(
+                    "UPDATE orders SET
+                         currency = $2, exchange_rate = $3,
+                         shipping_address = coalesce(shipping_address, $4),
+                         billing_address = coalesce(billing_address, $5),
+                         promotion_code = NULL, discount = NULL,
+                         shipping_rate_id = NULL, shipping_method = NULL, shipping_cost = NULL, tax = NULL,
+                         updated_at = NOW()
+                     WHERE id = $1",
+                    order_id,
+                    rate.currency as Currency,
+                    rate.rate,
+                    shipping_address.map(Json) as _,
+                    billing_address.map(Json) as _
+                )
+                .execute(&mut **tx)
+                .await?;
+                sqlx::query!("DELETE FROM orders_product WHERE order_id = $1", order_id)
+                    .execute(&mut **tx)
+                    .await?;
+                order_id
+            }
+            None => {
+                sqlx::query_scalar!(
+                    "INSERT INTO orders (customer_id, shipping_address, billing_address, currency, exchange_rate)
+                     VALUES ($1, $2, $3, $4, $5)
+                     RETURNING id",
+                    customer_id,
+                    shipping_address.map(Json) as _,
+                    billing_address.map(Json) as _,
+                    rate.currency as Currency,
+                    rate.rate
+                )
+                .fetch_one(&mut **tx)
+                .await?
+            }
+        };
+        Self::create_order_lines(tx, order_id, items).await?;
+        Ok(order_id)
+    }
+
+    // changing the address may change where we ship to, so the shipping method is picked again
+    pub async fn set_draft_addresses(
+        pool: &PgPool,
+        order_id: Uuid,
+        customer_id: i64,
+        shipping_address: &AddressSnapshot,
+        billing_address: &AddressSnapshot,
+    ) -> Result<(), OrderError> {
+        let updated = sqlx::query!(
+            "UPDATE orders SET
+                 shipping_address = $3, billing_address = $4,
+                 shipping_rate_id = NULL, shipping_method = NULL,
+                 updated_at = NOW()
+             WHERE id = $1 AND customer_id = $2 AND status = 'cart' AND is_confirmed IS NOT TRUE",
+            order_id,
+            customer_id,
+            Json(shipping_address) as _,
+            Json(billing_address) as _
+        )
+        .execute(pool)
+        .await?;
+        if updated.rows_affected() == 0 {
+            return Err(OrderError::NotFound);
+        }
+        Ok(())
+    }
+
+    pub async fn set_draft_shipping(
+        pool: &PgPool,
+        order_id: Uuid,
+        customer_id: i64,
+        shipping: &ShippingRate,
+    ) -> Result<(), OrderError> {
+        let updated = sqlx::query!(
+            "UPDATE orders SET shipping_rate_id = $3, shipping_method = $4, updated_at = NOW()
+             WHERE id = $1 AND customer_id = $2 AND status = 'cart' AND is_confirmed IS NOT TRUE",
+            order_id,
+            customer_id,
+            shipping.id,
+            shipping.name
+        )
+        .execute(pool)
         .await?;
-        Ok(result.id.unwrap_or_else(|| "".to_string())) // kostyl'!
+        if updated.rows_affected() == 0 {
+            return Err(OrderError::NotFound);
+        }
+        Ok(())
     }
-    pub async fn create_order_products_with_order_uuid(
-        // step 2 - insert into orders with customer_id
+
+    // None removes the coupon; the code is checked again when the order is confirmed
+    pub async fn set_draft_promotion(
         pool: &PgPool,
-        order_uuid: String,
-        product_id: i32,
-        quantity: i32,
-        sum: i32,
-    ) -> Result<Vec<i32>, Error> {
-        // TODO: refactor this!
-        let mut order_uuids: Vec<Uuid> = Vec::with_capacity(quantity as usize);
-        let mut product_ids: Vec<i32> = Vec::with_capacity(quantity as usize);
-        let mut sums: Vec<i32> = Vec::with_capacity(quantity as usize);
-        for _ in 0..quantity {
-            order_uuids.push(order_uuid.clone().parse().unwrap());
+        order_id: Uuid,
+        customer_id: i64,
+        promotion: Option<&AppliedPromotion>,
+    ) -> Result<(), OrderError> {
+        let updated = sqlx::query!(
+            "UPDATE orders SET promotion_code = $3, discount = $4, updated_at = NOW()
+             WHERE id = $1 AND customer_id = $2 AND status = 'cart' AND is_confirmed IS NOT TRUE",
+            order_id,
+            customer_id,
+            promotion.map(|promotion| promotion.code.clone()),
+            promotion.map(|promotion| promotion.discount) as Option<Money>
+        )
+        .execute(pool)
+        .await?;
+        if updated.rows_affected() == 0 {
+            return Err(OrderError::NotFound);
         }
-        for _ in 0..quantity {
-            product_ids.push(product_id);
+        Ok(())
+    }
+
+    // turns a priced draft (see `Checkout::price_draft`) into a placed order: stock is reserved,
+    // the coupon use recorded and the totals stored; the caller holds the draft lock and commits
+    pub async fn confirm_order(
+        tx: &mut Transaction<'_, Postgres>,
+        order: &OrderDetails,
+        customer_id: i64,
+        promotion: Option<&AppliedPromotion>,
+    ) -> Result<CreatedOrder, OrderError> {
+        let order_id = Uuid::parse_str(&order.order_id).map_err(|_| OrderError::NotFound)?;
+
+        // variants in id order so concurrent checkouts lock stock rows the same way
+        let mut by_variant: Vec<&OrderLine> = order.lines.iter().collect();
+        by_variant.sort_by_key(|line| line.variant_id);
+        for line in &by_variant {
+            let reserved = match line.variant_id {
+                Some(variant_id) => {
+                    InventoryRepository::reserve(tx, order_id, variant_id, line.quantity).await?
+                }
+                None => false,
+            };
+            if !reserved {
+                return Err(OrderError::OutOfStock {
+                    product_name: line.product_name.clone(),
+                    size: line.size.clone().unwrap_or_default(),
+                    colour: line.colour.clone().unwrap_or_default(),
+                });
+            }
         }
-        for _ in 0..quantity {
-            sums.push(sum);
+
+        if let Some(promotion) = promotion {
+            PromotionRepository::record_redemption(
+                tx,
+                promotion.promotion_id,
+                order_id,
+                customer_id,
+                promotion.discount,
+            )
+            .await?;
         }
 
-        // https://github.com/launchbadge/sqlx/blob/main/FAQ.md#how-can-i-bind-an-array-to-a-values-clause-how-can-i-do-bulk-inserts
-        let result = sqlx::query!("INSERT INTO orders_product (order_id, product_id, sum) SELECT * FROM UNNEST($1::uuid[], $2::int[], $3::int[]) RETURNING product_id",
-            &order_uuids,
-            &product_ids,
-            &sums)
-            .fetch_all(pool)
+        sqlx::query!(
+            "UPDATE orders SET
+                 is_confirmed = true,
+                 promotion_code = $2, discount = $3, shipping_cost = $4, tax = $5,
+                 tax_country = $6, shipping_tax = $7,
+                 updated_at = NOW()
+             WHERE id = $1",
+            order_id,
+            promotion.map(|promotion| promotion.code.clone()),
+            promotion.map(|promotion| promotion.discount) as Option<Money>,
+            order.shipping_cost as Option<Money>,
+            order.tax as Option<Money>,
+            order.tax_country,
+            order.shipping_tax as Option<Money>
+        )
+        .execute(&mut **tx)
+        .await?;
+        for line in &order.lines {
+            sqlx::query!(
+                "UPDATE orders_product SET tax_rate = $3, tax = $4 WHERE id = $1 AND order_id = $2",
+                line.id,
+                order_id,
+                line.tax_rate,
+                line.tax as Option<Money>
+            )
+            .execute(&mut **tx)
             .await?;
-        let mut result_vec = Vec::with_capacity(result.len());
-        for row in result {
-            result_vec.push(row.product_id.unwrap_or_else(|| 0)); //what we do with zero product ids?
         }
-        Ok(result_vec)
+
+        let status = Self::transition(
+            tx,
+            order_id,
+            OrderStatus::Placed,
+            &OrderActor::Customer(customer_id),
+        )
+        .await?;
+
+        Ok(CreatedOrder {
+            order_id: order.order_id.clone(),
+            status,
+            product_ids: order.lines.iter().map(|line| line.product_id).collect(),
+            total: order.total,
+            created_at: order.created_at,
+        })
+    }
+
+    // current status of a customer's order, locked until the transaction ends
+    pub async fn lock_order(
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+        customer_id: i64,
+    ) -> Result<OrderStatus, OrderError> {
+        let order = sqlx::query!(
+            r#"SELECT status as "status: OrderStatus" FROM orders WHERE id = $1 AND customer_id = $2 FOR UPDATE"#,
+            order_id,
+            customer_id
+        )
+        .fetch_one(&mut **tx)
+        .await?;
+        Ok(order.status)
+    }
+
+    // the caller commits, so a status change can go together with whatever caused it
+    pub async fn transition(
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+        to: OrderStatus,
+        actor: &OrderActor,
+    ) -> Result<OrderStatus, OrderError> {
+        // lock the row so two concurrent transitions can't both pass the check
+        let current = sqlx::query!(
+            r#"SELECT status as "status: OrderStatus" FROM orders WHERE id = $1 FOR UPDATE"#,
+            order_id
+        )
+        .fetch_one(&mut **tx)
+        .await?;
+
+        if !current.status.can_transition_to(to) {
+            return Err(OrderError::IllegalTransition {
+                from: current.status,
+                to,
+            });
+        }
+
+        sqlx::query!(
+            "UPDATE orders SET status = $2, updated_at = NOW() WHERE id = $1",
+            order_id,
+            to as OrderStatus
+        )
+        .execute(&mut **tx)
+        .await?;
+
+        sqlx::query!(
+            "INSERT INTO order_status_history (order_id, from_status, to_status, actor) VALUES ($1, $2, $3, $4)",
+            order_id,
+            current.status as OrderStatus,
+            to as OrderStatus,
+            actor.to_string()
+        )
+        .execute(&mut **tx)
+        .await?;
+
+        // reserved stock goes back on the shelf together with the status change
+        if to == OrderStatus::Cancelled {
+            InventoryRepository::release_order(tx, order_id).await?;
+        }
+
+        Ok(to)
+    }
+
+    // one orders_product row per cart line
+    async fn create_order_lines(
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+        items: &[CartItem],
+    ) -> Result<(), Error> {
+        let product_ids: Vec<i32> = items.iter().map(|item| item.product_id).collect();
+        let variant_ids: Vec<i32> = items.iter().map(|item| item.variant_id).collect();
+        let quantities: Vec<i32> = items.iter().map(|item| item.quantity).collect();
+        let unit_prices: Vec<Money> = items.iter().map(|item| item.product_price).collect();
+        let line_totals: Vec<Money> = items.iter().map(|item| item.line_total).collect();
+        // https://github.com/launchbadge/sqlx/blob/main/FAQ.md#how-can-i-bind-an-array-to-a-values-clause-how-can-i-do-bulk-inserts
+        // indexed instead of UNNEST, which would spread the money_amount composites over several columns
+        sqlx::query!(
+            r#"INSERT INTO orders_product (order_id, product_id, variant_id, quantity, unit_price, line_total)
+               SELECT $1, ($2::int[])[i], ($3::int[])[i], ($4::int[])[i], ($5::money_amount[])[i], ($6::money_amount[])[i]
+               FROM generate_subscripts($2::int[], 1) AS i"#,
+            order_id,
+            &product_ids,
+            &variant_ids,
+            &quantities,
+            &unit_prices as &[Money],
+            &line_totals as &[Money]
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
     }
 }
 // pub async fn get_product_by_order_uuid() {}
@@ -0,0 +1,82 @@
+use crate::repository::customer_repository::CustomerError;
+use crate::services::auth::AuthService;
+use bcrypt::{DEFAULT_COST, hash};
+use sqlx::PgPool;
+
+const RESET_TOKEN_TTL_SECONDS: f64 = 3600.0;
+
+pub struct PasswordResetRepository;
+
+impl PasswordResetRepository {
+    pub async fn create_token(pool: &PgPool, customer_id: i64) -> Result<String, CustomerError> {
+        let token = AuthService::generate_token();
+        sqlx::query!(
+            "INSERT INTO password_reset_tokens (customer_id, token_hash, expires_at)
+             VALUES ($1, $2, NOW() + make_interval(secs => $3))",
+            customer_id,
+            AuthService::hash_token(&token),
+            RESET_TOKEN_TTL_SECONDS
+        )
+        .execute(pool)
+        .await?;
+        Ok(token)
+    }
+
+    pub async fn is_token_valid(pool: &PgPool, token: &str) -> Result<bool, CustomerError> {
+        let result = sqlx::query!(
+            "SELECT id FROM password_reset_tokens
+             WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()",
+            AuthService::hash_token(token)
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(result.is_some())
+    }
+
+    // consumes the token, sets the new password and signs the customer out everywhere
+    pub async fn reset_password(
+        pool: &PgPool,
+        token: &str,
+        new_password: &str,
+    ) -> Result<i64, CustomerError> {
+        let hashed_pwd = hash(new_password, DEFAULT_COST).map_err(|_| CustomerError::HashingError)?;
+
+        let mut tx = pool.begin().await?;
+
+        // RowNotFound (used, expired or unknown token) -> CustomerError::NotFound
+        let consumed = sqlx::query!(
+            "UPDATE password_reset_tokens SET used_at = NOW()
+             WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+             RETURNING customer_id",
+            AuthService::hash_token(token)
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "UPDATE customers SET password = $2, updated_at = NOW() WHERE id = $1",
+            consumed.customer_id,
+            hashed_pwd
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        // other links sent to the same customer must not work after a successful reset
+        sqlx::query!(
+            "UPDATE password_reset_tokens SET used_at = NOW() WHERE customer_id = $1 AND used_at IS NULL",
+            consumed.customer_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "UPDATE sessions SET revoked_at = NOW() WHERE customer_id = $1 AND revoked_at IS NULL",
+            consumed.customer_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(consumed.customer_id)
+    }
+}
@@ -0,0 +1,276 @@
+use crate::models::money::Money;
+use crate::models::payment::{
+    Payment, PaymentStatus, PendingRefund, Refund, RefundStatus, RefundablePayment,
+};
+use sqlx::{Error, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+pub struct PaymentRepository;
+
+impl PaymentRepository {
+    pub async fn list_payments(pool: &PgPool, order_id: Uuid) -> Result<Vec<Payment>, Error> {
+        let payments = sqlx::query_as!(
+            Payment,
+            r#"
+select
+    id,
+    order_id::text as "order_id!",
+    provider,
+    reference,
+    amount as "amount!: Money",
+    status as "status: PaymentStatus",
+    failure_reason,
+    created_at
+from payments
+where order_id = $1
+order by created_at, id;"#,
+            order_id
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(payments)
+    }
+
+    pub async fn create_payment(
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+        provider: &str,
+        amount: Money,
+    ) -> Result<i64, Error> {
+        let payment_id = sqlx::query_scalar!(
+            "INSERT INTO payments (order_id, provider, amount) VALUES ($1, $2, $3) RETURNING id",
+            order_id,
+            provider,
+            amount as Money
+        )
+        .fetch_one(&mut **tx)
+        .await?;
+        Ok(payment_id)
+    }
+
+    // an attempt still waiting for the provider; one older than that is taken as lost (the
+    // process died during the call), a late success for it is refunded by the webhook
+    pub async fn has_payment_in_flight(
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+    ) -> Result<bool, Error> {
+        let in_flight = sqlx::query_scalar!(
+            r#"SELECT EXISTS (
+                   SELECT 1 FROM payments
+                   WHERE order_id = $1 AND status IN ('pending', 'authorized')
+                     AND created_at > NOW() - INTERVAL '15 minutes'
+               ) as "in_flight!""#,
+            order_id
+        )
+        .fetch_one(&mut **tx)
+        .await?;
+        Ok(in_flight)
+    }
+
+    // the row stays locked until the transaction ends, a webhook and the payment page can't
+    // both move the same payment
+    pub async fn find_by_reference_for_update(
+        tx: &mut Transaction<'_, Postgres>,
+        provider: &str,
+        reference: &str,
+    ) -> Result<Option<Payment>, Error> {
+        let payment = sqlx::query_as!(
+            Payment,
+            r#"
+select
+    id,
+    order_id::text as "order_id!",
+    provider,
+    reference,
+    amount as "amount!: Money",
+    status as "status: PaymentStatus",
+    failure_reason,
+    created_at
+from payments
+where provider = $1 and reference = $2
+for update;"#,
+            provider,
+            reference
+        )
+        .fetch_optional(&mut **tx)
+        .await?;
+        Ok(payment)
+    }
+
+    // `reference` is kept when already known (None)
+    pub async fn update_status(
+        tx: &mut Transaction<'_, Postgres>,
+        payment_id: i64,
+        status: PaymentStatus,
+        reference: Option<&str>,
+        failure_reason: Option<&str>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "UPDATE payments SET
+                 status = $2, reference = coalesce($3, reference), failure_reason = $4, updated_at = NOW()
+             WHERE id = $1",
+            payment_id,
+            status as PaymentStatus,
+            reference,
+            failure_reason
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    // false when the event was handled before
+    pub async fn record_event(
+        tx: &mut Transaction<'_, Postgres>,
+        provider: &str,
+        event_id: &str,
+        payment_id: i64,
+        kind: &str,
+    ) -> Result<bool, Error> {
+        let inserted = sqlx::query!(
+            "INSERT INTO payment_events (provider, event_id, payment_id, kind) VALUES ($1, $2, $3, $4)
+             ON CONFLICT (provider, event_id) DO NOTHING",
+            provider,
+            event_id,
+            payment_id,
+            kind
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(inserted.rows_affected() == 1)
+    }
+
+    // captured payments of an order, oldest first, locked so refunds of the same order queue up
+    pub async fn lock_refundable(
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+    ) -> Result<Vec<RefundablePayment>, Error> {
+        let rows = sqlx::query!(
+            r#"
+select
+    p.id,
+    p.reference as "reference!",
+    p.amount as "amount!: Money",
+    coalesce((select sum((r.amount).amount) from refunds r where r.payment_id = p.id), 0)::bigint as "refunded!"
+from payments p
+where p.order_id = $1 and p.status = 'captured' and p.reference is not null
+order by p.created_at, p.id
+for update;"#,
+            order_id
+        )
+        .fetch_all(&mut **tx)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| RefundablePayment {
+                id: row.id,
+                reference: row.reference,
+                amount: row.amount,
+                refunded: Money::new(row.refunded, row.amount.currency),
+            })
+            .collect())
+    }
+
+    // pending until `complete_refund`, it counts as refunded right away so nothing goes out twice
+    pub async fn create_refund(
+        tx: &mut Transaction<'_, Postgres>,
+        payment_id: i64,
+        return_request_id: Option<i64>,
+        amount: Money,
+    ) -> Result<i64, Error> {
+        sqlx::query_scalar!(
+            "INSERT INTO refunds (payment_id, return_request_id, amount) VALUES ($1, $2, $3) RETURNING id",
+            payment_id,
+            return_request_id,
+            amount as Money
+        )
+        .fetch_one(&mut **tx)
+        .await
+    }
+
+    // oldest first, the ones whose provider call failed or never happened
+    pub async fn list_pending_refunds(pool: &PgPool) -> Result<Vec<PendingRefund>, Error> {
+        sqlx::query_as!(
+            PendingRefund,
+            r#"
+select
+    r.id,
+    r.payment_id,
+    p.reference as "payment_reference!",
+    r.amount as "amount!: Money"
+from refunds r
+     join payments p on p.id = r.payment_id
+where r.status = 'pending'
+order by r.id;"#
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    // the provider accepted the refund; the payment counts as refunded once all of it went back
+    pub async fn complete_refund(
+        pool: &PgPool,
+        refund_id: i64,
+        payment_id: i64,
+        reference: &str,
+    ) -> Result<(), Error> {
+        let mut tx = pool.begin().await?;
+        sqlx::query!(
+            "UPDATE refunds SET status = 'succeeded', reference = $2, failure = NULL, updated_at = NOW()
+             WHERE id = $1",
+            refund_id,
+            reference
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE payments p SET status = 'refunded', updated_at = NOW()
+             WHERE p.id = $1 AND p.status = 'captured'
+               AND (p.amount).amount <= (
+                   select coalesce(sum((r.amount).amount), 0) from refunds r
+                   where r.payment_id = p.id and r.status = 'succeeded'
+               )",
+            payment_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    // stays pending for `send-pending-refunds`
+    pub async fn fail_refund(pool: &PgPool, refund_id: i64, failure: &str) -> Result<(), Error> {
+        sqlx::query!(
+            "UPDATE refunds SET failure = $2, updated_at = NOW() WHERE id = $1",
+            refund_id,
+            failure
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn list_refunds(pool: &PgPool, order_id: Uuid) -> Result<Vec<Refund>, Error> {
+        let refunds = sqlx::query_as!(
+            Refund,
+            r#"
+select
+    r.id,
+    r.payment_id,
+    r.return_request_id,
+    r.reference,
+    r.amount as "amount!: Money",
+    r.status as "status: RefundStatus",
+    r.failure,
+    r.created_at
+from refunds r
+     join payments p on p.id = r.payment_id
+where p.order_id = $1
+order by r.created_at, r.id;"#,
+            order_id
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(refunds)
+    }
+}
@@ -1,4 +1,7 @@
-use crate::models::products::{FullProduct, Product, ProductsWithCategory, SumProduct};
+use crate::models::money::Money;
+use crate::models::products::{
+    FullProduct, Product, ProductVariant, ProductsWithCategory, SumProduct,
+};
 use sqlx::{PgPool, Row};
 use std::collections::HashMap;
 
@@ -17,13 +20,15 @@ impl ProductRepository {
        images,
        rating,
        code,
+       is_in_stock,
        category_name
 FROM (SELECT p.id,
              p.name,
-             p.price::integer,
+             p.price,
              p.images,
              p.rating,
              p.code,
+             exists(select 1 from product_variants v where v.product_id = p.id and v.is_active = true and v.stock > 0) AS is_in_stock,
              c.name AS category_name,
              ROW_NUMBER() OVER (
                  PARTITION BY c.id
@@ -43,7 +48,7 @@ WHERE rn <= $1;",
         for product in products {
             map_products
                 .entry(product.get("category_name"))
-                .or_insert_with(Vec::new)
+                .or_default()
                 .push(ProductsWithCategory {
                     id: product.get("id"),
                     name: product.get("name"),
@@ -51,6 +56,7 @@ WHERE rn <= $1;",
                     images: product.get("images"),
                     rating: product.get("rating"),
                     code: product.get("code"),
+                    is_in_stock: product.get("is_in_stock"),
                 })
         }
         Ok(map_products)
@@ -65,10 +71,11 @@ WHERE rn <= $1;",
                 r#"select
     p.id,
     p.name,
-    p.price::integer,
+    p.price,
     p.rating,
     p.code,
-    p.images
+    p.images,
+    exists(select 1 from product_variants v where v.product_id = p.id and v.is_active = true and v.stock > 0) as is_in_stock
 from products p
          join product_categories pc on p.id = pc.product_id
         join categories c on pc.category_id = c.id
@@ -103,6 +110,7 @@ limit $2;"#,
                 rating: product.get("rating"),
                 code: product.get("code"),
                 images: product.get("images"),
+                is_in_stock: product.get("is_in_stock"),
             })
         }
         Ok((ctx_products, count))
@@ -120,10 +128,11 @@ limit $2;"#,
 select
     p.id,
     p.name,
-    p.price::integer,
+    p.price,
     p.rating,
     p.code,
     p.images,
+    exists(select 1 from product_variants v where v.product_id = p.id and v.is_active = true and v.stock > 0) as is_in_stock,
     c.name as "category_name",
     c.description as "category_description"
 from products p
@@ -175,6 +184,7 @@ where c.is_active = true and c.name = $1;"#
                 rating: product.get("rating"),
                 code: product.get("code"),
                 images: product.get("images"),
+                is_in_stock: product.get("is_in_stock"),
             });
         }
 
@@ -191,7 +201,7 @@ select
     id,
     name,
     description,
-    price::integer,
+    price,
     rating,
     code,
     images
@@ -201,31 +211,67 @@ from products where code = $1;",
         .fetch_one(pool)
         .await?;
 
+        let product_id: i32 = product.get("id");
+        let variants = Self::get_product_variants(product_id, pool).await?;
+
         Ok(FullProduct {
-            id: product.get("id"),
+            id: product_id,
             name: product.get("name"),
             description: product.get("description"),
             price: product.get("price"),
             rating: product.get("rating"),
             code: product.get("code"),
             images: product.get("images"),
+            is_in_stock: variants.iter().any(|variant| variant.stock > 0),
+            variants,
         })
     }
 
-    pub async fn get_product_by_id(
+    pub async fn get_product_variants(
         product_id: i32,
         pool: &PgPool,
+    ) -> Result<Vec<ProductVariant>, sqlx::Error> {
+        let variants = sqlx::query_as!(
+            ProductVariant,
+            r#"
+select
+    v.id,
+    v.sku,
+    v.size,
+    v.colour,
+    coalesce(v.price, p.price) as "price!: Money",
+    v.stock
+from product_variants v
+     join products p on p.id = v.product_id
+where v.product_id = $1 and v.is_active = true
+order by v.colour, v.size, v.id;"#,
+            product_id
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(variants)
+    }
+
+    // price and stock of what goes into the cart, RowNotFound for unknown or disabled variants
+    pub async fn get_variant_by_id(
+        variant_id: i32,
+        pool: &PgPool,
     ) -> Result<SumProduct, sqlx::Error> {
         let product = sqlx::query(
-            "select id, price::integer from products where id = $1;",
+            "select p.id, v.id as variant_id, coalesce(v.price, p.price) as price, v.stock
+             from product_variants v
+                  join products p on p.id = v.product_id
+             where v.id = $1 and v.is_active = true;",
         )
-            .bind(product_id)
-            .fetch_one(pool)
-            .await?;
+        .bind(variant_id)
+        .fetch_one(pool)
+        .await?;
 
         Ok(SumProduct {
             id: product.get("id"),
+            variant_id: product.get("variant_id"),
             price: product.get("price"),
+            stock: product.get("stock"),
         })
     }
 }
@@ -0,0 +1,103 @@
+use crate::models::money::Money;
+use crate::models::promotion::{DiscountKind, Promotion, PromotionUsage};
+use sqlx::{Error, Postgres, Transaction};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+pub struct PromotionRepository;
+
+impl PromotionRepository {
+    // locks the promotion until the order is stored, two checkouts can't both take the last use
+    pub async fn find_by_code_for_update(
+        tx: &mut Transaction<'_, Postgres>,
+        code: &str,
+    ) -> Result<Option<Promotion>, Error> {
+        let promotion = sqlx::query_as!(
+            Promotion,
+            r#"
+select
+    id,
+    code,
+    kind as "kind: DiscountKind",
+    percent_off,
+    amount_off as "amount_off: Money",
+    min_order_total as "min_order_total: Money",
+    category_id,
+    product_id,
+    max_uses,
+    max_uses_per_customer,
+    (starts_at is null or starts_at <= NOW()) as "has_started!",
+    (ends_at is not null and ends_at <= NOW()) as "has_ended!"
+from promotions
+where code = upper($1) and is_active = true
+for update;"#,
+            code.trim()
+        )
+        .fetch_optional(&mut **tx)
+        .await?;
+        Ok(promotion)
+    }
+
+    // cancelled and failed orders give their use back
+    pub async fn usage(
+        tx: &mut Transaction<'_, Postgres>,
+        promotion_id: i32,
+        customer_id: i64,
+    ) -> Result<PromotionUsage, Error> {
+        let usage = sqlx::query_as!(
+            PromotionUsage,
+            r#"
+select
+    count(*) as "total!",
+    count(*) filter (where r.customer_id = $2) as "by_customer!"
+from promotion_redemptions r
+     join orders o on o.id = r.order_id
+where r.promotion_id = $1
+  and o.status not in ('cancelled', 'payment_failed');"#,
+            promotion_id,
+            customer_id
+        )
+        .fetch_one(&mut **tx)
+        .await?;
+        Ok(usage)
+    }
+
+    // products the discount applies to, None when it applies to the whole order
+    pub async fn eligible_product_ids(
+        tx: &mut Transaction<'_, Postgres>,
+        promotion: &Promotion,
+    ) -> Result<Option<HashSet<i32>>, Error> {
+        if let Some(product_id) = promotion.product_id {
+            return Ok(Some(HashSet::from([product_id])));
+        }
+        let Some(category_id) = promotion.category_id else {
+            return Ok(None);
+        };
+        let product_ids = sqlx::query_scalar!(
+            "SELECT product_id FROM product_categories WHERE category_id = $1",
+            category_id
+        )
+        .fetch_all(&mut **tx)
+        .await?;
+        Ok(Some(product_ids.into_iter().collect()))
+    }
+
+    pub async fn record_redemption(
+        tx: &mut Transaction<'_, Postgres>,
+        promotion_id: i32,
+        order_id: Uuid,
+        customer_id: i64,
+        discount: Money,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "INSERT INTO promotion_redemptions (promotion_id, order_id, customer_id, discount) VALUES ($1, $2, $3, $4)",
+            promotion_id,
+            order_id,
+            customer_id,
+            discount as Money
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+}
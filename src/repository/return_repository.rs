@@ -0,0 +1,228 @@
+use crate::models::money::{Currency, Money};
+use crate::models::returns::{
+    NewReturnLine, ReturnLine, ReturnReason, ReturnRequest, ReturnStatus,
+};
+use sqlx::{Error, PgPool, Postgres, Transaction};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+pub struct ReturnRepository;
+
+impl ReturnRepository {
+    pub async fn create_request(
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+        customer_id: i64,
+        comment: Option<&str>,
+        lines: &[NewReturnLine],
+    ) -> Result<i64, Error> {
+        let return_id = sqlx::query_scalar!(
+            "INSERT INTO return_requests (order_id, customer_id, comment) VALUES ($1, $2, $3) RETURNING id",
+            order_id,
+            customer_id,
+            comment
+        )
+        .fetch_one(&mut **tx)
+        .await?;
+
+        for line in lines {
+            sqlx::query!(
+                "INSERT INTO return_request_lines (return_request_id, order_line_id, quantity, reason)
+                 VALUES ($1, $2, $3, $4)",
+                return_id,
+                line.order_line_id,
+                line.quantity,
+                line.reason as ReturnReason
+            )
+            .execute(&mut **tx)
+            .await?;
+        }
+        Ok(return_id)
+    }
+
+    // per order line, what approved requests took back, and with `include_open` what open ones
+    // are about to
+    pub async fn returned_quantities(
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+        include_open: bool,
+    ) -> Result<HashMap<i32, i32>, Error> {
+        let rows = sqlx::query!(
+            r#"
+select
+    l.order_line_id,
+    sum(l.quantity)::int as "quantity!"
+from return_request_lines l
+     join return_requests r on r.id = l.return_request_id
+where r.order_id = $1 and (r.status = 'approved' or ($2 and r.status = 'requested'))
+group by l.order_line_id;"#,
+            order_id,
+            include_open
+        )
+        .fetch_all(&mut **tx)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.order_line_id, row.quantity))
+            .collect())
+    }
+
+    pub async fn list_for_order(
+        pool: &PgPool,
+        order_id: Uuid,
+    ) -> Result<Vec<ReturnRequest>, Error> {
+        let ids = sqlx::query_scalar!(
+            "SELECT id FROM return_requests WHERE order_id = $1 ORDER BY created_at, id",
+            order_id
+        )
+        .fetch_all(pool)
+        .await?;
+        Self::get_requests(pool, &ids).await
+    }
+
+    // oldest first, the order staff work through them
+    pub async fn list_by_status(
+        pool: &PgPool,
+        status: ReturnStatus,
+    ) -> Result<Vec<ReturnRequest>, Error> {
+        let ids = sqlx::query_scalar!(
+            "SELECT id FROM return_requests WHERE status = $1 ORDER BY created_at, id LIMIT 200",
+            status as ReturnStatus
+        )
+        .fetch_all(pool)
+        .await?;
+        Self::get_requests(pool, &ids).await
+    }
+
+    pub async fn get_request(pool: &PgPool, return_id: i64) -> Result<ReturnRequest, Error> {
+        Self::get_requests(pool, &[return_id])
+            .await?
+            .pop()
+            .ok_or(Error::RowNotFound)
+    }
+
+    // status of the request, locked until the transaction ends so it is decided only once
+    pub async fn lock_request(
+        tx: &mut Transaction<'_, Postgres>,
+        return_id: i64,
+    ) -> Result<ReturnStatus, Error> {
+        let status = sqlx::query_scalar!(
+            r#"SELECT status as "status: ReturnStatus" FROM return_requests WHERE id = $1 FOR UPDATE"#,
+            return_id
+        )
+        .fetch_one(&mut **tx)
+        .await?;
+        Ok(status)
+    }
+
+    pub async fn decide(
+        tx: &mut Transaction<'_, Postgres>,
+        return_id: i64,
+        status: ReturnStatus,
+        staff_id: i64,
+        staff_note: Option<&str>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "UPDATE return_requests SET
+                 status = $2, staff_note = $3, decided_by = $4, decided_at = NOW(), updated_at = NOW()
+             WHERE id = $1",
+            return_id,
+            status as ReturnStatus,
+            staff_note,
+            staff_id
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    // requests in the order of `return_ids`, with their lines and refunds
+    async fn get_requests(pool: &PgPool, return_ids: &[i64]) -> Result<Vec<ReturnRequest>, Error> {
+        let rows = sqlx::query!(
+            r#"
+select
+    r.id,
+    r.order_id,
+    r.customer_id,
+    c.email as customer_email,
+    r.status as "status: ReturnStatus",
+    r.comment,
+    r.staff_note,
+    r.decided_at,
+    r.created_at,
+    (select sum((f.amount).amount) from refunds f where f.return_request_id = r.id)::bigint as refunded,
+    (select (f.amount).currency from refunds f where f.return_request_id = r.id limit 1) as refund_currency
+from return_requests r
+     join customers c on c.id = r.customer_id
+where r.id = any($1)
+order by array_position($1, r.id);"#,
+            return_ids
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let mut lines = sqlx::query!(
+            r#"
+select
+    l.return_request_id,
+    l.order_line_id,
+    p.name as product_name,
+    p.code as "product_code?",
+    v.id as "variant_id?",
+    v.sku as "sku?",
+    v.size as "size?",
+    v.colour as "colour?",
+    op.unit_price as "unit_price: Money",
+    l.quantity,
+    l.reason as "reason: ReturnReason"
+from return_request_lines l
+     join orders_product op on op.id = l.order_line_id
+     join products p on p.id = op.product_id
+     left join product_variants v on v.id = op.variant_id
+where l.return_request_id = any($1)
+order by l.id;"#,
+            return_ids
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .fold(HashMap::<i64, Vec<ReturnLine>>::new(), |mut lines, row| {
+            lines
+                .entry(row.return_request_id)
+                .or_default()
+                .push(ReturnLine {
+                    order_line_id: row.order_line_id,
+                    product_name: row.product_name,
+                    product_code: row.product_code.unwrap_or_default(),
+                    variant_id: row.variant_id,
+                    sku: row.sku,
+                    size: row.size,
+                    colour: row.colour,
+                    unit_price: row.unit_price,
+                    quantity: row.quantity,
+                    reason: row.reason,
+                });
+            lines
+        });
+
+        Ok(rows
+            .into_iter()
+            .map(|row| ReturnRequest {
+                id: row.id,
+                order_id: row.order_id.to_string(),
+                customer_id: row.customer_id,
+                customer_email: row.customer_email,
+                status: row.status,
+                comment: row.comment,
+                staff_note: row.staff_note,
+                decided_at: row.decided_at,
+                created_at: row.created_at,
+                refunded: row
+                    .refunded
+                    .zip(row.refund_currency.as_deref().and_then(Currency::from_code))
+                    .map(|(amount, currency)| Money::new(amount, currency)),
+                lines: lines.remove(&row.id).unwrap_or_default(),
+            })
+            .collect())
+    }
+}
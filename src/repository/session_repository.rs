@@ -0,0 +1,106 @@
+use crate::models::session::{ActiveSession, CurrentSession};
+use crate::services::auth::{AuthService, SESSION_TTL_SECONDS};
+use sqlx::{Error, PgPool};
+
+pub struct SessionRepository;
+
+impl SessionRepository {
+    pub async fn create_session(
+        pool: &PgPool,
+        customer_id: i64,
+        user_agent: Option<&str>,
+        ip_address: Option<&str>,
+    ) -> Result<String, Error> {
+        let token = AuthService::generate_token();
+        sqlx::query!(
+            "INSERT INTO sessions (customer_id, token_hash, user_agent, ip_address, expires_at)
+             VALUES ($1, $2, $3, $4, NOW() + make_interval(secs => $5))",
+            customer_id,
+            AuthService::hash_token(&token),
+            user_agent,
+            ip_address,
+            SESSION_TTL_SECONDS as f64
+        )
+        .execute(pool)
+        .await?;
+        Ok(token)
+    }
+
+    // resolves a cookie token to a live session and bumps last_seen_at
+    pub async fn touch_active_session(
+        pool: &PgPool,
+        token: &str,
+    ) -> Result<Option<CurrentSession>, Error> {
+        let session = sqlx::query_as!(
+            CurrentSession,
+            "UPDATE sessions SET last_seen_at = NOW()
+             WHERE token_hash = $1 AND revoked_at IS NULL AND expires_at > NOW()
+             RETURNING id, customer_id",
+            AuthService::hash_token(token)
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(session)
+    }
+
+    pub async fn list_active_sessions(
+        pool: &PgPool,
+        customer_id: i64,
+    ) -> Result<Vec<ActiveSession>, Error> {
+        let sessions = sqlx::query_as!(
+            ActiveSession,
+            "SELECT id, user_agent, ip_address, created_at, last_seen_at, expires_at
+             FROM sessions
+             WHERE customer_id = $1 AND revoked_at IS NULL AND expires_at > NOW()
+             ORDER BY last_seen_at DESC",
+            customer_id
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(sessions)
+    }
+
+    pub async fn revoke_session(
+        pool: &PgPool,
+        session_id: i64,
+        customer_id: i64,
+    ) -> Result<(), Error> {
+        // customer_id keeps customers from revoking each other's sessions
+        sqlx::query!(
+            "UPDATE sessions SET revoked_at = NOW()
+             WHERE id = $1 AND customer_id = $2 AND revoked_at IS NULL",
+            session_id,
+            customer_id
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn revoke_all_sessions(pool: &PgPool, customer_id: i64) -> Result<(), Error> {
+        sqlx::query!(
+            "UPDATE sessions SET revoked_at = NOW() WHERE customer_id = $1 AND revoked_at IS NULL",
+            customer_id
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    // after a password change every device but the one making the change is signed out
+    pub async fn revoke_other_sessions(
+        pool: &PgPool,
+        customer_id: i64,
+        keep_session_id: i64,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "UPDATE sessions SET revoked_at = NOW()
+             WHERE customer_id = $1 AND id <> $2 AND revoked_at IS NULL",
+            customer_id,
+            keep_session_id
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,60 @@
+use crate::models::money::Money;
+use crate::models::shipping::ShippingRate;
+use sqlx::{Error, PgPool};
+
+pub struct ShippingRepository;
+
+impl ShippingRepository {
+    // rates that deliver to `country`, cheapest first within the configured order
+    pub async fn list_rates_for_country(
+        pool: &PgPool,
+        country: &str,
+    ) -> Result<Vec<ShippingRate>, Error> {
+        let rates = sqlx::query_as!(
+            ShippingRate,
+            r#"
+select
+    id,
+    name,
+    description,
+    price as "price!: Money",
+    free_over as "free_over: Money",
+    min_days,
+    max_days
+from shipping_rates
+where is_active = true and (countries is null or upper(trim($1)) = any(countries))
+order by sort_order, (price).amount, id;"#,
+            country
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(rates)
+    }
+
+    // RowNotFound when the rate does not exist, is switched off or does not ship to `country`
+    pub async fn get_rate_for_country(
+        pool: &PgPool,
+        shipping_rate_id: i32,
+        country: &str,
+    ) -> Result<ShippingRate, Error> {
+        let rate = sqlx::query_as!(
+            ShippingRate,
+            r#"
+select
+    id,
+    name,
+    description,
+    price as "price!: Money",
+    free_over as "free_over: Money",
+    min_days,
+    max_days
+from shipping_rates
+where id = $1 and is_active = true and (countries is null or upper(trim($2)) = any(countries));"#,
+            shipping_rate_id,
+            country
+        )
+        .fetch_one(pool)
+        .await?;
+        Ok(rate)
+    }
+}
@@ -0,0 +1,54 @@
+use crate::models::tax::TaxRates;
+use rust_decimal::Decimal;
+use sqlx::{Error, PgPool};
+use std::collections::HashMap;
+
+pub struct TaxRepository;
+
+impl TaxRepository {
+    // a product in several categories with their own rate gets the lowest of them;
+    // None when the country has no rates at all, a zero-tax country has a standard rate of 0
+    pub async fn rates_for_country(
+        pool: &PgPool,
+        country: &str,
+        product_ids: &[i32],
+    ) -> Result<Option<TaxRates>, Error> {
+        let country = country.trim().to_ascii_uppercase();
+        let standard = sqlx::query!(
+            r#"SELECT name, rate FROM tax_rates WHERE country = $1 AND category_id IS NULL"#,
+            country
+        )
+        .fetch_optional(pool)
+        .await?;
+        let product_rates = sqlx::query!(
+            r#"
+select
+    pc.product_id,
+    min(t.rate) as "rate!"
+from product_categories pc
+     join tax_rates t on t.category_id = pc.category_id
+where t.country = $1 and pc.product_id = any($2)
+group by pc.product_id;"#,
+            country,
+            product_ids
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| (row.product_id, row.rate.normalize()))
+        .collect::<HashMap<_, _>>();
+
+        if standard.is_none() && product_rates.is_empty() {
+            return Ok(None);
+        }
+        let (name, standard_rate) = standard.map_or(("VAT".to_string(), Decimal::ZERO), |row| {
+            (row.name, row.rate.normalize())
+        });
+        Ok(Some(TaxRates {
+            country: Some(country),
+            name,
+            standard_rate,
+            product_rates,
+        }))
+    }
+}
@@ -1,15 +1,35 @@
-use crate::middlewares::{extract_user_id_from_cookie, optional_customer, redirect_if_authed};
+use crate::middlewares::{
+    csrf_protect, optional_customer, redirect_if_authed, render_error_page, require_customer,
+    require_staff, select_currency,
+};
 use crate::models::state::AppState;
 use crate::views::{
-    about::about, customer::get_customer_login_page, customer::get_customer_registration_page,
-    customer::get_profile_customer_page, customer::logout_customer,
-    customer::post_customer_login_page, customer::post_customer_registration_page, home::home,
-    order::get_list_orders, order::get_order_by_uuid_and_customer, order::post_add_product_to_cart,
-    products::get_product_by_code, products::get_products, products::get_products_by_category_name,
+    about::about, about::not_found, address::get_addresses_page, address::get_edit_address_page,
+    address::get_new_address_page, address::post_delete_address, address::post_edit_address,
+    address::post_new_address, cart::get_cart, cart::post_add_product_to_cart,
+    cart::post_remove_cart_item, cart::post_update_cart_item, checkout::get_checkout_address,
+    checkout::get_checkout_review, checkout::get_checkout_shipping, checkout::post_checkout_address,
+    checkout::post_checkout_confirm, checkout::post_checkout_coupon, checkout::post_checkout_shipping,
+    checkout::post_start_checkout,
+    contact::get_contact_page, contact::post_contact_page, currency::post_select_currency,
+    customer::get_customer_login_page, customer::get_customer_registration_page,
+    customer::get_profile_customer_page, customer::logout_customer, customer::post_change_email,
+    customer::post_change_password, customer::post_customer_login_page,
+    customer::post_customer_registration_page, customer::post_revoke_all_sessions,
+    customer::post_revoke_session, customer::post_update_profile,
+    email_verification::get_verify_email_page, email_verification::post_resend_verification_email,
+    home::home, order::get_list_orders, order::get_order_by_uuid_and_customer,
+    order::get_order_invoice, order::get_order_receipt, order::post_cancel_order, password_reset::get_forgot_password_page,
+    password_reset::get_reset_password_page, password_reset::post_forgot_password_page,
+    password_reset::post_reset_password_page, payment::get_pay_order, payment::post_pay_order,
+    payment::post_payment_webhook, products::get_product_by_code,
+    products::get_products, products::get_products_by_category_name,
+    returns::get_return_request_page, returns::post_return_request, staff::get_staff_return,
+    staff::get_staff_orders, staff::get_staff_returns, staff::post_approve_return,
+    staff::post_deliver_order, staff::post_reject_return, staff::post_ship_order,
 };
 use axum::routing::{get, post};
 use axum::{Extension, Router, middleware};
-use minijinja::Environment;
 use simple_cookie::SigningKey;
 use sqlx::PgPool;
 use std::sync::Arc;
@@ -18,14 +38,81 @@ use tower_http::services::ServeDir;
 pub fn create_router(
     pool: PgPool,
     static_files: ServeDir,
-    env: Environment<'static>,
+    state: AppState,
     signing_key: SigningKey,
 ) -> Router {
+    let state = Arc::new(state);
+
     let auth_routes = Router::new()
         .route("/profile", get(get_profile_customer_page))
-        .route("/order", post(post_add_product_to_cart))
+        .route("/update-profile", post(post_update_profile))
+        .route("/profile/password", post(post_change_password))
+        .route("/profile/email", post(post_change_email))
+        .route("/profile/sessions/revoke", post(post_revoke_session))
+        .route(
+            "/profile/sessions/revoke-all",
+            post(post_revoke_all_sessions),
+        )
+        .route(
+            "/profile/verify-email/resend",
+            post(post_resend_verification_email),
+        )
+        .route(
+            "/profile/addresses",
+            get(get_addresses_page).post(post_new_address),
+        )
+        .route("/profile/addresses/new", get(get_new_address_page))
+        .route(
+            "/profile/addresses/{address_id}",
+            get(get_edit_address_page).post(post_edit_address),
+        )
+        .route(
+            "/profile/addresses/{address_id}/delete",
+            post(post_delete_address),
+        )
+        .route("/cart", get(get_cart))
+        .route("/cart/add", post(post_add_product_to_cart))
+        .route("/cart/update", post(post_update_cart_item))
+        .route("/cart/remove", post(post_remove_cart_item))
+        .route("/checkout", post(post_start_checkout))
+        .route(
+            "/checkout/address",
+            get(get_checkout_address).post(post_checkout_address),
+        )
+        .route(
+            "/checkout/shipping",
+            get(get_checkout_shipping).post(post_checkout_shipping),
+        )
+        .route("/checkout/review", get(get_checkout_review))
+        .route("/checkout/coupon", post(post_checkout_coupon))
+        .route("/checkout/confirm", post(post_checkout_confirm))
         .route("/my-orders", get(get_list_orders))
-        .layer(middleware::from_fn(extract_user_id_from_cookie));
+        .route("/order/{order_uuid}", get(get_order_by_uuid_and_customer))
+        .route("/order/{order_uuid}/receipt", get(get_order_receipt))
+        .route("/order/{order_uuid}/invoice.pdf", get(get_order_invoice))
+        .route(
+            "/order/{order_uuid}/pay",
+            get(get_pay_order).post(post_pay_order),
+        )
+        .route("/order/{order_uuid}/cancel", post(post_cancel_order))
+        .route(
+            "/order/{order_uuid}/return",
+            get(get_return_request_page).post(post_return_request),
+        )
+        .layer(middleware::from_fn(require_customer));
+
+    let staff_routes = Router::new()
+        .route("/staff/returns", get(get_staff_returns))
+        .route("/staff/returns/{return_id}", get(get_staff_return))
+        .route(
+            "/staff/returns/{return_id}/approve",
+            post(post_approve_return),
+        )
+        .route("/staff/returns/{return_id}/reject", post(post_reject_return))
+        .route("/staff/orders", get(get_staff_orders))
+        .route("/staff/orders/{order_uuid}/ship", post(post_ship_order))
+        .route("/staff/orders/{order_uuid}/deliver", post(post_deliver_order))
+        .layer(middleware::from_fn(require_staff));
 
     let non_auth_routes = Router::new()
         .route(
@@ -33,11 +120,25 @@ pub fn create_router(
             get(get_customer_registration_page).post(post_customer_registration_page),
         )
         .route("/login", get(get_customer_login_page))
+        .route(
+            "/forgot-password",
+            get(get_forgot_password_page).post(post_forgot_password_page),
+        )
+        .route(
+            "/reset-password/{token}",
+            get(get_reset_password_page).post(post_reset_password_page),
+        )
         .layer(middleware::from_fn(redirect_if_authed));
 
+    // provider callbacks carry a signature instead of a session and a csrf token
+    let webhook_routes = Router::new()
+        .route("/payments/webhook", post(post_payment_webhook))
+        .layer(Extension(pool.clone()));
+
     Router::new()
         .route("/", get(home))
         .route("/about", get(about))
+        .route("/contact", get(get_contact_page).post(post_contact_page))
         .route("/logout", get(logout_customer))
         .route("/products", get(get_products))
         .route(
@@ -45,16 +146,22 @@ pub fn create_router(
             get(get_products_by_category_name),
         )
         .route("/product/{code}", get(get_product_by_code))
-        .route("/order/{order_uuid}", get(get_order_by_uuid_and_customer))
+        .route("/verify-email/{token}", get(get_verify_email_page))
         .route("/login", post(post_customer_login_page))
-        // .route("/order", post())
+        .route("/currency", post(post_select_currency))
         .merge(auth_routes)
+        .merge(staff_routes)
         .merge(non_auth_routes)
+        .fallback(not_found)
         .layer((
             Extension(pool),
             Extension(signing_key),
             middleware::from_fn(optional_customer),
+            middleware::from_fn_with_state(state.clone(), render_error_page),
+            middleware::from_fn(csrf_protect),
+            middleware::from_fn(select_currency),
         ))
-        .with_state(Arc::new(AppState { tpl_env: env }))
+        .merge(webhook_routes)
+        .with_state(state)
         .nest_service("/static", static_files) // pass it via nginx on production
 }
@@ -1,37 +1,141 @@
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
 use simple_cookie::{SigningKey, decode_cookie, encode_cookie};
 
+pub const SESSION_TTL_SECONDS: i64 = 86400;
+const SESSION_COOKIE_NAME: &str = "PHPSESSID";
+const CSRF_COOKIE_NAME: &str = "csrf_token";
+const CURRENCY_COOKIE_NAME: &str = "currency";
+const SESSION_TOKEN_BYTES: usize = 32;
+
 pub struct AuthService;
 
 impl AuthService {
-    pub fn create_cookie_header(customer_id: i64, signing_key: &SigningKey) -> String {
-        let encoded = encode_cookie(
-            *signing_key,
-            "customer_id",
-            customer_id.to_le_bytes().to_vec(),
-        );
-
-        let cookie_value = format!(
-            "PHPSESSID={}; HttpOnly; Secure; SameSite=Strict; Max-Age=86400",
-            encoded
-        );
-        cookie_value
-    }
-    pub fn parse_cookie_value(cookie_value: &str, signing_key: SigningKey) -> Result<i64, String> {
-        // cookie_value = "PHPSESSID=fedkhbbkiagplcgmamicbhlgankcjgbdimhbpjifchimbbhihbbfpcdbdkebedkp"
-        let cookie_pairs = cookie_value.split(';').next().unwrap();
-
-        if let Some((name, value)) = cookie_pairs.split_once("=")
-            && name.trim() == "PHPSESSID"
-            && let Ok(decoded) = decode_cookie(signing_key, "customer_id", value)
-        {
-            return if decoded.len() == 8 {
-                let bytes: [u8; 8] = decoded.try_into().unwrap();
-                let customer_id = i64::from_le_bytes(bytes);
-                Ok(customer_id)
-            } else {
-                Err("Failed to decode cookie".to_string())
-            };
-        }
-        Err("Invalid cookie format".to_string())
+    // opaque random token, only its hash is stored (sessions, password resets)
+    pub fn generate_token() -> String {
+        let mut bytes = [0u8; SESSION_TOKEN_BYTES];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        hex::encode(bytes)
+    }
+
+    pub fn hash_token(token: &str) -> String {
+        hex::encode(Sha256::digest(token.as_bytes()))
+    }
+
+    pub fn create_cookie_header(session_token: &str, signing_key: &SigningKey) -> String {
+        let encoded = encode_cookie(*signing_key, "session_token", session_token.as_bytes());
+
+        format!(
+            "{}={}; HttpOnly; Secure; SameSite=Strict; Max-Age={}",
+            SESSION_COOKIE_NAME, encoded, SESSION_TTL_SECONDS
+        )
+    }
+
+    pub fn clear_cookie_header() -> String {
+        format!(
+            "{}=; HttpOnly; Secure; SameSite=Strict; Max-Age=0; expires=Thu, 01 Jan 1970 00:00:00 GMT",
+            SESSION_COOKIE_NAME
+        )
+    }
+
+    // the csrf token of a signed-in request: bound to its session, so a new one comes with every
+    // login and it stops working with logout or revocation
+    pub fn session_csrf_token(session_id: i64, signing_key: &SigningKey) -> String {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(signing_key).expect("HMAC accepts keys of any length");
+        mac.update(format!("csrf:{}", session_id).as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    // guests (login, registration, contact form) get a random token in a cookie instead;
+    // no Max-Age: lives as long as the browser session
+    pub fn create_csrf_cookie_header(csrf_token: &str, signing_key: &SigningKey) -> String {
+        let encoded = encode_cookie(*signing_key, CSRF_COOKIE_NAME, csrf_token.as_bytes());
+        format!(
+            "{}={}; Path=/; HttpOnly; Secure; SameSite=Strict",
+            CSRF_COOKIE_NAME, encoded
+        )
+    }
+
+    // sent with every login and logout, the next guest page gets a fresh token
+    pub fn clear_csrf_cookie_header() -> String {
+        format!(
+            "{}=; Path=/; HttpOnly; Secure; SameSite=Strict; Max-Age=0; expires=Thu, 01 Jan 1970 00:00:00 GMT",
+            CSRF_COOKIE_NAME
+        )
+    }
+
+    // display currency, like the csrf cookie it lasts for the browser session
+    pub fn create_currency_cookie_header(currency_code: &str, signing_key: &SigningKey) -> String {
+        let encoded = encode_cookie(*signing_key, CURRENCY_COOKIE_NAME, currency_code.as_bytes());
+        format!(
+            "{}={}; Path=/; HttpOnly; Secure; SameSite=Lax",
+            CURRENCY_COOKIE_NAME, encoded
+        )
+    }
+
+    pub fn parse_cookie_value(
+        cookie_value: &str,
+        signing_key: SigningKey,
+    ) -> Result<String, String> {
+        // cookie_value = "PHPSESSID=fedkhbbkiagplcgmamicbhlgankcjgbdimhbpjifchimbbhihbbfpcdbdkebedkp; other=1"
+        Self::decode_signed_cookie(
+            cookie_value,
+            SESSION_COOKIE_NAME,
+            "session_token",
+            signing_key,
+        )
+    }
+
+    pub fn parse_csrf_cookie_value(
+        cookie_value: &str,
+        signing_key: SigningKey,
+    ) -> Result<String, String> {
+        Self::decode_signed_cookie(
+            cookie_value,
+            CSRF_COOKIE_NAME,
+            CSRF_COOKIE_NAME,
+            signing_key,
+        )
+    }
+
+    pub fn parse_currency_cookie_value(
+        cookie_value: &str,
+        signing_key: SigningKey,
+    ) -> Result<String, String> {
+        Self::decode_signed_cookie(
+            cookie_value,
+            CURRENCY_COOKIE_NAME,
+            CURRENCY_COOKIE_NAME,
+            signing_key,
+        )
+    }
+
+    fn decode_signed_cookie(
+        cookie_value: &str,
+        cookie_name: &str,
+        signed_name: &str,
+        signing_key: SigningKey,
+    ) -> Result<String, String> {
+        let value = cookie_value
+            .split(';')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(name, _)| name.trim() == cookie_name)
+            .map(|(_, value)| value.trim())
+            .ok_or_else(|| "Invalid cookie format".to_string())?;
+
+        let decoded = decode_cookie(signing_key, signed_name, value)
+            .map_err(|_| "Failed to decode cookie".to_string())?;
+        String::from_utf8(decoded).map_err(|_| "Failed to decode cookie".to_string())
+    }
+
+    // compares every byte so the response time doesn't leak how much of a token matched
+    pub fn tokens_match(a: &str, b: &str) -> bool {
+        a.len() == b.len()
+            && a.bytes()
+                .zip(b.bytes())
+                .fold(0u8, |diff, (x, y)| diff | (x ^ y))
+                == 0
     }
 }
@@ -0,0 +1,23 @@
+use crate::models::money::CurrencyMismatch;
+use crate::models::order::OrderDetails;
+use crate::models::shipping::ShippingRate;
+use crate::models::tax::TaxRates;
+use crate::services::taxes::Taxes;
+
+pub struct Checkout;
+
+impl Checkout {
+    // fills in shipping, tax and total of a draft order; shipping is priced on the goods after
+    // the coupon, tax comes from the rates of the shipping country (see `Taxes::apply_to_order`)
+    pub fn price_draft(
+        order: &mut OrderDetails,
+        shipping: Option<&ShippingRate>,
+        tax_rates: &TaxRates,
+    ) -> Result<(), CurrencyMismatch> {
+        let goods_total = order.goods_total()?;
+        order.shipping_method = shipping.map(|rate| rate.name.clone());
+        order.shipping_cost = shipping.map(|rate| rate.cost(goods_total, &order.exchange_rate()));
+        Taxes::apply_to_order(order, tax_rates)?;
+        order.update_total()
+    }
+}
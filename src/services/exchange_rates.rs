@@ -0,0 +1,60 @@
+use crate::models::money::{BASE_CURRENCY, Currency, ExchangeRate};
+use crate::repository::exchange_rate_repository::ExchangeRateRepository;
+use rust_decimal::Decimal;
+use sqlx::PgPool;
+
+pub struct ExchangeRateImport;
+
+impl ExchangeRateImport {
+    // `currency,rate` per line, e.g. `EUR,0.9215`; a header line and blank lines are skipped
+    pub fn parse_csv(content: &str) -> Result<Vec<ExchangeRate>, String> {
+        let mut rates = Vec::new();
+        for (index, line) in content.lines().enumerate() {
+            let line_no = index + 1;
+            let line = line.trim();
+            if line.is_empty() || (index == 0 && line.to_ascii_lowercase().starts_with("currency"))
+            {
+                continue;
+            }
+            let Some((code, rate)) = line.split_once(',') else {
+                return Err(format!("line {}: expected `currency,rate`", line_no));
+            };
+            let currency = Currency::from_code(code)
+                .ok_or_else(|| format!("line {}: unknown currency {:?}", line_no, code.trim()))?;
+            if currency == BASE_CURRENCY {
+                return Err(format!(
+                    "line {}: {} is the base currency, its rate is always 1",
+                    line_no, currency
+                ));
+            }
+            // exchange_rates.rate is NUMERIC(18, 8), anything finer would be rounded silently
+            let rate = rate
+                .trim()
+                .parse::<Decimal>()
+                .ok()
+                .filter(|rate| {
+                    rate.is_sign_positive()
+                        && !rate.is_zero()
+                        && rate.normalize().scale() <= 8
+                        && *rate < Decimal::from(10_000_000_000i64)
+                })
+                .ok_or_else(|| format!("line {}: invalid rate {:?}", line_no, rate.trim()))?;
+            rates.push(ExchangeRate { currency, rate });
+        }
+        if rates.is_empty() {
+            return Err("no exchange rates found".to_string());
+        }
+        Ok(rates)
+    }
+
+    // `test_shop_rust import-exchange-rates rates.csv`, the whole file is imported or nothing
+    pub async fn import_file(pool: &PgPool, path: &str) -> Result<usize, String> {
+        let content =
+            std::fs::read_to_string(path).map_err(|e| format!("can not read {}: {}", path, e))?;
+        let rates = Self::parse_csv(&content)?;
+        ExchangeRateRepository::import_rates(pool, &rates)
+            .await
+            .map_err(|e| format!("can not save exchange rates: {}", e))?;
+        Ok(rates.len())
+    }
+}
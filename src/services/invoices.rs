@@ -0,0 +1,402 @@
+use crate::models::address::AddressSnapshot;
+use crate::models::country::country_name;
+use crate::models::invoice::Invoice;
+use crate::models::money::{CurrencyMismatch, Money};
+use crate::models::order::{OrderDetails, OrderStatus};
+use crate::repository::invoice_repository::InvoiceRepository;
+use crate::repository::order_repository::{OrderError, OrderRepository};
+use printpdf::{
+    IndirectFontRef, Line, Mm, PdfDocument, PdfDocumentReference, PdfLayerReference, Point,
+};
+use rust_decimal::Decimal;
+use sqlx::PgPool;
+use ttf_parser::Face;
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub enum InvoiceError {
+    Database(sqlx::Error),
+    OrderNotFound,
+    NotInvoiceable(OrderStatus),
+    Pdf(printpdf::Error),
+    Currency(CurrencyMismatch),
+}
+
+impl std::fmt::Display for InvoiceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InvoiceError::Database(e) => write!(f, "Database error occurred: {}", e),
+            InvoiceError::OrderNotFound => write!(f, "Order not found"),
+            InvoiceError::NotInvoiceable(status) => write!(
+                f,
+                "The invoice is issued once the order is paid, this one is {}",
+                status
+            ),
+            InvoiceError::Pdf(e) => write!(f, "Rendering the invoice failed: {}", e),
+            InvoiceError::Currency(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for InvoiceError {}
+
+impl From<sqlx::Error> for InvoiceError {
+    fn from(e: sqlx::Error) -> Self {
+        InvoiceError::Database(e)
+    }
+}
+
+impl From<OrderError> for InvoiceError {
+    fn from(e: OrderError) -> Self {
+        match e {
+            OrderError::Database(e) => InvoiceError::Database(e),
+            OrderError::Currency(e) => InvoiceError::Currency(e),
+            _ => InvoiceError::OrderNotFound,
+        }
+    }
+}
+
+impl From<printpdf::Error> for InvoiceError {
+    fn from(e: printpdf::Error) -> Self {
+        InvoiceError::Pdf(e)
+    }
+}
+
+impl From<CurrencyMismatch> for InvoiceError {
+    fn from(e: CurrencyMismatch) -> Self {
+        InvoiceError::Currency(e)
+    }
+}
+
+// A4, in mm from the bottom left corner
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const LEFT: f32 = 15.0;
+const RIGHT: f32 = 195.0;
+const TOP: f32 = 280.0;
+const BOTTOM: f32 = 20.0;
+
+// right edges of the line table columns
+const QUANTITY_X: f32 = 120.0;
+const UNIT_PRICE_X: f32 = 145.0;
+const TAX_RATE_X: f32 = 162.0;
+
+// embedded in every invoice, subset to the glyphs it uses: names and addresses can be in any
+// script, the PDF built-in fonts only cover Windows-1252
+const REGULAR_FONT: &[u8] = include_bytes!("../../assets/fonts/DejaVuSans.ttf");
+const BOLD_FONT: &[u8] = include_bytes!("../../assets/fonts/DejaVuSans-Bold.ttf");
+
+pub struct Invoices;
+
+impl Invoices {
+    // paid orders get their invoice with the payment, the ones paid before invoices existed get
+    // it on the first download; a cancelled or refunded order keeps the invoice it had
+    pub async fn for_order(
+        pool: &PgPool,
+        order: &OrderDetails,
+        customer_id: i64,
+    ) -> Result<Invoice, InvoiceError> {
+        let order_id = Uuid::parse_str(&order.order_id).map_err(|_| InvoiceError::OrderNotFound)?;
+        if let Some(invoice) = InvoiceRepository::find_for_order(pool, order_id).await? {
+            return Ok(invoice);
+        }
+        if !matches!(
+            order.status,
+            OrderStatus::Paid
+                | OrderStatus::Shipped
+                | OrderStatus::Delivered
+                | OrderStatus::Refunded
+        ) {
+            return Err(InvoiceError::NotInvoiceable(order.status));
+        }
+
+        let mut tx = pool.begin().await?;
+        OrderRepository::lock_order(&mut tx, order_id, customer_id).await?;
+        let invoice = InvoiceRepository::issue(&mut tx, order_id).await?;
+        tx.commit().await?;
+        tracing::info!("Invoice {} issued for order {}", invoice.number, order_id);
+        Ok(invoice)
+    }
+
+    // `issuer` are the lines of the shop's address block (INVOICE_ISSUER), `locale` formats the
+    // amounts like the `money` filter does
+    pub fn render_pdf(
+        invoice: &Invoice,
+        order: &OrderDetails,
+        issuer: &[String],
+        locale: &str,
+    ) -> Result<Vec<u8>, InvoiceError> {
+        let money = |amount: Money| amount.format(locale);
+        let (doc, page, layer) = PdfDocument::new(
+            format!("Invoice {}", invoice.number),
+            Mm(PAGE_WIDTH),
+            Mm(PAGE_HEIGHT),
+            "Invoice",
+        );
+        let mut pdf = PdfWriter {
+            layer: doc.get_page(page).get_layer(layer),
+            regular: doc.add_external_font(REGULAR_FONT)?,
+            bold: doc.add_external_font(BOLD_FONT)?,
+            regular_face: Face::parse(REGULAR_FONT, 0).map_err(printpdf::Error::FaceParsing)?,
+            bold_face: Face::parse(BOLD_FONT, 0).map_err(printpdf::Error::FaceParsing)?,
+            doc,
+            y: TOP,
+        };
+
+        // issuer on the left, invoice data on the right
+        for (i, line) in issuer.iter().enumerate() {
+            pdf.text(line, if i == 0 { 12.0 } else { 9.0 }, LEFT, i == 0);
+            pdf.down(if i == 0 { 6.0 } else { 4.5 });
+        }
+        let issuer_bottom = pdf.y;
+        pdf.y = TOP;
+        pdf.text("INVOICE", 18.0, 120.0, true);
+        pdf.down(8.0);
+        for (label, value) in [
+            ("Invoice no.", invoice.number.clone()),
+            (
+                "Invoice date",
+                invoice.issued_at.format("%Y-%m-%d").to_string(),
+            ),
+            (
+                "Order date",
+                order.created_at.format("%Y-%m-%d").to_string(),
+            ),
+            ("Order", order.order_id.clone()),
+        ] {
+            pdf.text(label, 8.0, 120.0, false);
+            pdf.text(&value, 8.0, 142.0, false);
+            pdf.down(4.5);
+        }
+        pdf.y = pdf.y.min(issuer_bottom) - 10.0;
+
+        // billing and shipping address
+        let address_top = pdf.y;
+        let billing = order
+            .billing_address
+            .as_ref()
+            .or(order.shipping_address.as_ref());
+        if let Some(address) = billing {
+            pdf.address("Bill to", address, LEFT);
+        }
+        let address_bottom = pdf.y;
+        if let Some(address) = &order.shipping_address {
+            pdf.y = address_top;
+            pdf.address("Ship to", address, 110.0);
+        }
+        pdf.y = pdf.y.min(address_bottom) - 10.0;
+
+        // the lines
+        pdf.table_header();
+        for line in &order.lines {
+            if pdf.y - 9.0 < BOTTOM {
+                pdf.new_page();
+                pdf.table_header();
+            }
+            pdf.text(&truncate(&line.product_name, 48), 9.0, LEFT, false);
+            pdf.text_right(&line.quantity.to_string(), 9.0, QUANTITY_X, false);
+            pdf.text_right(&money(line.unit_price), 9.0, UNIT_PRICE_X, false);
+            if let Some(tax_rate) = line.tax_rate {
+                pdf.text_right(&format!("{}%", tax_rate), 9.0, TAX_RATE_X, false);
+            }
+            pdf.text_right(&money(line.line_total), 9.0, RIGHT, false);
+            pdf.down(4.0);
+            let detail = match (&line.sku, &line.size, &line.colour) {
+                (Some(sku), Some(size), Some(colour)) => {
+                    format!("Size {}, {} - SKU {}", size, colour, sku)
+                }
+                _ => line.product_code.clone(),
+            };
+            pdf.text(&detail, 7.5, LEFT, false);
+            pdf.down(5.5);
+        }
+        pdf.rule();
+        pdf.down(6.0);
+
+        // totals; the tax is broken down by rate where the lines carry it
+        let mut totals = vec![("Subtotal".to_string(), money(order.subtotal))];
+        if let Some(discount) = order.discount {
+            totals.push((
+                format!(
+                    "Coupon {}",
+                    order.promotion_code.as_deref().unwrap_or_default()
+                ),
+                money(-discount),
+            ));
+        }
+        if let Some(shipping_cost) = order.shipping_cost {
+            totals.push((
+                format!(
+                    "Shipping {}",
+                    order.shipping_method.as_deref().unwrap_or_default()
+                ),
+                money(shipping_cost),
+            ));
+        }
+        let tax_label = match &order.tax_country {
+            Some(country) => format!("Tax ({})", country),
+            None => "Tax".to_string(),
+        };
+        let mut rates: Vec<(Decimal, Money)> = Vec::new();
+        for line in &order.lines {
+            if let (Some(rate), Some(tax)) = (line.tax_rate, line.tax) {
+                match rates.iter_mut().find(|(known, _)| *known == rate) {
+                    Some((_, total)) => *total = total.checked_add(tax)?,
+                    None => rates.push((rate, tax)),
+                }
+            }
+        }
+        if !rates.is_empty() {
+            rates.sort_by_key(|(rate, _)| *rate);
+            for (rate, tax) in rates {
+                totals.push((format!("{} {}% on goods", tax_label, rate), money(tax)));
+            }
+            if let Some(shipping_tax) = order.shipping_tax.filter(|tax| tax.amount != 0) {
+                totals.push((format!("{} on shipping", tax_label), money(shipping_tax)));
+            }
+        } else if let Some(tax) = order.tax.filter(|tax| tax.amount != 0) {
+            totals.push((tax_label, money(tax)));
+        }
+        for (label, amount) in totals {
+            pdf.ensure_space(5.0);
+            pdf.text(&label, 9.0, 110.0, false);
+            pdf.text_right(&amount, 9.0, RIGHT, false);
+            pdf.down(5.0);
+        }
+        pdf.ensure_space(8.0);
+        pdf.down(1.0);
+        pdf.text("Total", 11.0, 110.0, true);
+        pdf.text_right(&money(order.total), 11.0, RIGHT, true);
+        pdf.down(12.0);
+
+        pdf.ensure_space(5.0);
+        pdf.text(
+            &format!("Amounts in {}. Thank you for your order.", order.currency),
+            8.0,
+            LEFT,
+            false,
+        );
+
+        Ok(pdf.doc.save_to_bytes()?)
+    }
+}
+
+// a cursor over the pages, `y` is the baseline of the next line
+struct PdfWriter {
+    doc: PdfDocumentReference,
+    layer: PdfLayerReference,
+    regular: IndirectFontRef,
+    bold: IndirectFontRef,
+    regular_face: Face<'static>, // glyphs and widths of the fonts above
+    bold_face: Face<'static>,
+    y: f32,
+}
+
+impl PdfWriter {
+    fn text(&self, text: &str, size: f32, x: f32, bold: bool) {
+        let font = if bold { &self.bold } else { &self.regular };
+        let text = self.printable(text, bold);
+        self.layer.use_text(text, size, Mm(x), Mm(self.y), font);
+    }
+
+    fn text_right(&self, text: &str, size: f32, right: f32, bold: bool) {
+        self.text(text, size, right - self.text_width(text, size, bold), bold);
+    }
+
+    fn face(&self, bold: bool) -> &Face<'static> {
+        if bold {
+            &self.bold_face
+        } else {
+            &self.regular_face
+        }
+    }
+
+    // characters the font has no glyph for print as U+FFFD instead of an empty box
+    fn printable(&self, text: &str, bold: bool) -> String {
+        let face = self.face(bold);
+        text.chars()
+            .map(|ch| match face.glyph_index(ch) {
+                Some(_) => ch,
+                None => char::REPLACEMENT_CHARACTER,
+            })
+            .collect()
+    }
+
+    // in mm, from the advance widths of the font
+    fn text_width(&self, text: &str, size: f32, bold: bool) -> f32 {
+        let face = self.face(bold);
+        let units: u32 = self
+            .printable(text, bold)
+            .chars()
+            .filter_map(|ch| face.glyph_hor_advance(face.glyph_index(ch)?))
+            .map(u32::from)
+            .sum();
+        // points to mm
+        units as f32 / face.units_per_em() as f32 * size * 25.4 / 72.0
+    }
+
+    fn down(&mut self, mm: f32) {
+        self.y -= mm;
+    }
+
+    fn ensure_space(&mut self, mm: f32) {
+        if self.y - mm < BOTTOM {
+            self.new_page();
+        }
+    }
+
+    fn new_page(&mut self) {
+        let (page, layer) = self
+            .doc
+            .add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Invoice");
+        self.layer = self.doc.get_page(page).get_layer(layer);
+        self.y = TOP;
+    }
+
+    fn rule(&self) {
+        self.layer.set_outline_thickness(0.5);
+        self.layer.add_line(Line {
+            points: vec![
+                (Point::new(Mm(LEFT), Mm(self.y)), false),
+                (Point::new(Mm(RIGHT), Mm(self.y)), false),
+            ],
+            is_closed: false,
+        });
+    }
+
+    fn table_header(&mut self) {
+        self.text("Product", 9.0, LEFT, true);
+        self.text_right("Qty", 9.0, QUANTITY_X, true);
+        self.text_right("Unit price", 9.0, UNIT_PRICE_X, true);
+        self.text_right("Tax", 9.0, TAX_RATE_X, true);
+        self.text_right("Amount", 9.0, RIGHT, true);
+        self.down(2.0);
+        self.rule();
+        self.down(5.0);
+    }
+
+    fn address(&mut self, title: &str, address: &AddressSnapshot, x: f32) {
+        self.text(title, 8.0, x, true);
+        self.down(4.5);
+        let mut lines = vec![address.full_name.clone(), address.line1.clone()];
+        lines.extend(address.line2.clone());
+        lines.push(format!("{} {}", address.postal_code, address.city));
+        // the ISO code is stored, the invoice spells it out
+        lines.push(
+            country_name(&address.country).map_or_else(|| address.country.clone(), String::from),
+        );
+        for line in lines {
+            self.text(&line, 9.0, x, false);
+            self.down(4.5);
+        }
+    }
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(max_chars - 3).collect();
+    truncated.push_str("...");
+    truncated
+}
@@ -0,0 +1,149 @@
+use crate::models::session::RecentFailures;
+use serde::Serialize;
+
+// failures older than this are forgotten
+pub const FAILURE_WINDOW_SECONDS: f64 = 15.0 * 60.0;
+const MAX_BACKOFF_SECONDS: f64 = 5.0 * 60.0;
+
+struct ThrottlePolicy {
+    free_attempts: i64, // failures allowed before any delay
+    lockout_after: i64,
+    lockout_seconds: f64,
+}
+
+const EMAIL_POLICY: ThrottlePolicy = ThrottlePolicy {
+    free_attempts: 3,
+    lockout_after: 10,
+    lockout_seconds: 15.0 * 60.0,
+};
+
+// an office or a mobile carrier can share one address, so the ip gets more room
+const IP_POLICY: ThrottlePolicy = ThrottlePolicy {
+    free_attempts: 10,
+    lockout_after: 50,
+    lockout_seconds: 15.0 * 60.0,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct LoginBlock {
+    pub retry_after_seconds: u64,
+    pub is_account_locked: bool,
+}
+
+pub struct LoginThrottle;
+
+impl LoginThrottle {
+    // None when the attempt may go on to the password check
+    pub fn check(email: RecentFailures, ip: RecentFailures) -> Option<LoginBlock> {
+        let by_email =
+            Self::evaluate(email, &EMAIL_POLICY).map(|(retry_after_seconds, locked)| LoginBlock {
+                retry_after_seconds,
+                is_account_locked: locked,
+            });
+        let by_ip = Self::evaluate(ip, &IP_POLICY).map(|(retry_after_seconds, _)| LoginBlock {
+            retry_after_seconds,
+            is_account_locked: false,
+        });
+
+        match (by_email, by_ip) {
+            (Some(email), Some(ip)) if ip.retry_after_seconds > email.retry_after_seconds => {
+                Some(LoginBlock {
+                    is_account_locked: email.is_account_locked,
+                    ..ip
+                })
+            }
+            (Some(email), _) => Some(email),
+            (None, ip) => ip,
+        }
+    }
+
+    // exponential backoff (1s, 2s, 4s, ...) after the free attempts, a flat lockout after `lockout_after`
+    fn evaluate(failures: RecentFailures, policy: &ThrottlePolicy) -> Option<(u64, bool)> {
+        if failures.count < policy.free_attempts {
+            return None;
+        }
+        let is_locked = failures.count >= policy.lockout_after;
+        let wait = if is_locked {
+            policy.lockout_seconds
+        } else {
+            let exponent = (failures.count - policy.free_attempts) as i32;
+            2f64.powi(exponent).min(MAX_BACKOFF_SECONDS)
+        };
+        let since_last = failures.seconds_since_last.unwrap_or(f64::INFINITY);
+        let remaining = wait - since_last;
+        (remaining > 0.0).then(|| (remaining.ceil() as u64, is_locked))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failures(count: i64, seconds_since_last: f64) -> RecentFailures {
+        RecentFailures {
+            count,
+            seconds_since_last: Some(seconds_since_last),
+        }
+    }
+
+    fn retry_after(email: RecentFailures) -> Option<u64> {
+        LoginThrottle::check(email, RecentFailures::default())
+            .map(|block| block.retry_after_seconds)
+    }
+
+    #[test]
+    fn free_attempts_are_not_delayed() {
+        assert_eq!(
+            LoginThrottle::check(RecentFailures::default(), RecentFailures::default()),
+            None
+        );
+        assert_eq!(retry_after(failures(2, 0.0)), None);
+    }
+
+    #[test]
+    fn backoff_doubles_after_the_free_attempts() {
+        assert_eq!(retry_after(failures(3, 0.0)), Some(1));
+        assert_eq!(retry_after(failures(4, 0.0)), Some(2));
+        assert_eq!(retry_after(failures(5, 0.0)), Some(4));
+        assert_eq!(retry_after(failures(9, 0.0)), Some(64));
+        // the wait counts from the last failure and is rounded up
+        assert_eq!(retry_after(failures(5, 2.5)), Some(2));
+        assert_eq!(retry_after(failures(5, 4.0)), None);
+    }
+
+    #[test]
+    fn ip_backoff_is_capped() {
+        let block = LoginThrottle::check(RecentFailures::default(), failures(49, 0.0)).unwrap();
+        assert_eq!(block.retry_after_seconds, MAX_BACKOFF_SECONDS as u64);
+        assert!(!block.is_account_locked);
+    }
+
+    #[test]
+    fn account_locks_at_the_threshold() {
+        let block = LoginThrottle::check(failures(10, 60.0), RecentFailures::default()).unwrap();
+        assert!(block.is_account_locked);
+        assert_eq!(block.retry_after_seconds, 14 * 60);
+        assert_eq!(retry_after(failures(10, 15.0 * 60.0)), None);
+        // a busy ip never locks the account, it only delays
+        let block = LoginThrottle::check(RecentFailures::default(), failures(50, 0.0)).unwrap();
+        assert!(!block.is_account_locked);
+        assert_eq!(block.retry_after_seconds, 15 * 60);
+    }
+
+    #[test]
+    fn longer_ip_wait_keeps_the_account_lock() {
+        let block = LoginThrottle::check(failures(4, 0.0), failures(50, 0.0)).unwrap();
+        assert_eq!(block.retry_after_seconds, 15 * 60);
+        assert!(!block.is_account_locked);
+        let block = LoginThrottle::check(failures(10, 0.0), failures(12, 0.0)).unwrap();
+        assert_eq!(block.retry_after_seconds, 15 * 60);
+        assert!(block.is_account_locked);
+    }
+
+    #[test]
+    fn success_resets_the_count() {
+        // failures are only counted since the last successful login, which leaves none
+        assert!(retry_after(failures(9, 0.0)).is_some());
+        assert_eq!(retry_after(RecentFailures::default()), None);
+    }
+}
@@ -0,0 +1,81 @@
+use chrono::Utc;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use tokio::fs;
+
+#[derive(Debug, Clone)]
+pub struct EmailMessage {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug)]
+pub struct MailError(pub String);
+
+impl std::fmt::Display for MailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Failed to send email: {}", self.0)
+    }
+}
+
+impl std::error::Error for MailError {}
+
+pub type MailFuture<'a> = Pin<Box<dyn Future<Output = Result<(), MailError>> + Send + 'a>>;
+
+// plug a real SMTP/API sender in here for production; sending runs inside request handlers, so
+// it must not block the runtime
+pub trait Mailer: Send + Sync + std::fmt::Debug {
+    fn send<'a>(&'a self, message: &'a EmailMessage) -> MailFuture<'a>;
+}
+
+// local development stand-in: every message becomes a .eml file in `dir` and a log line
+#[derive(Debug)]
+pub struct FileMailer {
+    dir: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        FileMailer { dir: dir.into() }
+    }
+
+    async fn write(&self, message: &EmailMessage) -> Result<(), MailError> {
+        fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| MailError(e.to_string()))?;
+
+        let now = Utc::now();
+        let file_name = format!(
+            "{}-{}.eml",
+            now.format("%Y%m%d%H%M%S%f"),
+            message.to.replace(['@', '/', '\\'], "_")
+        );
+        let path = self.dir.join(file_name);
+        let content = format!(
+            "To: {}\r\nSubject: {}\r\nDate: {}\r\n\r\n{}\r\n",
+            message.to,
+            message.subject,
+            now.to_rfc2822(),
+            message.body
+        );
+        fs::write(&path, content)
+            .await
+            .map_err(|e| MailError(e.to_string()))?;
+
+        tracing::info!(
+            "Email \"{}\" to {} written to {}",
+            message.subject,
+            message.to,
+            path.display()
+        );
+        Ok(())
+    }
+}
+
+impl Mailer for FileMailer {
+    fn send<'a>(&'a self, message: &'a EmailMessage) -> MailFuture<'a> {
+        Box::pin(self.write(message))
+    }
+}
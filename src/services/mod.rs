@@ -1 +1,11 @@
 pub mod auth;
+pub mod validation;
+pub mod mailer;
+pub mod login_throttle;
+pub mod exchange_rates;
+pub mod promotions;
+pub mod checkout;
+pub mod payments;
+pub mod refunds;
+pub mod taxes;
+pub mod invoices;
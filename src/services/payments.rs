@@ -0,0 +1,696 @@
+use crate::models::money::{CurrencyMismatch, Money};
+use crate::models::order::{OrderActor, OrderStatus};
+use crate::models::payment::{Payment, PaymentStatus, PendingRefund, RefundablePayment};
+use crate::repository::invoice_repository::InvoiceRepository;
+use crate::repository::order_repository::{OrderError, OrderRepository};
+use crate::repository::payment_repository::PaymentRepository;
+use axum::http::HeaderMap;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::pin::Pin;
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub enum PaymentError {
+    Database(sqlx::Error),
+    OrderNotFound,
+    NotPayable,       // already paid, cancelled, ...
+    InProgress,       // another attempt is waiting for the provider
+    Declined(String), // the provider's reason, shown to the customer
+    Provider(String),
+    InvalidSignature,
+    InvalidEvent(String),
+    UnknownPayment,
+    RefundTooLarge { refundable: Money },
+    Currency(CurrencyMismatch),
+}
+
+impl std::fmt::Display for PaymentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PaymentError::Database(e) => write!(f, "Database error occurred: {}", e),
+            PaymentError::OrderNotFound => write!(f, "Order not found"),
+            PaymentError::NotPayable => write!(f, "This order can not be paid anymore"),
+            PaymentError::InProgress => write!(
+                f,
+                "A payment for this order is being processed, check the order in a few minutes"
+            ),
+            PaymentError::Declined(reason) => write!(f, "{}", reason),
+            PaymentError::Provider(e) => write!(f, "Payment provider error: {}", e),
+            PaymentError::InvalidSignature => write!(f, "Invalid webhook signature"),
+            PaymentError::InvalidEvent(e) => write!(f, "Invalid webhook event: {}", e),
+            PaymentError::UnknownPayment => write!(f, "Unknown payment"),
+            PaymentError::RefundTooLarge { refundable } => {
+                write!(
+                    f,
+                    "The refund must be more than zero and at most {}",
+                    refundable
+                )
+            }
+            PaymentError::Currency(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for PaymentError {}
+
+impl From<sqlx::Error> for PaymentError {
+    fn from(e: sqlx::Error) -> Self {
+        PaymentError::Database(e)
+    }
+}
+
+impl From<OrderError> for PaymentError {
+    fn from(e: OrderError) -> Self {
+        match e {
+            OrderError::Database(e) => PaymentError::Database(e),
+            OrderError::NotFound => PaymentError::OrderNotFound,
+            OrderError::IllegalTransition { .. } | OrderError::OutOfStock { .. } => {
+                PaymentError::NotPayable
+            }
+            OrderError::Currency(e) => PaymentError::Currency(e),
+        }
+    }
+}
+
+impl From<CurrencyMismatch> for PaymentError {
+    fn from(e: CurrencyMismatch) -> Self {
+        PaymentError::Currency(e)
+    }
+}
+
+#[derive(Debug)]
+pub struct PaymentRequest<'a> {
+    pub order_id: Uuid,
+    pub amount: Money,
+    pub card_number: &'a str, // a real provider gets a token from its card form instead
+}
+
+#[derive(Debug)]
+pub struct Authorization {
+    pub reference: String, // the provider's id of the payment
+}
+
+#[derive(Debug)]
+pub enum PaymentEventKind {
+    Succeeded,
+    Failed { reason: String },
+}
+
+impl PaymentEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PaymentEventKind::Succeeded => "payment.succeeded",
+            PaymentEventKind::Failed { .. } => "payment.failed",
+        }
+    }
+}
+
+// a verified webhook call
+#[derive(Debug)]
+pub struct PaymentEvent {
+    pub event_id: String, // the same event can be delivered more than once
+    pub reference: String,
+    pub kind: PaymentEventKind,
+}
+
+pub type GatewayFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, PaymentError>> + Send + 'a>>;
+
+// plug a real payment provider in here for production; the calls run inside request handlers,
+// so they must not block the runtime, and no database lock is held while they wait
+pub trait PaymentGateway: Send + Sync + std::fmt::Debug {
+    // stored with every payment, and the actor of the status changes it causes
+    fn name(&self) -> &'static str;
+    // reserves the amount on the card, `PaymentError::Declined` when the card is refused
+    fn authorize<'a>(&'a self, request: &'a PaymentRequest<'a>)
+    -> GatewayFuture<'a, Authorization>;
+    fn capture<'a>(&'a self, reference: &'a str, amount: Money) -> GatewayFuture<'a, ()>;
+    // returns the provider's id of the refund; every attempt of one refund sends the same
+    // `idempotency_key`, so a retry after a lost answer doesn't pay out twice
+    fn refund<'a>(
+        &'a self,
+        reference: &'a str,
+        amount: Money,
+        idempotency_key: &'a str,
+    ) -> GatewayFuture<'a, String>;
+    fn verify_webhook<'a>(
+        &'a self,
+        headers: &'a HeaderMap,
+        body: &'a [u8],
+    ) -> GatewayFuture<'a, PaymentEvent>;
+}
+
+pub const MOCK_SIGNATURE_HEADER: &str = "x-mock-signature";
+
+// test cards of the mock, any other number that passes the Luhn check is accepted
+const MOCK_DECLINED_CARD: &str = "4000000000000002";
+const MOCK_INSUFFICIENT_FUNDS_CARD: &str = "4000000000009995";
+
+// local development stand-in: decides by card number, webhooks are form encoded
+// (`id`, `type`, `reference`, `reason`) and signed with HMAC-SHA256 in MOCK_SIGNATURE_HEADER
+#[derive(Debug)]
+pub struct MockGateway {
+    webhook_secret: Vec<u8>,
+}
+
+impl MockGateway {
+    pub fn new(webhook_secret: impl Into<Vec<u8>>) -> Self {
+        MockGateway {
+            webhook_secret: webhook_secret.into(),
+        }
+    }
+
+    // hex signature of a webhook body, to fake provider calls during development
+    pub fn sign(&self, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.webhook_secret)
+            .expect("HMAC takes keys of any size");
+        mac.update(body);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    fn new_reference(prefix: &str) -> String {
+        format!("{}_{}", prefix, hex::encode(rand::random::<[u8; 12]>()))
+    }
+
+    fn is_known_reference(reference: &str) -> bool {
+        reference.starts_with("mock_pay_")
+    }
+
+    fn authorize_now(&self, request: &PaymentRequest) -> Result<Authorization, PaymentError> {
+        if request.amount.amount <= 0 {
+            return Err(PaymentError::Provider(
+                "amount must be positive".to_string(),
+            ));
+        }
+        let digits: String = request
+            .card_number
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '-')
+            .collect();
+        match digits.as_str() {
+            MOCK_DECLINED_CARD => Err(PaymentError::Declined(
+                "Your card was declined.".to_string(),
+            )),
+            MOCK_INSUFFICIENT_FUNDS_CARD => Err(PaymentError::Declined(
+                "Your card has insufficient funds.".to_string(),
+            )),
+            digits if !passes_luhn_check(digits) => Err(PaymentError::Declined(
+                "Your card number is incorrect.".to_string(),
+            )),
+            _ => {
+                let reference = Self::new_reference("mock_pay");
+                tracing::info!(
+                    "Mock payment {} authorized, {} for order {}",
+                    reference,
+                    request.amount,
+                    request.order_id
+                );
+                Ok(Authorization { reference })
+            }
+        }
+    }
+
+    fn capture_now(&self, reference: &str) -> Result<(), PaymentError> {
+        if !Self::is_known_reference(reference) {
+            return Err(PaymentError::Provider(format!("no payment {}", reference)));
+        }
+        Ok(())
+    }
+
+    fn refund_now(&self, reference: &str, amount: Money) -> Result<String, PaymentError> {
+        if !Self::is_known_reference(reference) {
+            return Err(PaymentError::Provider(format!("no payment {}", reference)));
+        }
+        if amount.amount <= 0 {
+            return Err(PaymentError::Provider(
+                "amount must be positive".to_string(),
+            ));
+        }
+        Ok(Self::new_reference("mock_re"))
+    }
+
+    fn verify_webhook_now(
+        &self,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<PaymentEvent, PaymentError> {
+        let signature = headers
+            .get(MOCK_SIGNATURE_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| hex::decode(value).ok())
+            .ok_or(PaymentError::InvalidSignature)?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.webhook_secret)
+            .expect("HMAC takes keys of any size");
+        mac.update(body);
+        mac.verify_slice(&signature)
+            .map_err(|_| PaymentError::InvalidSignature)?;
+
+        let field = |name: &str| {
+            form_urlencoded::parse(body)
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+                .filter(|value| !value.is_empty())
+        };
+        let event_id = field("id").ok_or(PaymentError::InvalidEvent("missing id".to_string()))?;
+        let reference = field("reference")
+            .ok_or(PaymentError::InvalidEvent("missing reference".to_string()))?;
+        let kind = match field("type").as_deref() {
+            Some("payment.succeeded") => PaymentEventKind::Succeeded,
+            Some("payment.failed") => PaymentEventKind::Failed {
+                reason: field("reason").unwrap_or_else(|| "Payment failed".to_string()),
+            },
+            other => {
+                return Err(PaymentError::InvalidEvent(format!(
+                    "unknown type {:?}",
+                    other.unwrap_or_default()
+                )));
+            }
+        };
+        Ok(PaymentEvent {
+            event_id,
+            reference,
+            kind,
+        })
+    }
+}
+
+// the mock answers right away, there is nothing to wait for
+impl PaymentGateway for MockGateway {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn authorize<'a>(
+        &'a self,
+        request: &'a PaymentRequest<'a>,
+    ) -> GatewayFuture<'a, Authorization> {
+        Box::pin(std::future::ready(self.authorize_now(request)))
+    }
+
+    fn capture<'a>(&'a self, reference: &'a str, _amount: Money) -> GatewayFuture<'a, ()> {
+        Box::pin(std::future::ready(self.capture_now(reference)))
+    }
+
+    fn refund<'a>(
+        &'a self,
+        reference: &'a str,
+        amount: Money,
+        _idempotency_key: &'a str,
+    ) -> GatewayFuture<'a, String> {
+        Box::pin(std::future::ready(self.refund_now(reference, amount)))
+    }
+
+    fn verify_webhook<'a>(
+        &'a self,
+        headers: &'a HeaderMap,
+        body: &'a [u8],
+    ) -> GatewayFuture<'a, PaymentEvent> {
+        Box::pin(std::future::ready(self.verify_webhook_now(headers, body)))
+    }
+}
+
+fn passes_luhn_check(digits: &str) -> bool {
+    if !(12..=19).contains(&digits.len()) || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return false;
+    }
+    let sum: u32 = digits
+        .bytes()
+        .rev()
+        .map(|b| (b - b'0') as u32)
+        .enumerate()
+        .map(|(i, digit)| match (i % 2 == 1, digit * 2) {
+            (true, doubled) if doubled > 9 => doubled - 9,
+            (true, doubled) => doubled,
+            (false, _) => digit,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+pub struct Payments;
+
+impl Payments {
+    // charges `amount` right away (authorize + capture); the attempt is committed before the
+    // provider is called, so no order lock is held while it answers, and a double submit finds
+    // it in flight instead of charging twice
+    pub async fn pay(
+        pool: &PgPool,
+        gateway: &dyn PaymentGateway,
+        order_id: Uuid,
+        customer_id: i64,
+        amount: Money,
+        card_number: &str,
+    ) -> Result<(), PaymentError> {
+        let mut tx = pool.begin().await?;
+        let status = OrderRepository::lock_order(&mut tx, order_id, customer_id).await?;
+        if !matches!(status, OrderStatus::Placed | OrderStatus::PaymentFailed) {
+            return Err(PaymentError::NotPayable);
+        }
+        if PaymentRepository::has_payment_in_flight(&mut tx, order_id).await? {
+            return Err(PaymentError::InProgress);
+        }
+        let payment_id =
+            PaymentRepository::create_payment(&mut tx, order_id, gateway.name(), amount).await?;
+        tx.commit().await?;
+        let actor = OrderActor::PaymentProvider(gateway.name().to_string());
+
+        let request = PaymentRequest {
+            order_id,
+            amount,
+            card_number,
+        };
+        let authorization = match gateway.authorize(&request).await {
+            Ok(authorization) => authorization,
+            Err(e) => return Self::fail(pool, payment_id, order_id, &actor, e).await,
+        };
+        // stored before the capture, a webhook about it finds the payment
+        let mut tx = pool.begin().await?;
+        PaymentRepository::update_status(
+            &mut tx,
+            payment_id,
+            PaymentStatus::Authorized,
+            Some(&authorization.reference),
+            None,
+        )
+        .await?;
+        tx.commit().await?;
+        if let Err(e) = gateway.capture(&authorization.reference, amount).await {
+            return Self::fail(pool, payment_id, order_id, &actor, e).await;
+        }
+
+        let mut tx = pool.begin().await?;
+        let payment = PaymentRepository::find_by_reference_for_update(
+            &mut tx,
+            gateway.name(),
+            &authorization.reference,
+        )
+        .await?
+        .ok_or(PaymentError::UnknownPayment)?;
+        let refunds = Self::record_capture(&mut tx, &payment, order_id, &actor).await?;
+        tx.commit().await?;
+        tracing::info!(
+            "Order {} paid, {} via {} ({})",
+            order_id,
+            amount,
+            gateway.name(),
+            authorization.reference
+        );
+        if let Err(e) = Self::send_refunds(pool, gateway, &refunds).await {
+            tracing::error!(
+                "Sending the refund of payment {} failed: {}",
+                authorization.reference,
+                e
+            );
+        }
+        Ok(())
+    }
+
+    // provider notifications; an event that was handled before changes nothing
+    pub async fn handle_webhook(
+        pool: &PgPool,
+        gateway: &dyn PaymentGateway,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<(), PaymentError> {
+        let event = gateway.verify_webhook(headers, body).await?;
+
+        let mut tx = pool.begin().await?;
+        let payment = PaymentRepository::find_by_reference_for_update(
+            &mut tx,
+            gateway.name(),
+            &event.reference,
+        )
+        .await?
+        .ok_or(PaymentError::UnknownPayment)?;
+        let is_new = PaymentRepository::record_event(
+            &mut tx,
+            gateway.name(),
+            &event.event_id,
+            payment.id,
+            event.kind.as_str(),
+        )
+        .await?;
+        if !is_new {
+            tracing::info!("Payment event {} was handled before", event.event_id);
+            return Ok(());
+        }
+
+        let order_id =
+            Uuid::parse_str(&payment.order_id).map_err(|_| PaymentError::OrderNotFound)?;
+        let actor = OrderActor::PaymentProvider(gateway.name().to_string());
+        let mut refunds = Vec::new();
+        match &event.kind {
+            PaymentEventKind::Succeeded => {
+                refunds = Self::record_capture(&mut tx, &payment, order_id, &actor).await?;
+            }
+            PaymentEventKind::Failed { reason } => {
+                if matches!(
+                    payment.status,
+                    PaymentStatus::Pending | PaymentStatus::Authorized
+                ) {
+                    PaymentRepository::update_status(
+                        &mut tx,
+                        payment.id,
+                        PaymentStatus::Failed,
+                        None,
+                        Some(reason),
+                    )
+                    .await?;
+                    Self::advance(&mut tx, order_id, OrderStatus::PaymentFailed, &actor).await?;
+                }
+            }
+        }
+        tx.commit().await?;
+        tracing::info!(
+            "Payment event {} ({}) for order {} handled",
+            event.event_id,
+            event.kind.as_str(),
+            order_id
+        );
+        if let Err(e) = Self::send_refunds(pool, gateway, &refunds).await {
+            tracing::error!(
+                "Sending the refund of payment {} failed: {}",
+                event.reference,
+                e
+            );
+        }
+        Ok(())
+    }
+
+    // books `amount`, or all that is left when None, against the order's captured payments oldest
+    // first; the refunds are only written here, pending, and `send_refunds` hands them to the
+    // provider after the caller (who holds the order lock) committed
+    pub async fn refund(
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+        amount: Option<Money>,
+        return_request_id: Option<i64>,
+    ) -> Result<Vec<PendingRefund>, PaymentError> {
+        let payments = PaymentRepository::lock_refundable(tx, order_id).await?;
+        let remaining = payments
+            .iter()
+            .map(RefundablePayment::remaining)
+            .collect::<Result<Vec<_>, _>>()?;
+        let refundable = remaining
+            .first()
+            .map(|first| Money::sum(first.currency, remaining.iter().copied()))
+            .transpose()?;
+        let amount = match (amount, refundable) {
+            (None, None) => return Ok(Vec::new()),
+            (None, Some(refundable)) => refundable,
+            (Some(amount), refundable) => {
+                let refundable = refundable.unwrap_or(Money::zero(amount.currency));
+                if amount.currency != refundable.currency
+                    || amount.amount <= 0
+                    || amount.amount > refundable.amount
+                {
+                    return Err(PaymentError::RefundTooLarge { refundable });
+                }
+                amount
+            }
+        };
+
+        let mut refunds = Vec::new();
+        let mut left = amount;
+        for (payment, remaining) in payments.iter().zip(remaining) {
+            let part = Money::new(left.amount.min(remaining.amount), amount.currency);
+            if part.amount <= 0 {
+                continue;
+            }
+            let id =
+                PaymentRepository::create_refund(tx, payment.id, return_request_id, part).await?;
+            refunds.push(PendingRefund {
+                id,
+                payment_id: payment.id,
+                payment_reference: payment.reference.clone(),
+                amount: part,
+            });
+            left = left.checked_sub(part)?;
+            if left.amount == 0 {
+                break;
+            }
+        }
+        Ok(refunds)
+    }
+
+    // after the refunds were committed: a refund the provider refuses stays pending with its
+    // answer, `send-pending-refunds` tries it again; database errors end the run
+    pub async fn send_refunds(
+        pool: &PgPool,
+        gateway: &dyn PaymentGateway,
+        refunds: &[PendingRefund],
+    ) -> Result<usize, PaymentError> {
+        let mut sent = 0;
+        for refund in refunds {
+            let idempotency_key = format!("refund_{}", refund.id);
+            match gateway
+                .refund(&refund.payment_reference, refund.amount, &idempotency_key)
+                .await
+            {
+                Ok(reference) => {
+                    PaymentRepository::complete_refund(
+                        pool,
+                        refund.id,
+                        refund.payment_id,
+                        &reference,
+                    )
+                    .await?;
+                    tracing::info!(
+                        "Refunded {} of payment {} ({})",
+                        refund.amount,
+                        refund.payment_reference,
+                        reference
+                    );
+                    sent += 1;
+                }
+                Err(PaymentError::Database(e)) => return Err(PaymentError::Database(e)),
+                Err(e) => {
+                    tracing::error!(
+                        "Refund {} of {} on payment {} failed: {}",
+                        refund.id,
+                        refund.amount,
+                        refund.payment_reference,
+                        e
+                    );
+                    PaymentRepository::fail_refund(pool, refund.id, &e.to_string()).await?;
+                }
+            }
+        }
+        Ok(sent)
+    }
+
+    // the money of a locked payment arrived: the order is paid, or, when it was paid by another
+    // attempt or cancelled meanwhile, the payment goes straight back once the caller committed;
+    // nothing happens when the other of the payment page and the webhook got here first
+    async fn record_capture(
+        tx: &mut Transaction<'_, Postgres>,
+        payment: &Payment,
+        order_id: Uuid,
+        actor: &OrderActor,
+    ) -> Result<Vec<PendingRefund>, PaymentError> {
+        if matches!(
+            payment.status,
+            PaymentStatus::Captured | PaymentStatus::Refunded
+        ) {
+            return Ok(Vec::new());
+        }
+        let mut refunds = Vec::new();
+        if !Self::advance(tx, order_id, OrderStatus::Paid, actor).await? {
+            let id = PaymentRepository::create_refund(tx, payment.id, None, payment.amount).await?;
+            let reference = payment.reference.clone().unwrap_or_default();
+            tracing::info!(
+                "Payment {} for order {} came too late, it is refunded",
+                reference,
+                order_id
+            );
+            refunds.push(PendingRefund {
+                id,
+                payment_id: payment.id,
+                payment_reference: reference,
+                amount: payment.amount,
+            });
+        }
+        PaymentRepository::update_status(tx, payment.id, PaymentStatus::Captured, None, None)
+            .await?;
+        Ok(refunds)
+    }
+
+    // the failed attempt is kept, the customer can try again
+    async fn fail(
+        pool: &PgPool,
+        payment_id: i64,
+        order_id: Uuid,
+        actor: &OrderActor,
+        error: PaymentError,
+    ) -> Result<(), PaymentError> {
+        let reason = match &error {
+            PaymentError::Declined(reason) => reason.clone(),
+            e => e.to_string(),
+        };
+        let mut tx = pool.begin().await?;
+        PaymentRepository::update_status(
+            &mut tx,
+            payment_id,
+            PaymentStatus::Failed,
+            None,
+            Some(&reason),
+        )
+        .await?;
+        Self::advance(&mut tx, order_id, OrderStatus::PaymentFailed, actor).await?;
+        tx.commit().await?;
+        tracing::info!("Payment for order {} failed: {}", order_id, reason);
+        Err(error)
+    }
+
+    // false when the order can't take the status anymore; a late or repeated notification
+    // must not fail, e.g. a second failure or a success for an order that was cancelled
+    async fn advance(
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+        to: OrderStatus,
+        actor: &OrderActor,
+    ) -> Result<bool, PaymentError> {
+        match OrderRepository::transition(tx, order_id, to, actor).await {
+            Ok(_) => {
+                // the invoice number goes with the payment, in the same transaction
+                if to == OrderStatus::Paid {
+                    InvoiceRepository::issue(tx, order_id).await?;
+                }
+                Ok(true)
+            }
+            Err(OrderError::IllegalTransition { from, to }) => {
+                tracing::warn!("Order {} stays {}, not moved to {}", order_id, from, to);
+                Ok(false)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::passes_luhn_check;
+
+    #[test]
+    fn luhn_check() {
+        let cases = [
+            ("4242424242424242", true),      // visa test card
+            ("4000000000000002", true),      // the mock's declined card
+            ("5555555555554444", true),      // mastercard
+            ("378282246310005", true),       // amex, 15 digits
+            ("6011111111111117", true),      // discover
+            ("4242424242424241", false),     // last digit off
+            ("4242424242424224", false),     // two digits swapped
+            ("000000000000", true),          // shortest accepted length
+            ("00000000000", false),          // too short
+            ("00000000000000000000", false), // too long
+            ("4242 4242 4242 4242", false),  // `pay` strips spaces and dashes first
+            ("424242424242424a", false),
+            ("", false),
+        ];
+        for (digits, valid) in cases {
+            assert_eq!(passes_luhn_check(digits), valid, "{:?}", digits);
+        }
+    }
+}
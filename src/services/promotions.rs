@@ -0,0 +1,453 @@
+use crate::models::money::{CurrencyMismatch, ExchangeRate, Money};
+use crate::models::order::OrderLine;
+use crate::models::promotion::{AppliedPromotion, DiscountKind, Promotion, PromotionUsage};
+use crate::repository::promotion_repository::PromotionRepository;
+use sqlx::{Postgres, Transaction};
+use std::collections::HashSet;
+
+#[derive(Debug)]
+pub enum PromotionError {
+    Database(sqlx::Error),
+    UnknownCode,
+    NotStarted,
+    Expired,
+    UsageLimitReached,
+    CustomerLimitReached,
+    MinimumNotReached { minimum: Money },
+    NotApplicable, // nothing in the order is covered by the code
+    Currency(CurrencyMismatch),
+}
+
+impl std::fmt::Display for PromotionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PromotionError::Database(e) => write!(f, "Database error occurred: {}", e),
+            PromotionError::UnknownCode => write!(f, "This coupon code is not valid"),
+            PromotionError::NotStarted => write!(f, "This coupon code is not valid yet"),
+            PromotionError::Expired => write!(f, "This coupon code has expired"),
+            PromotionError::UsageLimitReached => {
+                write!(f, "This coupon code has been used up")
+            }
+            PromotionError::CustomerLimitReached => {
+                write!(f, "You have already used this coupon code")
+            }
+            PromotionError::MinimumNotReached { minimum } => {
+                write!(f, "This coupon code needs an order of at least {}", minimum)
+            }
+            PromotionError::NotApplicable => {
+                write!(
+                    f,
+                    "This coupon code does not apply to anything in your cart"
+                )
+            }
+            PromotionError::Currency(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<sqlx::Error> for PromotionError {
+    fn from(e: sqlx::Error) -> Self {
+        PromotionError::Database(e)
+    }
+}
+
+impl From<CurrencyMismatch> for PromotionError {
+    fn from(e: CurrencyMismatch) -> Self {
+        PromotionError::Currency(e)
+    }
+}
+
+pub struct Promotions;
+
+impl Promotions {
+    // looks the code up and checks it against the order lines, the promotion stays locked
+    // until the transaction ends
+    pub async fn apply(
+        tx: &mut Transaction<'_, Postgres>,
+        code: &str,
+        customer_id: i64,
+        lines: &[OrderLine],
+        rate: &ExchangeRate,
+    ) -> Result<AppliedPromotion, PromotionError> {
+        let promotion = PromotionRepository::find_by_code_for_update(tx, code)
+            .await?
+            .ok_or(PromotionError::UnknownCode)?;
+        let usage = PromotionRepository::usage(tx, promotion.id, customer_id).await?;
+        let eligible = PromotionRepository::eligible_product_ids(tx, &promotion).await?;
+
+        let discount = Self::evaluate(&promotion, usage, lines, rate, eligible.as_ref())?;
+        Ok(AppliedPromotion {
+            promotion_id: promotion.id,
+            code: promotion.code,
+            discount,
+        })
+    }
+
+    // the discount in the order currency, amounts on the promotion are in the base currency
+    pub fn evaluate(
+        promotion: &Promotion,
+        usage: PromotionUsage,
+        lines: &[OrderLine],
+        rate: &ExchangeRate,
+        eligible: Option<&HashSet<i32>>,
+    ) -> Result<Money, PromotionError> {
+        if !promotion.has_started {
+            return Err(PromotionError::NotStarted);
+        }
+        if promotion.has_ended {
+            return Err(PromotionError::Expired);
+        }
+        if promotion
+            .max_uses
+            .is_some_and(|max| usage.total >= max as i64)
+        {
+            return Err(PromotionError::UsageLimitReached);
+        }
+        if promotion
+            .max_uses_per_customer
+            .is_some_and(|max| usage.by_customer >= max as i64)
+        {
+            return Err(PromotionError::CustomerLimitReached);
+        }
+        let order_total = Money::sum(rate.currency, lines.iter().map(|line| line.line_total))?;
+        if let Some(minimum) = promotion.min_order_total.map(|m| rate.convert(m))
+            && (minimum.currency != rate.currency || order_total.amount < minimum.amount)
+        {
+            return Err(PromotionError::MinimumNotReached { minimum });
+        }
+
+        let subtotal = Money::sum(
+            rate.currency,
+            lines
+                .iter()
+                .filter(|line| eligible.is_none_or(|ids| ids.contains(&line.product_id)))
+                .map(|line| line.line_total),
+        )?;
+        if subtotal.amount <= 0 {
+            return Err(PromotionError::NotApplicable);
+        }
+
+        let amount = match promotion.kind {
+            // rounded half up to the cent
+            DiscountKind::Percentage => {
+                let percent = promotion.percent_off.unwrap_or_default().clamp(0, 100) as i64;
+                (subtotal.amount * percent + 50) / 100
+            }
+            // never more than what it applies to
+            DiscountKind::FixedAmount => {
+                let Some(amount_off) = promotion.amount_off.map(|m| rate.convert(m)) else {
+                    return Err(PromotionError::NotApplicable);
+                };
+                if amount_off.currency != rate.currency {
+                    return Err(PromotionError::NotApplicable);
+                }
+                amount_off.amount.min(subtotal.amount)
+            }
+        };
+        if amount <= 0 {
+            return Err(PromotionError::NotApplicable);
+        }
+        Ok(Money::new(amount, rate.currency))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::money::Currency;
+    use crate::models::order::fixtures::order_line;
+
+    fn usd(amount: i64) -> Money {
+        Money::new(amount, Currency::Usd)
+    }
+
+    fn percentage(percent_off: i32) -> Promotion {
+        Promotion {
+            id: 1,
+            code: "SAVE".to_string(),
+            kind: DiscountKind::Percentage,
+            percent_off: Some(percent_off),
+            amount_off: None,
+            min_order_total: None,
+            category_id: None,
+            product_id: None,
+            max_uses: None,
+            max_uses_per_customer: None,
+            has_started: true,
+            has_ended: false,
+        }
+    }
+
+    fn fixed(amount_off: Money) -> Promotion {
+        Promotion {
+            kind: DiscountKind::FixedAmount,
+            percent_off: None,
+            amount_off: Some(amount_off),
+            ..percentage(0)
+        }
+    }
+
+    // name, promotion, usage, order lines, rate, eligible products, discount or error variant
+    type Case<'a> = (
+        &'a str,
+        Promotion,
+        PromotionUsage,
+        Vec<OrderLine>,
+        ExchangeRate,
+        Option<&'a HashSet<i32>>,
+        Result<Money, &'a str>,
+    );
+
+    #[test]
+    fn evaluate() {
+        let eur = ExchangeRate {
+            currency: Currency::Eur,
+            rate: "0.5".parse().unwrap(),
+        };
+        let only_product_2 = HashSet::from([2]);
+        let used = |total, by_customer| PromotionUsage { total, by_customer };
+        let cases: Vec<Case> = vec![
+            (
+                "percentage of the order",
+                percentage(10),
+                used(0, 0),
+                vec![order_line(1, usd(5000)), order_line(2, usd(2500))],
+                ExchangeRate::base(),
+                None,
+                Ok(usd(750)),
+            ),
+            (
+                "percentage rounds half up to the cent",
+                percentage(10),
+                used(0, 0),
+                vec![order_line(1, usd(1005))],
+                ExchangeRate::base(),
+                None,
+                Ok(usd(101)),
+            ),
+            (
+                "percentage rounds down below half a cent",
+                percentage(15),
+                used(0, 0),
+                vec![order_line(1, usd(333))],
+                ExchangeRate::base(),
+                None,
+                Ok(usd(50)),
+            ),
+            (
+                "percentage above 100 is capped",
+                percentage(150),
+                used(0, 0),
+                vec![order_line(1, usd(1000))],
+                ExchangeRate::base(),
+                None,
+                Ok(usd(1000)),
+            ),
+            (
+                "percentage of the eligible lines only",
+                percentage(50),
+                used(0, 0),
+                vec![order_line(1, usd(5000)), order_line(2, usd(999))],
+                ExchangeRate::base(),
+                Some(&only_product_2),
+                Ok(usd(500)),
+            ),
+            (
+                "fixed amount",
+                fixed(usd(1000)),
+                used(0, 0),
+                vec![order_line(1, usd(5000))],
+                ExchangeRate::base(),
+                None,
+                Ok(usd(1000)),
+            ),
+            (
+                "fixed amount never exceeds what it applies to",
+                fixed(usd(1000)),
+                used(0, 0),
+                vec![order_line(1, usd(5000)), order_line(2, usd(400))],
+                ExchangeRate::base(),
+                Some(&only_product_2),
+                Ok(usd(400)),
+            ),
+            (
+                "fixed amount is converted to the order currency",
+                fixed(usd(1001)),
+                used(0, 0),
+                vec![order_line(1, Money::new(5000, Currency::Eur))],
+                eur,
+                None,
+                Ok(Money::new(501, Currency::Eur)),
+            ),
+            (
+                "not started",
+                Promotion {
+                    has_started: false,
+                    ..percentage(10)
+                },
+                used(0, 0),
+                vec![order_line(1, usd(5000))],
+                ExchangeRate::base(),
+                None,
+                Err("NotStarted"),
+            ),
+            (
+                "expired",
+                Promotion {
+                    has_ended: true,
+                    ..percentage(10)
+                },
+                used(0, 0),
+                vec![order_line(1, usd(5000))],
+                ExchangeRate::base(),
+                None,
+                Err("Expired"),
+            ),
+            (
+                "used up",
+                Promotion {
+                    max_uses: Some(3),
+                    ..percentage(10)
+                },
+                used(3, 0),
+                vec![order_line(1, usd(5000))],
+                ExchangeRate::base(),
+                None,
+                Err("UsageLimitReached"),
+            ),
+            (
+                "one use left",
+                Promotion {
+                    max_uses: Some(3),
+                    ..percentage(10)
+                },
+                used(2, 0),
+                vec![order_line(1, usd(5000))],
+                ExchangeRate::base(),
+                None,
+                Ok(usd(500)),
+            ),
+            (
+                "used by this customer already",
+                Promotion {
+                    max_uses_per_customer: Some(1),
+                    ..percentage(10)
+                },
+                used(5, 1),
+                vec![order_line(1, usd(5000))],
+                ExchangeRate::base(),
+                None,
+                Err("CustomerLimitReached"),
+            ),
+            (
+                "below the minimum",
+                Promotion {
+                    min_order_total: Some(usd(5000)),
+                    ..percentage(10)
+                },
+                used(0, 0),
+                vec![order_line(1, usd(4999))],
+                ExchangeRate::base(),
+                None,
+                Err("MinimumNotReached"),
+            ),
+            (
+                "at the minimum",
+                Promotion {
+                    min_order_total: Some(usd(5000)),
+                    ..percentage(10)
+                },
+                used(0, 0),
+                vec![order_line(1, usd(5000))],
+                ExchangeRate::base(),
+                None,
+                Ok(usd(500)),
+            ),
+            (
+                "minimum counts the whole order, not just eligible lines",
+                Promotion {
+                    min_order_total: Some(usd(5000)),
+                    ..percentage(10)
+                },
+                used(0, 0),
+                vec![order_line(1, usd(4000)), order_line(2, usd(1000))],
+                ExchangeRate::base(),
+                Some(&only_product_2),
+                Ok(usd(100)),
+            ),
+            (
+                "nothing eligible in the order",
+                percentage(10),
+                used(0, 0),
+                vec![order_line(1, usd(5000))],
+                ExchangeRate::base(),
+                Some(&only_product_2),
+                Err("NotApplicable"),
+            ),
+            (
+                "empty order",
+                percentage(10),
+                used(0, 0),
+                vec![],
+                ExchangeRate::base(),
+                None,
+                Err("NotApplicable"),
+            ),
+            (
+                "discount rounds to zero",
+                percentage(1),
+                used(0, 0),
+                vec![order_line(1, usd(49))],
+                ExchangeRate::base(),
+                None,
+                Err("NotApplicable"),
+            ),
+            (
+                "fixed amount without an amount",
+                Promotion {
+                    amount_off: None,
+                    ..fixed(usd(1000))
+                },
+                used(0, 0),
+                vec![order_line(1, usd(5000))],
+                ExchangeRate::base(),
+                None,
+                Err("NotApplicable"),
+            ),
+            (
+                "fixed amount in a currency the order can't convert",
+                fixed(Money::new(1000, Currency::Gbp)),
+                used(0, 0),
+                vec![order_line(1, usd(5000))],
+                ExchangeRate::base(),
+                None,
+                Err("NotApplicable"),
+            ),
+            (
+                "lines in another currency than the order",
+                percentage(10),
+                used(0, 0),
+                vec![order_line(1, Money::new(5000, Currency::Gbp))],
+                ExchangeRate::base(),
+                None,
+                Err("Currency"),
+            ),
+        ];
+
+        for (name, promotion, usage, lines, rate, eligible, expected) in cases {
+            let result = Promotions::evaluate(&promotion, usage, &lines, &rate, eligible);
+            match (result, expected) {
+                (Ok(discount), Ok(expected)) => assert_eq!(discount, expected, "{}", name),
+                (Err(e), Err(expected)) => {
+                    assert!(
+                        format!("{:?}", e).starts_with(expected),
+                        "{}: {:?}",
+                        name,
+                        e
+                    )
+                }
+                (result, expected) => panic!("{}: got {:?}, expected {:?}", name, result, expected),
+            }
+        }
+    }
+}
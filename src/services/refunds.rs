@@ -0,0 +1,351 @@
+use crate::models::money::{CurrencyMismatch, Money};
+use crate::models::order::{OrderActor, OrderDetails, OrderStatus};
+use crate::models::payment::PendingRefund;
+use crate::models::returns::{NewReturnLine, ReturnReason, ReturnRequest, ReturnStatus};
+use crate::repository::inventory_repository::InventoryRepository;
+use crate::repository::order_repository::{OrderError, OrderRepository};
+use crate::repository::return_repository::ReturnRepository;
+use crate::services::payments::{PaymentError, PaymentGateway, Payments};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub enum RefundError {
+    Database(sqlx::Error),
+    OrderNotFound,
+    NotCancellable(OrderStatus),
+    NotReturnable(OrderStatus),
+    InvalidReturn(String), // shown next to the return form
+    AlreadyDecided(ReturnStatus),
+    Order(OrderError),
+    Payment(PaymentError),
+}
+
+impl std::fmt::Display for RefundError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RefundError::Database(e) => write!(f, "Database error occurred: {}", e),
+            RefundError::OrderNotFound => write!(f, "Order not found"),
+            RefundError::NotCancellable(status) => {
+                write!(f, "A {} order can not be cancelled anymore", status)
+            }
+            RefundError::NotReturnable(status) => write!(
+                f,
+                "Goods can be returned once the order is delivered, this one is {}",
+                status
+            ),
+            RefundError::InvalidReturn(message) => write!(f, "{}", message),
+            RefundError::AlreadyDecided(status) => {
+                write!(f, "The return request was {} already", status.as_str())
+            }
+            RefundError::Order(e) => write!(f, "{}", e),
+            RefundError::Payment(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for RefundError {}
+
+impl From<sqlx::Error> for RefundError {
+    fn from(e: sqlx::Error) -> Self {
+        RefundError::Database(e)
+    }
+}
+
+impl From<OrderError> for RefundError {
+    fn from(e: OrderError) -> Self {
+        match e {
+            OrderError::Database(e) => RefundError::Database(e),
+            OrderError::NotFound => RefundError::OrderNotFound,
+            e => RefundError::Order(e),
+        }
+    }
+}
+
+impl From<PaymentError> for RefundError {
+    fn from(e: PaymentError) -> Self {
+        match e {
+            PaymentError::Database(e) => RefundError::Database(e),
+            e => RefundError::Payment(e),
+        }
+    }
+}
+
+impl From<CurrencyMismatch> for RefundError {
+    fn from(e: CurrencyMismatch) -> Self {
+        RefundError::Order(OrderError::Currency(e))
+    }
+}
+
+pub struct Refunds;
+
+impl Refunds {
+    // unpaid orders are simply cancelled, paid ones that haven't shipped get their money back;
+    // reserved stock goes back with the status change. The provider is only asked for the
+    // refund once the cancellation committed
+    pub async fn cancel_order(
+        pool: &PgPool,
+        gateway: &dyn PaymentGateway,
+        order_id: Uuid,
+        customer_id: i64,
+    ) -> Result<(), RefundError> {
+        let mut tx = pool.begin().await?;
+        let status = OrderRepository::lock_order(&mut tx, order_id, customer_id).await?;
+        if !matches!(
+            status,
+            OrderStatus::Placed | OrderStatus::PaymentFailed | OrderStatus::Paid
+        ) {
+            return Err(RefundError::NotCancellable(status));
+        }
+        let refunds = if status == OrderStatus::Paid {
+            Payments::refund(&mut tx, order_id, None, None).await?
+        } else {
+            Vec::new()
+        };
+        OrderRepository::transition(
+            &mut tx,
+            order_id,
+            OrderStatus::Cancelled,
+            &OrderActor::Customer(customer_id),
+        )
+        .await?;
+        tx.commit().await?;
+        tracing::info!("Order {} cancelled by customer {}", order_id, customer_id);
+        Self::send_refunds(pool, gateway, order_id, &refunds).await;
+        Ok(())
+    }
+
+    // `fields` is the return form: `quantity_<line id>` and `reason_<line id>` per order line,
+    // empty or 0 for lines that stay with the customer
+    pub async fn request_return(
+        pool: &PgPool,
+        order: &OrderDetails,
+        customer_id: i64,
+        fields: &HashMap<String, String>,
+        comment: Option<&str>,
+    ) -> Result<i64, RefundError> {
+        let order_id = Uuid::parse_str(&order.order_id).map_err(|_| RefundError::OrderNotFound)?;
+        let mut tx = pool.begin().await?;
+        let status = OrderRepository::lock_order(&mut tx, order_id, customer_id).await?;
+        if status != OrderStatus::Delivered {
+            return Err(RefundError::NotReturnable(status));
+        }
+        let returned = ReturnRepository::returned_quantities(&mut tx, order_id, true).await?;
+
+        let mut lines = Vec::new();
+        for line in &order.lines {
+            let quantity = match fields
+                .get(&format!("quantity_{}", line.id))
+                .map(|value| value.trim())
+                .filter(|value| !value.is_empty())
+            {
+                Some(value) => value.parse::<i32>().map_err(|_| {
+                    RefundError::InvalidReturn(format!(
+                        "Please enter how many of {} you are sending back",
+                        line.product_name
+                    ))
+                })?,
+                None => 0,
+            };
+            if quantity == 0 {
+                continue;
+            }
+            let returnable = line.quantity - returned.get(&line.id).copied().unwrap_or(0);
+            if !(1..=returnable).contains(&quantity) {
+                return Err(RefundError::InvalidReturn(format!(
+                    "You can return at most {} of {}",
+                    returnable.max(0),
+                    line.product_name
+                )));
+            }
+            let reason = fields
+                .get(&format!("reason_{}", line.id))
+                .and_then(|value| ReturnReason::parse(value))
+                .ok_or_else(|| {
+                    RefundError::InvalidReturn(format!(
+                        "Please tell us why you are returning {}",
+                        line.product_name
+                    ))
+                })?;
+            lines.push(NewReturnLine {
+                order_line_id: line.id,
+                quantity,
+                reason,
+            });
+        }
+        if lines.is_empty() {
+            return Err(RefundError::InvalidReturn(
+                "Please choose at least one item to return".to_string(),
+            ));
+        }
+
+        let return_id =
+            ReturnRepository::create_request(&mut tx, order_id, customer_id, comment, &lines)
+                .await?;
+        tx.commit().await?;
+        tracing::info!(
+            "Return request {} for order {} created",
+            return_id,
+            order_id
+        );
+        Ok(return_id)
+    }
+
+    // what the returned goods were paid with: their share of the goods after the coupon, plus the
+    // tax charged on them; shipping is not refunded
+    pub fn suggested_refund(
+        order: &OrderDetails,
+        request: &ReturnRequest,
+    ) -> Result<Money, CurrencyMismatch> {
+        let Some(value) = request.goods_value()? else {
+            return Ok(Money::zero(order.currency));
+        };
+        let goods_total = order.goods_total()?;
+        let paid_for_goods = goods_total.share(value.amount, order.subtotal.amount);
+
+        // orders placed with per-line tax: the tax of the returned units
+        let line_taxes: Option<Vec<Money>> = request
+            .lines
+            .iter()
+            .map(|returned| {
+                let line = order
+                    .lines
+                    .iter()
+                    .find(|line| line.id == returned.order_line_id)?;
+                Some(
+                    line.tax?
+                        .share(returned.quantity as i64, line.quantity as i64),
+                )
+            })
+            .collect();
+        if let Some(line_taxes) = line_taxes {
+            return line_taxes
+                .into_iter()
+                .try_fold(paid_for_goods, Money::checked_add);
+        }
+
+        // older orders only know the order tax, which covered shipping as well
+        let taxable = order
+            .shipping_cost
+            .map_or(Ok(goods_total), |shipping_cost| {
+                goods_total.checked_add(shipping_cost)
+            })?;
+        let paid_for_goods = order.tax.map_or(Ok(goods_total), |tax| {
+            goods_total.checked_add(tax.share(goods_total.amount, taxable.amount))
+        })?;
+        Ok(paid_for_goods.share(value.amount, order.subtotal.amount))
+    }
+
+    // the goods arrived back: they are restocked, except damaged ones which are written off, and
+    // `refund` goes back to the customer (zero for an exchange or when nothing is owed); once every
+    // unit came back the order counts as refunded
+    pub async fn approve_return(
+        pool: &PgPool,
+        gateway: &dyn PaymentGateway,
+        order: &OrderDetails,
+        request: &ReturnRequest,
+        staff_id: i64,
+        refund: Money,
+        staff_note: Option<&str>,
+    ) -> Result<(), RefundError> {
+        let order_id =
+            Uuid::parse_str(&request.order_id).map_err(|_| RefundError::OrderNotFound)?;
+        let mut tx = pool.begin().await?;
+        let status = ReturnRepository::lock_request(&mut tx, request.id).await?;
+        if status != ReturnStatus::Requested {
+            return Err(RefundError::AlreadyDecided(status));
+        }
+        OrderRepository::lock_order(&mut tx, order_id, request.customer_id).await?;
+
+        // lines in variant order, like reservations, so concurrent stock updates lock rows the same way
+        let mut restocked: Vec<(i32, i32)> = request
+            .lines
+            .iter()
+            .filter(|line| line.reason.is_restocked())
+            .filter_map(|line| {
+                line.variant_id
+                    .map(|variant_id| (variant_id, line.quantity))
+            })
+            .collect();
+        restocked.sort();
+        for (variant_id, quantity) in restocked {
+            InventoryRepository::restock_return(&mut tx, order_id, variant_id, quantity).await?;
+        }
+        let refunds = if refund.amount != 0 {
+            Payments::refund(&mut tx, order_id, Some(refund), Some(request.id)).await?
+        } else {
+            Vec::new()
+        };
+        ReturnRepository::decide(
+            &mut tx,
+            request.id,
+            ReturnStatus::Approved,
+            staff_id,
+            staff_note,
+        )
+        .await?;
+
+        let returned = ReturnRepository::returned_quantities(&mut tx, order_id, false).await?;
+        let is_all_returned = order
+            .lines
+            .iter()
+            .all(|line| returned.get(&line.id).copied().unwrap_or(0) >= line.quantity);
+        if is_all_returned {
+            OrderRepository::transition(
+                &mut tx,
+                order_id,
+                OrderStatus::Refunded,
+                &OrderActor::Staff(staff_id),
+            )
+            .await?;
+        }
+        tx.commit().await?;
+        tracing::info!(
+            "Return request {} for order {} approved by {}, {} refunded",
+            request.id,
+            order_id,
+            staff_id,
+            refund
+        );
+        Self::send_refunds(pool, gateway, order_id, &refunds).await;
+        Ok(())
+    }
+
+    // the decision stands either way, refunds the provider didn't take stay pending for
+    // `send-pending-refunds`
+    async fn send_refunds(
+        pool: &PgPool,
+        gateway: &dyn PaymentGateway,
+        order_id: Uuid,
+        refunds: &[PendingRefund],
+    ) {
+        if let Err(e) = Payments::send_refunds(pool, gateway, refunds).await {
+            tracing::error!("Sending the refunds of order {} failed: {}", order_id, e);
+        }
+    }
+
+    pub async fn reject_return(
+        pool: &PgPool,
+        return_id: i64,
+        staff_id: i64,
+        staff_note: Option<&str>,
+    ) -> Result<(), RefundError> {
+        let mut tx = pool.begin().await?;
+        let status = ReturnRepository::lock_request(&mut tx, return_id).await?;
+        if status != ReturnStatus::Requested {
+            return Err(RefundError::AlreadyDecided(status));
+        }
+        ReturnRepository::decide(
+            &mut tx,
+            return_id,
+            ReturnStatus::Rejected,
+            staff_id,
+            staff_note,
+        )
+        .await?;
+        tx.commit().await?;
+        tracing::info!("Return request {} rejected by {}", return_id, staff_id);
+        Ok(())
+    }
+}
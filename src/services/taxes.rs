@@ -0,0 +1,189 @@
+use crate::models::customer::ProfileCustomer;
+use crate::models::money::{CurrencyMismatch, Money};
+use crate::models::order::OrderDetails;
+use crate::models::state::AppState;
+use crate::models::tax::TaxRates;
+use crate::repository::tax_repository::TaxRepository;
+use sqlx::PgPool;
+
+pub struct Taxes;
+
+impl Taxes {
+    // whose tax the catalog shows: the customer's country, TAX_COUNTRY for guests and for
+    // profiles in a country we have no rates for; checkout refuses to ship there
+    pub async fn for_customer(
+        pool: &PgPool,
+        state: &AppState,
+        customer_user: &ProfileCustomer,
+        product_ids: &[i32],
+    ) -> Result<TaxRates, sqlx::Error> {
+        if customer_user.is_authenticated
+            && let Some(rates) =
+                TaxRepository::rates_for_country(pool, &customer_user.country, product_ids).await?
+        {
+            return Ok(rates);
+        }
+        let rates = match state.tax_country.as_deref() {
+            Some(country) => TaxRepository::rates_for_country(pool, country, product_ids).await?,
+            None => None,
+        };
+        Ok(rates.unwrap_or_default())
+    }
+
+    // tax of every line on what is left of it after its share of the coupon, shipping at the
+    // standard rate; the order tax is the sum of both
+    pub fn apply_to_order(
+        order: &mut OrderDetails,
+        rates: &TaxRates,
+    ) -> Result<(), CurrencyMismatch> {
+        let discount = order.discount.unwrap_or(Money::zero(order.currency)).amount;
+        let subtotal = order.subtotal;
+        let mut discount_left = discount;
+        let mut tax = Money::zero(order.currency);
+        let line_count = order.lines.len();
+        for (i, line) in order.lines.iter_mut().enumerate() {
+            // the last line takes the rounding remainder, the shares add up to the discount
+            let line_discount = if i + 1 == line_count {
+                discount_left
+            } else {
+                Money::new(discount, order.currency)
+                    .share(line.line_total.amount, subtotal.amount)
+                    .amount
+            };
+            discount_left -= line_discount;
+            let taxable = line
+                .line_total
+                .checked_sub(Money::new(line_discount, order.currency))?;
+            let rate = rates.rate_for(line.product_id);
+            line.tax_rate = Some(rate);
+            line.tax = Some(taxable.percent(rate));
+            tax = tax.checked_add(taxable.percent(rate))?;
+        }
+
+        order.shipping_tax = order
+            .shipping_cost
+            .map(|shipping_cost| shipping_cost.percent(rates.standard_rate));
+        if let Some(shipping_tax) = order.shipping_tax {
+            tax = tax.checked_add(shipping_tax)?;
+        }
+        order.tax = Some(tax);
+        order.tax_country = rates.country.clone();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::money::Currency;
+    use crate::models::order::OrderStatus;
+    use crate::models::order::fixtures::order_line;
+    use rust_decimal::Decimal;
+
+    fn usd(amount: i64) -> Money {
+        Money::new(amount, Currency::Usd)
+    }
+
+    fn order(
+        line_totals: &[i64],
+        discount: Option<i64>,
+        shipping_cost: Option<i64>,
+    ) -> OrderDetails {
+        let lines = line_totals
+            .iter()
+            .zip(1..)
+            .map(|(&line_total, product_id)| order_line(product_id, usd(line_total)))
+            .collect();
+        OrderDetails {
+            order_id: "00000000-0000-0000-0000-000000000000".to_string(),
+            status: OrderStatus::Cart,
+            is_confirmed: false,
+            created_at: chrono::NaiveDateTime::default(),
+            shipping_address: None,
+            billing_address: None,
+            lines,
+            currency: Currency::Usd,
+            exchange_rate: Decimal::ONE,
+            subtotal: usd(line_totals.iter().sum()),
+            promotion_code: discount.map(|_| "SAVE".to_string()),
+            discount: discount.map(usd),
+            shipping_rate_id: None,
+            shipping_method: None,
+            shipping_cost: shipping_cost.map(usd),
+            tax: None,
+            tax_country: None,
+            shipping_tax: None,
+            total: usd(0),
+            status_history: Vec::new(),
+        }
+    }
+
+    fn rates(standard_rate: i64, product_rates: &[(i32, i64)]) -> TaxRates {
+        TaxRates {
+            country: Some("DE".to_string()),
+            name: "VAT".to_string(),
+            standard_rate: Decimal::from(standard_rate),
+            product_rates: product_rates
+                .iter()
+                .map(|(product_id, rate)| (*product_id, Decimal::from(*rate)))
+                .collect(),
+        }
+    }
+
+    fn line_taxes(order: &OrderDetails) -> Vec<Option<i64>> {
+        order
+            .lines
+            .iter()
+            .map(|line| line.tax.map(|tax| tax.amount))
+            .collect()
+    }
+
+    #[test]
+    fn taxes_every_line_at_its_rate_and_shipping_at_the_standard_rate() {
+        let mut order = order(&[10_000, 5_000], None, Some(500));
+        Taxes::apply_to_order(&mut order, &rates(19, &[(2, 7)])).unwrap();
+
+        assert_eq!(line_taxes(&order), [Some(1_900), Some(350)]);
+        let line_rates: Vec<_> = order.lines.iter().map(|line| line.tax_rate).collect();
+        assert_eq!(
+            line_rates,
+            [Some(Decimal::from(19)), Some(Decimal::from(7))]
+        );
+        assert_eq!(order.shipping_tax, Some(usd(95)));
+        assert_eq!(order.tax, Some(usd(2_345)));
+        assert_eq!(order.tax_country.as_deref(), Some("DE"));
+    }
+
+    #[test]
+    fn spreads_the_discount_over_the_lines_by_their_share() {
+        // 1000 off 15000: 667 on the first line, 333 on the second
+        let mut order = order(&[10_000, 5_000], Some(1_000), None);
+        Taxes::apply_to_order(&mut order, &rates(19, &[(2, 7)])).unwrap();
+
+        // 9333 * 19% = 1773.27, 4667 * 7% = 326.69
+        assert_eq!(line_taxes(&order), [Some(1_773), Some(327)]);
+        assert_eq!(order.shipping_tax, None);
+        assert_eq!(order.tax, Some(usd(2_100)));
+    }
+
+    #[test]
+    fn last_line_takes_the_rounding_remainder_of_the_discount() {
+        // at 100% the tax is the taxable amount, so it shows each line's share of the discount
+        let mut order = order(&[1_000, 1_000, 1_000], Some(100), None);
+        Taxes::apply_to_order(&mut order, &rates(100, &[])).unwrap();
+
+        assert_eq!(line_taxes(&order), [Some(967), Some(967), Some(966)]);
+        assert_eq!(order.tax, Some(usd(2_900)));
+    }
+
+    #[test]
+    fn charges_nothing_where_no_tax_applies() {
+        let mut order = order(&[10_000], Some(1_000), Some(500));
+        Taxes::apply_to_order(&mut order, &TaxRates::default()).unwrap();
+
+        assert_eq!(line_taxes(&order), [Some(0)]);
+        assert_eq!(order.shipping_tax, Some(usd(0)));
+        assert_eq!(order.tax, Some(usd(0)));
+        assert_eq!(order.tax_country, None);
+    }
+}
@@ -0,0 +1,438 @@
+use crate::models::address::{AddressForm, NewAddress};
+use crate::models::contact::ContactForm;
+use crate::models::country::country_code;
+use crate::models::customer::{
+    AcceptEnum, NewCustomer, NewCustomerForm, ProfileUpdate, UpdateProfileForm,
+};
+use chrono::{Local, NaiveDate};
+use std::collections::HashMap;
+
+// field name -> message, rendered next to the field as `form_errors.<field>`
+pub type FormErrors = HashMap<&'static str, String>;
+
+const MAX_FIELD_LENGTH: usize = 255;
+const MIN_PASSWORD_LENGTH: usize = 8;
+const MIN_PHONE_DIGITS: usize = 7;
+const MAX_PHONE_DIGITS: usize = 15;
+const MAX_MESSAGE_LENGTH: usize = 5000;
+const MAX_POSTAL_CODE_LENGTH: usize = 32;
+
+pub struct CustomerValidator;
+
+impl CustomerValidator {
+    pub fn validate_new_customer(form: &NewCustomerForm) -> Result<NewCustomer, FormErrors> {
+        let mut errors = FormErrors::new();
+
+        let email = form.email.trim().to_lowercase();
+        if let Err(e) = Self::validate_email(&email) {
+            errors.insert("email", e);
+        }
+        let details = Self::validate_personal_details(
+            &mut errors,
+            &form.first_name,
+            &form.last_name,
+            &form.phone,
+            &form.city,
+            &form.country,
+            &form.date_birth,
+        );
+        if let Err(e) = Self::validate_password(&form.password) {
+            errors.insert("password", e);
+        }
+        if form.password != form.confirm_password {
+            errors.insert("confirm_password", "Passwords do not match".to_string());
+        }
+        if let AcceptEnum::Off = form.accept_all {
+            errors.insert("accept_all", "Required field!".to_string());
+        }
+
+        match details {
+            Some((date_birth, country)) if errors.is_empty() => Ok(NewCustomer {
+                email,
+                first_name: form.first_name.trim().to_string(),
+                last_name: form.last_name.trim().to_string(),
+                date_birth,
+                phone: form.phone.trim().to_string(),
+                city: form.city.trim().to_string(),
+                country: country.to_string(),
+                password: form.password.clone(),
+            }),
+            _ => Err(errors),
+        }
+    }
+
+    pub fn validate_profile_update(form: &UpdateProfileForm) -> Result<ProfileUpdate, FormErrors> {
+        let mut errors = FormErrors::new();
+        let details = Self::validate_personal_details(
+            &mut errors,
+            &form.first_name,
+            &form.last_name,
+            &form.phone,
+            &form.city,
+            &form.country,
+            &form.date_birth,
+        );
+
+        match details {
+            Some((date_birth, country)) if errors.is_empty() => Ok(ProfileUpdate {
+                first_name: form.first_name.trim().to_string(),
+                last_name: form.last_name.trim().to_string(),
+                date_birth,
+                phone: form.phone.trim().to_string(),
+                city: form.city.trim().to_string(),
+                country: country.to_string(),
+            }),
+            _ => Err(errors),
+        }
+    }
+
+    // fields shared by registration and the profile form, errors go into `errors`;
+    // the date of birth and the country code when both are valid
+    fn validate_personal_details(
+        errors: &mut FormErrors,
+        first_name: &str,
+        last_name: &str,
+        phone: &str,
+        city: &str,
+        country: &str,
+        date_birth: &str,
+    ) -> Option<(NaiveDate, &'static str)> {
+        if let Err(e) = Self::validate_required("First name", first_name) {
+            errors.insert("first_name", e);
+        }
+        if let Err(e) = Self::validate_required("Last name", last_name) {
+            errors.insert("last_name", e);
+        }
+        if let Err(e) = Self::validate_phone(phone) {
+            errors.insert("phone", e);
+        }
+        if let Err(e) = Self::validate_required("City", city) {
+            errors.insert("city", e);
+        }
+        let country = match Self::validate_country(country) {
+            Ok(country) => Some(country),
+            Err(e) => {
+                errors.insert("country", e);
+                None
+            }
+        };
+        match Self::validate_date_birth(date_birth) {
+            Ok(date) => country.map(|country| (date, country)),
+            Err(e) => {
+                errors.insert("date_birth", e);
+                None
+            }
+        }
+    }
+
+    pub fn validate_new_password(password: &str, confirm_password: &str) -> Result<(), FormErrors> {
+        let mut errors = FormErrors::new();
+        if let Err(e) = Self::validate_password(password) {
+            errors.insert("password", e);
+        }
+        if password != confirm_password {
+            errors.insert("confirm_password", "Passwords do not match".to_string());
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn validate_required(label: &str, value: &str) -> Result<(), String> {
+        let value = value.trim();
+        if value.is_empty() {
+            return Err(format!("{} is required", label));
+        }
+        if value.chars().count() > MAX_FIELD_LENGTH {
+            return Err(format!(
+                "{} must be at most {} characters",
+                label, MAX_FIELD_LENGTH
+            ));
+        }
+        Ok(())
+    }
+
+    // ISO 3166-1 alpha-2, the select list sends the code, an English name is accepted too
+    pub fn validate_country(country: &str) -> Result<&'static str, String> {
+        Self::validate_required("Country", country)?;
+        country_code(country).ok_or_else(|| "Choose a country from the list".to_string())
+    }
+
+    pub fn validate_email(email: &str) -> Result<(), String> {
+        Self::validate_required("Email", email)?;
+
+        let invalid = || Err("Enter a valid email address".to_string());
+        if email.chars().any(char::is_whitespace) {
+            return invalid();
+        }
+        let Some((local, domain)) = email.split_once('@') else {
+            return invalid();
+        };
+        if local.is_empty() || domain.contains('@') {
+            return invalid();
+        }
+        match domain.rsplit_once('.') {
+            Some((name, tld)) if !name.is_empty() && tld.len() >= 2 => Ok(()),
+            _ => invalid(),
+        }
+    }
+
+    pub fn validate_password(password: &str) -> Result<(), String> {
+        if password.chars().count() < MIN_PASSWORD_LENGTH {
+            return Err(format!(
+                "Password must be at least {} characters",
+                MIN_PASSWORD_LENGTH
+            ));
+        }
+        if !password.chars().any(char::is_alphabetic)
+            || !password.chars().any(|c| c.is_ascii_digit())
+        {
+            return Err("Password must contain letters and digits".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn validate_phone(phone: &str) -> Result<(), String> {
+        Self::validate_required("Phone", phone)?;
+
+        let phone = phone.trim();
+        let allowed = |c: char| c.is_ascii_digit() || " -()".contains(c);
+        let body = phone.strip_prefix('+').unwrap_or(phone);
+        let digits = body.chars().filter(char::is_ascii_digit).count();
+        if !body.chars().all(allowed) || !(MIN_PHONE_DIGITS..=MAX_PHONE_DIGITS).contains(&digits) {
+            return Err("Enter a valid phone number, e.g. +49 30 1234567".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn validate_date_birth(date_birth: &str) -> Result<NaiveDate, String> {
+        let date = NaiveDate::parse_from_str(date_birth.trim(), "%Y-%m-%d")
+            .map_err(|_| "Enter your date of birth".to_string())?;
+        if date >= Local::now().date_naive() {
+            return Err("Date of birth must be in the past".to_string());
+        }
+        if date < NaiveDate::from_ymd_opt(1900, 1, 1).unwrap() {
+            return Err("Enter a valid date of birth".to_string());
+        }
+        Ok(date)
+    }
+}
+
+pub struct ContactValidator;
+
+impl ContactValidator {
+    pub fn validate_contact_form(form: &ContactForm) -> Result<(), FormErrors> {
+        let mut errors = FormErrors::new();
+        if let Err(e) = CustomerValidator::validate_required("Name", &form.name) {
+            errors.insert("name", e);
+        }
+        if let Err(e) = CustomerValidator::validate_email(form.email.trim()) {
+            errors.insert("email", e);
+        }
+        let message = form.message.trim();
+        if message.is_empty() {
+            errors.insert("message", "Message is required".to_string());
+        } else if message.chars().count() > MAX_MESSAGE_LENGTH {
+            errors.insert(
+                "message",
+                format!("Message must be at most {} characters", MAX_MESSAGE_LENGTH),
+            );
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+pub struct AddressValidator;
+
+impl AddressValidator {
+    pub fn validate_address(form: &AddressForm) -> Result<NewAddress, FormErrors> {
+        let mut errors = FormErrors::new();
+        if let Err(e) = CustomerValidator::validate_required("Label", &form.label) {
+            errors.insert("label", e);
+        }
+        if let Err(e) = CustomerValidator::validate_required("Full name", &form.full_name) {
+            errors.insert("full_name", e);
+        }
+        if let Err(e) = CustomerValidator::validate_required("Address", &form.line1) {
+            errors.insert("line1", e);
+        }
+        if form.line2.trim().chars().count() > MAX_FIELD_LENGTH {
+            errors.insert(
+                "line2",
+                format!("Address must be at most {} characters", MAX_FIELD_LENGTH),
+            );
+        }
+        if let Err(e) = CustomerValidator::validate_required("City", &form.city) {
+            errors.insert("city", e);
+        }
+        let postal_code = form.postal_code.trim();
+        if postal_code.is_empty() {
+            errors.insert("postal_code", "Postal code is required".to_string());
+        } else if postal_code.chars().count() > MAX_POSTAL_CODE_LENGTH
+            || !postal_code
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || " -".contains(c))
+        {
+            errors.insert("postal_code", "Enter a valid postal code".to_string());
+        }
+        let country = match CustomerValidator::validate_country(&form.country) {
+            Ok(country) => Some(country),
+            Err(e) => {
+                errors.insert("country", e);
+                None
+            }
+        };
+        // optional, only checked when given
+        if !form.phone.trim().is_empty()
+            && let Err(e) = CustomerValidator::validate_phone(&form.phone)
+        {
+            errors.insert("phone", e);
+        }
+
+        let Some(country) = country.filter(|_| errors.is_empty()) else {
+            return Err(errors);
+        };
+        let optional =
+            |value: &str| Some(value.trim().to_string()).filter(|value| !value.is_empty());
+        Ok(NewAddress {
+            label: form.label.trim().to_string(),
+            full_name: form.full_name.trim().to_string(),
+            line1: form.line1.trim().to_string(),
+            line2: optional(&form.line2),
+            city: form.city.trim().to_string(),
+            postal_code: postal_code.to_uppercase(),
+            country: country.to_string(),
+            phone: optional(&form.phone),
+            is_default_shipping: matches!(form.is_default_shipping, AcceptEnum::On),
+            is_default_billing: matches!(form.is_default_billing, AcceptEnum::On),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Days;
+
+    fn address_form() -> AddressForm {
+        AddressForm {
+            label: " Home ".to_string(),
+            full_name: "Anna Berg".to_string(),
+            line1: "Hauptstr. 1".to_string(),
+            line2: String::new(),
+            city: "Berlin".to_string(),
+            postal_code: "10115".to_string(),
+            country: "de".to_string(),
+            phone: String::new(),
+            is_default_shipping: AcceptEnum::On,
+            is_default_billing: AcceptEnum::Off,
+        }
+    }
+
+    #[test]
+    fn email() {
+        for valid in ["anna@example.com", "a.b+shop@mail.example.de"] {
+            assert!(
+                CustomerValidator::validate_email(valid).is_ok(),
+                "{}",
+                valid
+            );
+        }
+        for invalid in [
+            "",
+            "anna",
+            "@example.com",
+            "anna@example",
+            "anna@.com",
+            "anna@example.c",
+            "anna@@example.com",
+            "an na@example.com",
+        ] {
+            assert!(
+                CustomerValidator::validate_email(invalid).is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn phone_digit_count() {
+        assert!(CustomerValidator::validate_phone("123456").is_err()); // 6 digits
+        assert!(CustomerValidator::validate_phone("1234567").is_ok()); // 7
+        assert!(CustomerValidator::validate_phone("+49 (30) 123-456-789-01").is_ok()); // 15
+        assert!(CustomerValidator::validate_phone("1234567890123456").is_err()); // 16
+        assert!(CustomerValidator::validate_phone("+49 30 1234567 ext").is_err());
+        assert!(CustomerValidator::validate_phone("49+301234567").is_err());
+        assert!(CustomerValidator::validate_phone(" ").is_err());
+    }
+
+    #[test]
+    fn password() {
+        assert!(CustomerValidator::validate_password("secret12").is_ok());
+        assert!(CustomerValidator::validate_password("secret1").is_err()); // 7 characters
+        assert!(CustomerValidator::validate_password("secretpassword").is_err());
+        assert!(CustomerValidator::validate_password("12345678").is_err());
+    }
+
+    #[test]
+    fn date_birth_bounds() {
+        let today = Local::now().date_naive();
+        assert_eq!(
+            CustomerValidator::validate_date_birth("1900-01-01"),
+            Ok(NaiveDate::from_ymd_opt(1900, 1, 1).unwrap())
+        );
+        assert!(CustomerValidator::validate_date_birth("1899-12-31").is_err());
+        let yesterday = today - Days::new(1);
+        assert_eq!(
+            CustomerValidator::validate_date_birth(&yesterday.format("%Y-%m-%d").to_string()),
+            Ok(yesterday)
+        );
+        assert!(
+            CustomerValidator::validate_date_birth(&today.format("%Y-%m-%d").to_string()).is_err()
+        );
+        assert!(CustomerValidator::validate_date_birth("01.02.1990").is_err());
+        assert!(CustomerValidator::validate_date_birth("").is_err());
+    }
+
+    #[test]
+    fn country_codes_and_names() {
+        assert_eq!(CustomerValidator::validate_country("de"), Ok("DE"));
+        assert_eq!(CustomerValidator::validate_country("Germany"), Ok("DE"));
+        assert!(CustomerValidator::validate_country("Atlantis").is_err());
+        assert!(CustomerValidator::validate_country("").is_err());
+    }
+
+    #[test]
+    fn address_is_trimmed_and_normalized() {
+        let mut form = address_form();
+        form.postal_code = "sw1a 1aa".to_string();
+        let address = AddressValidator::validate_address(&form).unwrap();
+        assert_eq!(address.label, "Home");
+        assert_eq!(address.line2, None);
+        assert_eq!(address.postal_code, "SW1A 1AA");
+        assert_eq!(address.country, "DE");
+        assert_eq!(address.phone, None);
+        assert!(address.is_default_shipping);
+        assert!(!address.is_default_billing);
+    }
+
+    #[test]
+    fn address_errors_are_keyed_by_field() {
+        let mut form = address_form();
+        form.full_name = " ".to_string();
+        form.postal_code = "10115!".to_string();
+        form.country = "Atlantis".to_string();
+        form.phone = "12".to_string();
+        let errors = AddressValidator::validate_address(&form).unwrap_err();
+        let mut fields: Vec<&str> = errors.keys().copied().collect();
+        fields.sort();
+        assert_eq!(fields, ["country", "full_name", "phone", "postal_code"]);
+    }
+}
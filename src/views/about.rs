@@ -1,3 +1,4 @@
+use crate::errors::AppError;
 use crate::models::customer::ProfileCustomer;
 use crate::models::state::AppState;
 use axum::Extension;
@@ -9,10 +10,12 @@ use std::sync::Arc;
 pub async fn about(
     State(state): State<Arc<AppState>>,
     Extension(customer_user): Extension<ProfileCustomer>,
-) -> Html<String> {
-    let template = state.tpl_env.get_template("about.html").unwrap();
-    let r = template
-        .render(context!(customer_user => customer_user))
-        .unwrap();
-    Html(r)
+) -> Result<Html<String>, AppError> {
+    let template = state.tpl_env.get_template("about.html")?;
+    let r = template.render(context!(customer_user => customer_user))?;
+    Ok(Html(r))
+}
+
+pub async fn not_found() -> AppError {
+    AppError::NotFound
 }
@@ -0,0 +1,106 @@
+use crate::errors::AppError;
+use crate::models::address::AddressForm;
+use crate::models::customer::ProfileCustomer;
+use crate::models::state::AppState;
+use crate::repository::address_repository::AddressRepository;
+use crate::services::validation::AddressValidator;
+use axum::extract::{Path, State};
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::{Extension, Form};
+use minijinja::context;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+pub async fn get_addresses_page(
+    State(state): State<Arc<AppState>>,
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+) -> Result<Html<String>, AppError> {
+    let addresses = AddressRepository::list_addresses(&pool, customer_user.id).await?;
+    let template = state.tpl_env.get_template("addresses.html")?;
+    let r = template.render(context!(customer_user => customer_user, addresses => addresses))?;
+    Ok(Html(r))
+}
+
+pub async fn get_new_address_page(
+    State(state): State<Arc<AppState>>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+) -> Result<Html<String>, AppError> {
+    let template = state.tpl_env.get_template("address-form.html")?;
+    let r = template.render(context!(customer_user => customer_user))?;
+    Ok(Html(r))
+}
+
+pub async fn post_new_address(
+    State(state): State<Arc<AppState>>,
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+    Form(form): Form<AddressForm>,
+) -> Result<Response, AppError> {
+    match AddressValidator::validate_address(&form) {
+        Ok(address) => {
+            AddressRepository::create_address(&pool, customer_user.id, &address).await?;
+            Ok(Redirect::to("/profile/addresses").into_response())
+        }
+        Err(form_errors) => {
+            let template = state.tpl_env.get_template("address-form.html")?;
+            let r = template.render(context!(
+                customer_user => customer_user,
+                form_errors => form_errors,
+                form => form,
+            ))?;
+            Ok(Html(r).into_response())
+        }
+    }
+}
+
+pub async fn get_edit_address_page(
+    State(state): State<Arc<AppState>>,
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+    Path(address_id): Path<i64>,
+) -> Result<Html<String>, AppError> {
+    let address = AddressRepository::get_address(&pool, address_id, customer_user.id).await?;
+    let template = state.tpl_env.get_template("address-form.html")?;
+    let r = template.render(context!(
+        customer_user => customer_user,
+        address_id => address.id,
+        form => address,
+    ))?;
+    Ok(Html(r))
+}
+
+pub async fn post_edit_address(
+    State(state): State<Arc<AppState>>,
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+    Path(address_id): Path<i64>,
+    Form(form): Form<AddressForm>,
+) -> Result<Response, AppError> {
+    match AddressValidator::validate_address(&form) {
+        Ok(address) => {
+            AddressRepository::update_address(&pool, address_id, customer_user.id, &address)
+                .await?;
+            Ok(Redirect::to("/profile/addresses").into_response())
+        }
+        Err(form_errors) => {
+            let template = state.tpl_env.get_template("address-form.html")?;
+            let r = template.render(context!(
+                customer_user => customer_user,
+                address_id => address_id,
+                form_errors => form_errors,
+                form => form,
+            ))?;
+            Ok(Html(r).into_response())
+        }
+    }
+}
+
+pub async fn post_delete_address(
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+    Path(address_id): Path<i64>,
+) -> Result<Redirect, AppError> {
+    AddressRepository::delete_address(&pool, address_id, customer_user.id).await?;
+    Ok(Redirect::to("/profile/addresses"))
+}
@@ -0,0 +1,109 @@
+use crate::errors::AppError;
+use crate::models::cart::{RemoveCartItemForm, UpdateCartItemForm};
+use crate::models::customer::ProfileCustomer;
+use crate::models::money::ExchangeRate;
+use crate::models::order::NewOrderForm;
+use crate::models::state::AppState;
+use crate::repository::cart_repository::CartRepository;
+use crate::repository::product_repository::ProductRepository;
+use axum::extract::State;
+use axum::response::{Html, Redirect};
+use axum::{Extension, Form};
+use minijinja::context;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+const MAX_ITEM_QUANTITY: i32 = 10;
+
+pub async fn get_cart(
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+    Extension(rate): Extension<ExchangeRate>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Html<String>, AppError> {
+    let mut cart = CartRepository::get_cart(&pool, customer_user.id).await?;
+    cart.convert_prices(&rate);
+    let template = state.tpl_env.get_template("cart.html")?;
+    let needs_verified_email = state.require_verified_email && !customer_user.is_email_verified;
+    let r = template.render(context!(
+        customer_user => customer_user,
+        cart_total => cart.total()?,
+        cart => cart,
+        needs_verified_email => needs_verified_email,
+    ))?;
+    Ok(Html(r))
+}
+
+pub async fn post_add_product_to_cart(
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+    Form(form): Form<NewOrderForm>,
+) -> Result<Redirect, AppError> {
+    if !(1..=MAX_ITEM_QUANTITY).contains(&form.quantity) {
+        return Err(AppError::Validation(format!(
+            "Quantity must be between 1 and {}",
+            MAX_ITEM_QUANTITY
+        )));
+    }
+
+    // make sure the variant exists and is on sale before it lands in the cart
+    let product = ProductRepository::get_variant_by_id(form.variant_id, &pool).await?;
+    if product.stock < form.quantity {
+        return Err(AppError::Validation(match product.stock {
+            0 => "Sorry, this size is sold out".to_string(),
+            left => format!("Sorry, only {} left in this size", left),
+        }));
+    }
+    // the same limits hold for what is already in the cart plus the new units
+    let max_quantity = product.stock.min(MAX_ITEM_QUANTITY);
+    let added = CartRepository::add_item(
+        &pool,
+        customer_user.id,
+        product.id,
+        product.variant_id,
+        form.quantity,
+        max_quantity,
+    )
+    .await?;
+    if !added {
+        return Err(AppError::Validation(if max_quantity < MAX_ITEM_QUANTITY {
+            format!(
+                "Sorry, only {} left in this size and some are already in your cart",
+                max_quantity
+            )
+        } else {
+            format!(
+                "You can have at most {} of this size in your cart",
+                MAX_ITEM_QUANTITY
+            )
+        }));
+    }
+    Ok(Redirect::to("/cart"))
+}
+
+pub async fn post_update_cart_item(
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+    Form(form): Form<UpdateCartItemForm>,
+) -> Result<Redirect, AppError> {
+    // zero quantity removes the line
+    if !(0..=MAX_ITEM_QUANTITY).contains(&form.quantity) {
+        return Err(AppError::Validation(format!(
+            "Quantity must be between 0 and {}",
+            MAX_ITEM_QUANTITY
+        )));
+    }
+
+    CartRepository::update_item_quantity(&pool, customer_user.id, form.variant_id, form.quantity)
+        .await?;
+    Ok(Redirect::to("/cart"))
+}
+
+pub async fn post_remove_cart_item(
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+    Form(form): Form<RemoveCartItemForm>,
+) -> Result<Redirect, AppError> {
+    CartRepository::remove_item(&pool, customer_user.id, form.variant_id).await?;
+    Ok(Redirect::to("/cart"))
+}
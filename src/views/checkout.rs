@@ -0,0 +1,357 @@
+use crate::errors::AppError;
+use crate::models::address::AddressSnapshot;
+use crate::models::cart::CheckoutForm;
+use crate::models::customer::ProfileCustomer;
+use crate::models::money::ExchangeRate;
+use crate::models::order::OrderDetails;
+use crate::models::promotion::CouponForm;
+use crate::models::shipping::{ShippingMethodForm, ShippingOption};
+use crate::models::state::AppState;
+use crate::models::tax::TaxRates;
+use crate::repository::address_repository::AddressRepository;
+use crate::repository::cart_repository::CartRepository;
+use crate::repository::order_repository::OrderRepository;
+use crate::repository::shipping_repository::ShippingRepository;
+use crate::repository::tax_repository::TaxRepository;
+use crate::services::checkout::Checkout;
+use crate::services::promotions::{PromotionError, Promotions};
+use axum::extract::State;
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::{Extension, Form};
+use minijinja::context;
+use sqlx::PgPool;
+use std::sync::Arc;
+use uuid::Uuid;
+
+// steps: cart -> address -> shipping -> review -> confirm, the draft order carries the choices
+pub async fn post_start_checkout(
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+    Extension(rate): Extension<ExchangeRate>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Redirect, AppError> {
+    check_email_verified(&state, &customer_user)?;
+
+    // charged in the currency the customer sees, at the rate shown
+    let mut cart = CartRepository::get_cart(&pool, customer_user.id).await?;
+    cart.convert_prices(&rate);
+    if cart.is_empty() {
+        return Ok(Redirect::to("/cart"));
+    }
+
+    let addresses = AddressRepository::list_addresses(&pool, customer_user.id).await?;
+    let shipping_address = addresses
+        .iter()
+        .find(|address| address.is_default_shipping)
+        .map(AddressSnapshot::from);
+    let billing_address = addresses
+        .iter()
+        .find(|address| address.is_default_billing)
+        .map(AddressSnapshot::from);
+
+    let mut tx = pool.begin().await?;
+    OrderRepository::save_draft(
+        &mut tx,
+        customer_user.id,
+        &cart.items,
+        &rate,
+        shipping_address.as_ref(),
+        billing_address.as_ref(),
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(Redirect::to("/checkout/address"))
+}
+
+pub async fn get_checkout_address(
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, AppError> {
+    let Some(order) = load_draft(&pool, customer_user.id).await? else {
+        return Ok(Redirect::to("/cart").into_response());
+    };
+    let addresses = AddressRepository::list_addresses(&pool, customer_user.id).await?;
+    let template = state.tpl_env.get_template("checkout-address.html")?;
+    let r = template.render(context!(
+        customer_user => customer_user,
+        order => order,
+        addresses => addresses,
+    ))?;
+    Ok(Html(r).into_response())
+}
+
+pub async fn post_checkout_address(
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+    Form(form): Form<CheckoutForm>,
+) -> Result<Redirect, AppError> {
+    let Some(order_id) = OrderRepository::get_draft_id(&pool, customer_user.id).await? else {
+        return Ok(Redirect::to("/cart"));
+    };
+    let Some(shipping_address_id) = form.shipping_address_id else {
+        return Err(AppError::Validation(
+            "Please choose a shipping address".to_string(),
+        ));
+    };
+    // RowNotFound for somebody else's address -> 404
+    let shipping_address =
+        AddressRepository::get_address(&pool, shipping_address_id, customer_user.id).await?;
+    let billing_address = match form.billing_address_id {
+        Some(id) if id != shipping_address_id => {
+            AddressRepository::get_address(&pool, id, customer_user.id).await?
+        }
+        _ => AddressRepository::get_address(&pool, shipping_address_id, customer_user.id).await?,
+    };
+
+    OrderRepository::set_draft_addresses(
+        &pool,
+        order_id,
+        customer_user.id,
+        &AddressSnapshot::from(&shipping_address),
+        &AddressSnapshot::from(&billing_address),
+    )
+    .await?;
+    Ok(Redirect::to("/checkout/shipping"))
+}
+
+pub async fn get_checkout_shipping(
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, AppError> {
+    let Some(order) = load_draft(&pool, customer_user.id).await? else {
+        return Ok(Redirect::to("/cart").into_response());
+    };
+    let Some(country) = order.shipping_address.as_ref().map(|a| a.country.clone()) else {
+        return Ok(Redirect::to("/checkout/address").into_response());
+    };
+
+    let goods_total = order.goods_total()?;
+    let options = ShippingRepository::list_rates_for_country(&pool, &country)
+        .await?
+        .into_iter()
+        .map(|rate| ShippingOption {
+            cost: rate.cost(goods_total, &order.exchange_rate()),
+            rate,
+        })
+        .collect::<Vec<_>>();
+
+    let template = state.tpl_env.get_template("checkout-shipping.html")?;
+    let r = template.render(context!(
+        customer_user => customer_user,
+        order => order,
+        options => options,
+    ))?;
+    Ok(Html(r).into_response())
+}
+
+pub async fn post_checkout_shipping(
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+    Form(form): Form<ShippingMethodForm>,
+) -> Result<Redirect, AppError> {
+    let Some(order) = load_draft(&pool, customer_user.id).await? else {
+        return Ok(Redirect::to("/cart"));
+    };
+    let Some(country) = order.shipping_address.as_ref().map(|a| a.country.as_str()) else {
+        return Ok(Redirect::to("/checkout/address"));
+    };
+    let shipping = ShippingRepository::get_rate_for_country(&pool, form.shipping_rate_id, country)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => AppError::Validation(
+                "This shipping method is not available for your address".to_string(),
+            ),
+            e => AppError::Database(e),
+        })?;
+
+    let order_id = Uuid::parse_str(&order.order_id).map_err(|_| AppError::NotFound)?;
+    OrderRepository::set_draft_shipping(&pool, order_id, customer_user.id, &shipping).await?;
+    Ok(Redirect::to("/checkout/review"))
+}
+
+pub async fn get_checkout_review(
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, AppError> {
+    render_review(&state, &pool, customer_user, None).await
+}
+
+pub async fn post_checkout_coupon(
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+    State(state): State<Arc<AppState>>,
+    Form(form): Form<CouponForm>,
+) -> Result<Response, AppError> {
+    let Some(order) = load_draft(&pool, customer_user.id).await? else {
+        return Ok(Redirect::to("/cart").into_response());
+    };
+    let order_id = Uuid::parse_str(&order.order_id).map_err(|_| AppError::NotFound)?;
+
+    let code = form.coupon_code.trim();
+    let promotion = if code.is_empty() {
+        None
+    } else {
+        // only a check here, the use is recorded when the order is confirmed
+        let mut tx = pool.begin().await?;
+        let applied = Promotions::apply(
+            &mut tx,
+            code,
+            customer_user.id,
+            &order.lines,
+            &order.exchange_rate(),
+        )
+        .await;
+        tx.rollback().await?;
+        match applied {
+            Ok(applied) => Some(applied),
+            Err(PromotionError::Database(e)) => return Err(AppError::Database(e)),
+            Err(e) => {
+                return render_review(&state, &pool, customer_user, Some((code, e))).await;
+            }
+        }
+    };
+    OrderRepository::set_draft_promotion(&pool, order_id, customer_user.id, promotion.as_ref())
+        .await?;
+    Ok(Redirect::to("/checkout/review").into_response())
+}
+
+pub async fn post_checkout_confirm(
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Redirect, AppError> {
+    check_email_verified(&state, &customer_user)?;
+
+    // the lock keeps a second tab from confirming or refilling the draft at the same time
+    let mut tx = pool.begin().await?;
+    let Some(order_id) = OrderRepository::lock_draft(&mut tx, customer_user.id).await? else {
+        return Ok(Redirect::to("/cart"));
+    };
+    let mut order =
+        OrderRepository::get_order_by_uuid_and_customer(&pool, order_id, customer_user.id).await?;
+    let Some(country) = order.shipping_address.as_ref().map(|a| a.country.clone()) else {
+        return Ok(Redirect::to("/checkout/address"));
+    };
+    let Some(shipping_rate_id) = order.shipping_rate_id else {
+        return Ok(Redirect::to("/checkout/shipping"));
+    };
+    let shipping = ShippingRepository::get_rate_for_country(&pool, shipping_rate_id, &country)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => AppError::Validation(
+                "This shipping method is not available anymore, please choose another one"
+                    .to_string(),
+            ),
+            e => AppError::Database(e),
+        })?;
+
+    // the coupon is checked again, it may have run out since it was entered
+    let promotion = match order.promotion_code.clone() {
+        Some(code) => Some(
+            Promotions::apply(
+                &mut tx,
+                &code,
+                customer_user.id,
+                &order.lines,
+                &order.exchange_rate(),
+            )
+            .await?,
+        ),
+        None => None,
+    };
+    order.discount = promotion.as_ref().map(|promotion| promotion.discount);
+    let tax_rates = load_tax_rates(&pool, &order, &country).await?;
+    Checkout::price_draft(&mut order, Some(&shipping), &tax_rates)?;
+
+    let created_order =
+        OrderRepository::confirm_order(&mut tx, &order, customer_user.id, promotion.as_ref())
+            .await?;
+    CartRepository::remove_ordered(&mut tx, customer_user.id, &order.lines).await?;
+    tx.commit().await?;
+    tracing::info!(
+        "Order {} placed by customer {}, total {}",
+        created_order.order_id,
+        customer_user.id,
+        created_order.total
+    );
+    Ok(Redirect::to(&format!(
+        "/order/{}/pay",
+        created_order.order_id
+    )))
+}
+
+fn check_email_verified(state: &AppState, customer_user: &ProfileCustomer) -> Result<(), AppError> {
+    if state.require_verified_email && !customer_user.is_email_verified {
+        return Err(AppError::Validation(
+            "Please confirm your email address before placing an order".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+async fn load_draft(pool: &PgPool, customer_id: i64) -> Result<Option<OrderDetails>, AppError> {
+    let Some(order_id) = OrderRepository::get_draft_id(pool, customer_id).await? else {
+        return Ok(None);
+    };
+    let order =
+        OrderRepository::get_order_by_uuid_and_customer(pool, order_id, customer_id).await?;
+    Ok(Some(order))
+}
+
+// the shipping country decides the tax; one without rates is refused rather than charged none
+async fn load_tax_rates(
+    pool: &PgPool,
+    order: &OrderDetails,
+    country: &str,
+) -> Result<TaxRates, AppError> {
+    let product_ids: Vec<i32> = order.lines.iter().map(|line| line.product_id).collect();
+    TaxRepository::rates_for_country(pool, country, &product_ids)
+        .await?
+        .ok_or_else(|| {
+            AppError::Validation(format!(
+                "Sorry, we can't deliver to {} yet, please choose another shipping address",
+                country
+            ))
+        })
+}
+
+// `coupon_error` is the code that was just rejected and why
+async fn render_review(
+    state: &AppState,
+    pool: &PgPool,
+    customer_user: ProfileCustomer,
+    coupon_error: Option<(&str, PromotionError)>,
+) -> Result<Response, AppError> {
+    let Some(mut order) = load_draft(pool, customer_user.id).await? else {
+        return Ok(Redirect::to("/cart").into_response());
+    };
+    let Some(country) = order.shipping_address.as_ref().map(|a| a.country.clone()) else {
+        return Ok(Redirect::to("/checkout/address").into_response());
+    };
+    let Some(shipping_rate_id) = order.shipping_rate_id else {
+        return Ok(Redirect::to("/checkout/shipping").into_response());
+    };
+    let shipping =
+        match ShippingRepository::get_rate_for_country(pool, shipping_rate_id, &country).await {
+            Ok(shipping) => shipping,
+            Err(sqlx::Error::RowNotFound) => {
+                return Ok(Redirect::to("/checkout/shipping").into_response());
+            }
+            Err(e) => return Err(AppError::Database(e)),
+        };
+    let tax_rates = load_tax_rates(pool, &order, &country).await?;
+    Checkout::price_draft(&mut order, Some(&shipping), &tax_rates)?;
+
+    let template = state.tpl_env.get_template("checkout-review.html")?;
+    let r = template.render(context!(
+        customer_user => customer_user,
+        needs_verified_email => state.require_verified_email && !customer_user.is_email_verified,
+        order => order,
+        coupon_code => coupon_error.as_ref().map(|(code, _)| *code),
+        coupon_error => coupon_error.as_ref().map(|(_, e)| e.to_string()),
+    ))?;
+    Ok(Html(r).into_response())
+}
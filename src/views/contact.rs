@@ -0,0 +1,52 @@
+use crate::errors::AppError;
+use crate::models::contact::ContactForm;
+use crate::models::customer::ProfileCustomer;
+use crate::models::state::AppState;
+use crate::repository::contact_repository::ContactRepository;
+use crate::services::validation::ContactValidator;
+use axum::extract::State;
+use axum::response::Html;
+use axum::{Extension, Form};
+use minijinja::context;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+pub async fn get_contact_page(
+    State(state): State<Arc<AppState>>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+) -> Result<Html<String>, AppError> {
+    let template = state.tpl_env.get_template("contact.html")?;
+    let r = template.render(context!(customer_user => customer_user))?;
+    Ok(Html(r))
+}
+
+pub async fn post_contact_page(
+    State(state): State<Arc<AppState>>,
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+    Form(form): Form<ContactForm>,
+) -> Result<Html<String>, AppError> {
+    let template = state.tpl_env.get_template("contact.html")?;
+
+    if let Err(form_errors) = ContactValidator::validate_contact_form(&form) {
+        let r = template.render(context!(
+            customer_user => customer_user,
+            form_errors => form_errors,
+            form => form,
+        ))?;
+        return Ok(Html(r));
+    }
+
+    let customer_id = customer_user.is_authenticated.then_some(customer_user.id);
+    ContactRepository::create_message(
+        &pool,
+        customer_id,
+        form.name.trim(),
+        &form.email.trim().to_lowercase(),
+        form.message.trim(),
+    )
+    .await?;
+
+    let r = template.render(context!(customer_user => customer_user, is_sent => true))?;
+    Ok(Html(r))
+}
@@ -0,0 +1,61 @@
+use crate::errors::AppError;
+use crate::models::money::{Currency, CurrencyForm};
+use crate::services::auth::AuthService;
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::{Extension, Form};
+use simple_cookie::SigningKey;
+
+pub async fn post_select_currency(
+    Extension(signing_key): Extension<SigningKey>,
+    Form(form): Form<CurrencyForm>,
+) -> Result<Response, AppError> {
+    let Some(currency) = Currency::from_code(&form.currency) else {
+        return Err(AppError::Validation("Unknown currency".to_string()));
+    };
+    let next = if is_local_path(&form.next) {
+        form.next.as_str()
+    } else {
+        "/"
+    };
+
+    let mut resp = Redirect::to(next).into_response();
+    resp.headers_mut().insert(
+        "Set-Cookie",
+        AuthService::create_currency_cookie_header(currency.code(), &signing_key)
+            .parse()
+            .unwrap(),
+    );
+    Ok(resp)
+}
+
+// only pages of this shop: browsers read "//host" and "/\host" as another site, and drop tabs and
+// line breaks before they look
+fn is_local_path(next: &str) -> bool {
+    let mut chars = next.chars();
+    chars.next() == Some('/')
+        && !matches!(chars.next(), Some('/' | '\\'))
+        && !next.chars().any(|c| c.is_control() || c.is_whitespace())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_local_path;
+
+    #[test]
+    fn only_paths_of_this_shop_are_followed() {
+        for next in ["/", "/products?page=2", "/product/42"] {
+            assert!(is_local_path(next), "{:?}", next);
+        }
+        for next in [
+            "",
+            "products",
+            "https://evil.example",
+            "//evil.example",
+            "/\\evil.example",
+            "/\t/evil.example",
+            "/\n/evil.example",
+        ] {
+            assert!(!is_local_path(next), "{:?}", next);
+        }
+    }
+}
@@ -1,121 +1,402 @@
-use crate::models::customer::{AcceptEnum, CustomerLoginPostForm, NewCustomer, ProfileCustomer};
+use crate::errors::AppError;
+use crate::middlewares::ClientInfo;
+use crate::models::customer::{
+    ChangeEmailForm, ChangePasswordForm, CustomerLoginPostForm, NewCustomerForm, ProfileCustomer,
+    UpdateProfileForm,
+};
+use crate::models::session::{CurrentSession, LoginOutcome, RecentFailures, RevokeSessionForm};
 use crate::models::state::AppState;
-use crate::repository::customer_repository::CustomerRepository;
+use crate::repository::customer_repository::{CustomerError, CustomerRepository};
+use crate::repository::email_verification_repository::EmailVerificationRepository;
+use crate::repository::login_attempt_repository::LoginAttemptRepository;
+use crate::repository::session_repository::SessionRepository;
 use crate::services::auth::AuthService;
-use axum::body::Body;
+use crate::services::login_throttle::{FAILURE_WINDOW_SECONDS, LoginThrottle};
+use crate::services::mailer::EmailMessage;
+use crate::services::validation::{CustomerValidator, FormErrors};
+use crate::views::email_verification::send_verification_email;
 use axum::extract::State;
+use axum::http::StatusCode;
 use axum::response::{Html, IntoResponse, Redirect, Response};
 use axum::{Extension, Form};
-use minijinja::context;
+use minijinja::{Value, context};
 use simple_cookie::SigningKey;
 use sqlx::PgPool;
 use std::collections::HashMap;
 use std::sync::Arc;
 
-pub async fn get_customer_registration_page(State(state): State<Arc<AppState>>) -> Html<String> {
-    let template = state.tpl_env.get_template("registration.html").unwrap();
-    let r = template.render(context!()).unwrap();
-    Html(r)
+pub async fn get_customer_registration_page(
+    State(state): State<Arc<AppState>>,
+) -> Result<Html<String>, AppError> {
+    let template = state.tpl_env.get_template("registration.html")?;
+    let r = template.render(context!())?;
+    Ok(Html(r))
 }
 
 pub async fn post_customer_registration_page(
     State(state): State<Arc<AppState>>,
     Extension(pool): Extension<PgPool>,
     Extension(signed_key): Extension<SigningKey>,
-    Form(customer): Form<NewCustomer>,
-) -> Response {
-    let mut form_errors = HashMap::new();
+    client: ClientInfo,
+    Form(form): Form<NewCustomerForm>,
+) -> Result<Response, AppError> {
+    let template = state.tpl_env.get_template("registration.html")?;
 
-    if let AcceptEnum::Off = customer.accept_all {
-        form_errors.insert("accept_all", "Required field!");
-    }
-    let template = state.tpl_env.get_template("registration.html").unwrap();
+    let form_errors = match CustomerValidator::validate_new_customer(&form) {
+        Ok(customer) => match CustomerRepository::create_customer(&pool, &customer).await {
+            Ok(customer_id) => {
+                send_verification_email(&state, &pool, customer_id, &customer.email).await?;
+                let cookie_value = start_session(&pool, customer_id, &client, &signed_key).await?;
+                let r = template.render(context!(is_register_ok => true))?;
 
-    let customer_id = CustomerRepository::create_customer(&pool, customer).await;
+                let mut resp = Html(r).into_response();
+                resp.headers_mut()
+                    .insert("Set-Cookie", cookie_value.parse().unwrap());
+                resp.headers_mut().append(
+                    "Set-Cookie",
+                    AuthService::clear_csrf_cookie_header().parse().unwrap(),
+                );
+                return Ok(resp);
+            }
+            Err(CustomerError::DuplicateEmail) => FormErrors::from([(
+                "email",
+                "An account with this email already exists. Try to log in instead.".to_string(),
+            )]),
+            Err(CustomerError::ConnectionFailure) => FormErrors::from([(
+                "error",
+                "The service is temporarily unavailable, please try again in a few minutes"
+                    .to_string(),
+            )]),
+            Err(e) => {
+                tracing::info!("Error register NewCustomer: {:?}", e);
+                FormErrors::from([(
+                    "error",
+                    "Error register customer user, please send this to the support".to_string(),
+                )])
+            }
+        },
+        Err(form_errors) => form_errors,
+    };
 
-    match customer_id {
-        Ok(customer_id) => {
-            let cookie_value = AuthService::create_cookie_header(customer_id, &signed_key);
-            let r = template.render(context!(is_register_ok => true)).unwrap();
+    let r = template.render(context!(form_errors => form_errors, form => form))?;
+    Ok(Html(r).into_response())
+}
 
-            let mut resp = Response::builder().status(200).body(Body::from(r)).unwrap();
-            resp.headers_mut()
-                .insert("Set-Cookie", cookie_value.parse().unwrap());
+pub async fn get_profile_customer_page(
+    State(state): State<Arc<AppState>>,
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+    Extension(current_session): Extension<CurrentSession>,
+) -> Result<Html<String>, AppError> {
+    render_profile_page(&state, &pool, &customer_user, &current_session, context!()).await
+}
 
-            return resp;
+pub async fn post_update_profile(
+    State(state): State<Arc<AppState>>,
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+    Extension(current_session): Extension<CurrentSession>,
+    Form(form): Form<UpdateProfileForm>,
+) -> Result<Html<String>, AppError> {
+    let update = match CustomerValidator::validate_profile_update(&form) {
+        Ok(update) => update,
+        Err(form_errors) => {
+            let extra = context!(form_errors => form_errors, form => form);
+            return render_profile_page(&state, &pool, &customer_user, &current_session, extra)
+                .await;
         }
-        Err(e) => {
-            tracing::info!("Error register NewCustomer: {:?}", e);
-            form_errors.insert(
-                "error",
-                "Error register customer user, please send this to the support",
+    };
+
+    CustomerRepository::update_profile(&pool, customer_user.id, &update).await?;
+    // `customer_user` was loaded before the update
+    let customer_user = CustomerRepository::verify_customer(&pool, customer_user.id).await?;
+    let extra = context!(is_profile_saved => true);
+    render_profile_page(&state, &pool, &customer_user, &current_session, extra).await
+}
+
+pub async fn post_change_password(
+    State(state): State<Arc<AppState>>,
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+    Extension(current_session): Extension<CurrentSession>,
+    Form(form): Form<ChangePasswordForm>,
+) -> Result<Html<String>, AppError> {
+    let mut password_errors =
+        CustomerValidator::validate_new_password(&form.password, &form.confirm_password)
+            .err()
+            .unwrap_or_default();
+    match CustomerRepository::check_password(&pool, customer_user.id, &form.current_password).await
+    {
+        Ok(()) => {}
+        Err(CustomerError::InvalidCredentials) => {
+            password_errors.insert(
+                "current_password",
+                "Current password is incorrect".to_string(),
             );
         }
+        Err(e) => return Err(e.into()),
     }
 
-    let r = template
-        .render(context!(form_errors => form_errors))
-        .unwrap();
+    if !password_errors.is_empty() {
+        let extra = context!(password_errors => password_errors);
+        return render_profile_page(&state, &pool, &customer_user, &current_session, extra).await;
+    }
 
-    Response::builder().status(200).body(Body::from(r)).unwrap()
+    CustomerRepository::update_password(&pool, customer_user.id, &form.password).await?;
+    SessionRepository::revoke_other_sessions(&pool, customer_user.id, current_session.id).await?;
+    let extra = context!(is_password_changed => true);
+    render_profile_page(&state, &pool, &customer_user, &current_session, extra).await
 }
 
-pub async fn get_profile_customer_page(
+// the new address only replaces the current one after it has been confirmed from the link
+pub async fn post_change_email(
     State(state): State<Arc<AppState>>,
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+    Extension(current_session): Extension<CurrentSession>,
+    Form(form): Form<ChangeEmailForm>,
+) -> Result<Html<String>, AppError> {
+    let email = form.email.trim().to_lowercase();
+    let mut email_errors = FormErrors::new();
+    if let Err(e) = CustomerValidator::validate_email(&email) {
+        email_errors.insert("email", e);
+    } else if email == customer_user.email {
+        email_errors.insert("email", "This is already your email address".to_string());
+    } else if CustomerRepository::find_customer_id_by_email(&pool, &email)
+        .await?
+        .is_some()
+    {
+        email_errors.insert(
+            "email",
+            "An account with this email already exists".to_string(),
+        );
+    }
+    match CustomerRepository::check_password(&pool, customer_user.id, &form.current_password).await
+    {
+        Ok(()) => {}
+        Err(CustomerError::InvalidCredentials) => {
+            email_errors.insert(
+                "current_password",
+                "Current password is incorrect".to_string(),
+            );
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    if !email_errors.is_empty() {
+        let extra = context!(email_errors => email_errors, email_form => form);
+        return render_profile_page(&state, &pool, &customer_user, &current_session, extra).await;
+    }
+
+    EmailVerificationRepository::invalidate_tokens(&pool, customer_user.id).await?;
+    send_verification_email(&state, &pool, customer_user.id, &email).await?;
+    let notice = EmailMessage {
+        to: customer_user.email.clone(),
+        subject: "Your email address is being changed".to_string(),
+        body: format!(
+            "Somebody asked to change the email address of your account to {}.\r\n\r\n\
+             The change takes effect once the new address is confirmed. \
+             If it wasn't you, change your password right away.",
+            email
+        ),
+    };
+    if let Err(e) = state.mailer.send(&notice).await {
+        tracing::error!("{}", e);
+    }
+
+    render_profile_page(&state, &pool, &customer_user, &current_session, context!()).await
+}
+
+pub async fn post_revoke_session(
+    Extension(pool): Extension<PgPool>,
     Extension(customer_user): Extension<ProfileCustomer>,
-) -> Html<String> {
-    let template = state.tpl_env.get_template("profile.html").unwrap();
-    let r = template
-        .render(context!(customer_user => customer_user))
-        .unwrap();
-    Html(r)
+    Extension(current_session): Extension<CurrentSession>,
+    Form(form): Form<RevokeSessionForm>,
+) -> Result<Response, AppError> {
+    SessionRepository::revoke_session(&pool, form.session_id, customer_user.id).await?;
+
+    if form.session_id == current_session.id {
+        return Ok(signed_out_redirect("/login"));
+    }
+    Ok(Redirect::to("/profile").into_response())
 }
 
-pub async fn get_customer_login_page(State(state): State<Arc<AppState>>) -> Html<String> {
-    let template = state.tpl_env.get_template("login.html").unwrap();
-    let r = template.render(context!()).unwrap();
-    Html(r)
+pub async fn post_revoke_all_sessions(
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+) -> Result<Response, AppError> {
+    SessionRepository::revoke_all_sessions(&pool, customer_user.id).await?;
+    Ok(signed_out_redirect("/login"))
+}
+
+pub async fn get_customer_login_page(
+    State(state): State<Arc<AppState>>,
+) -> Result<Html<String>, AppError> {
+    let template = state.tpl_env.get_template("login.html")?;
+    let r = template.render(context!())?;
+    Ok(Html(r))
 }
 
 pub async fn post_customer_login_page(
     State(state): State<Arc<AppState>>,
     Extension(pool): Extension<PgPool>,
     Extension(signed_key): Extension<SigningKey>,
+    client: ClientInfo,
     Form(form): Form<CustomerLoginPostForm>,
-) -> Result<Response, Html<String>> {
+) -> Result<Response, AppError> {
     // post form perform
 
-    let template = state.tpl_env.get_template("login.html").unwrap();
+    let template = state.tpl_env.get_template("login.html")?;
+    let email = form.email.trim().to_lowercase();
+    let ip_address = client.ip_address.as_deref();
+    let user_agent = client.user_agent.as_deref();
+
+    // throttling is decided before bcrypt runs, blocked attempts don't extend the lockout
+    let email_failures =
+        LoginAttemptRepository::recent_email_failures(&pool, &email, FAILURE_WINDOW_SECONDS)
+            .await?;
+    let ip_failures = match ip_address {
+        Some(ip) => {
+            LoginAttemptRepository::recent_ip_failures(&pool, ip, FAILURE_WINDOW_SECONDS).await?
+        }
+        None => RecentFailures::default(),
+    };
+    if let Some(login_block) = LoginThrottle::check(email_failures, ip_failures) {
+        LoginAttemptRepository::record_attempt(
+            &pool,
+            &email,
+            ip_address,
+            user_agent,
+            None,
+            LoginOutcome::Blocked,
+        )
+        .await?;
+        let r = template.render(context!(login_block => login_block))?;
+        return Ok((StatusCode::TOO_MANY_REQUESTS, Html(r)).into_response());
+    }
 
     let result = CustomerRepository::get_user_by_email_password(
         &pool,
-        form.email,
+        email.clone(),
         form.password,
         form.confirm_password,
     )
     .await;
     match result {
         Ok(customer_user) => {
-            let r = template.render(context!(is_login_ok => true)).unwrap();
-            let cookie_value = AuthService::create_cookie_header(customer_user.id, &signed_key);
-            let mut resp = Response::builder().status(200).body(Body::from(r)).unwrap();
+            LoginAttemptRepository::record_attempt(
+                &pool,
+                &email,
+                ip_address,
+                user_agent,
+                Some(customer_user.id),
+                LoginOutcome::Success,
+            )
+            .await?;
+            let cookie_value = start_session(&pool, customer_user.id, &client, &signed_key).await?;
+            let r = template.render(context!(is_login_ok => true))?;
+            let mut resp = Html(r).into_response();
             resp.headers_mut()
                 .insert("Set-Cookie", cookie_value.parse().unwrap());
+            resp.headers_mut().append(
+                "Set-Cookie",
+                AuthService::clear_csrf_cookie_header().parse().unwrap(),
+            );
             Ok(resp)
         }
-        Err(_) => {
+        Err(e) => {
+            // unknown email and wrong password share a message so the form can't be used to probe accounts
+            let message = match e {
+                CustomerError::NotFound | CustomerError::InvalidCredentials => {
+                    LoginAttemptRepository::record_attempt(
+                        &pool,
+                        &email,
+                        ip_address,
+                        user_agent,
+                        None,
+                        LoginOutcome::InvalidCredentials,
+                    )
+                    .await?;
+                    "User not found or password is incorrect"
+                }
+                CustomerError::PasswordMismatch => "Password and confirmation do not match",
+                CustomerError::ConnectionFailure => {
+                    "The service is temporarily unavailable, please try again in a few minutes"
+                }
+                e => {
+                    tracing::error!("Error login customer: {:?}", e);
+                    "Error login customer user, please send this to the support"
+                }
+            };
             let mut form_errors = HashMap::new();
-            form_errors.insert("error", "User not found or password is incorrect");
-            let r = template
-                .render(context!(form_errors => form_errors))
-                .unwrap();
-            Err(Html(r))
+            form_errors.insert("error", message);
+            let r = template.render(context!(form_errors => form_errors))?;
+            Ok(Html(r).into_response())
         }
     }
 }
 
-pub async fn logout_customer() -> Response {
-    let mut resp = Redirect::permanent("/").into_response();
-    resp.headers_mut().insert("Set-Cookie", "PHPSESSID=; HttpOnly; Secure; SameSite=Strict; Max-Age=0; expires=Thu, 01 Jan 1970 00:00:00 GMT".parse().unwrap());
+pub async fn logout_customer(
+    Extension(pool): Extension<PgPool>,
+    current_session: Option<Extension<CurrentSession>>,
+) -> Result<Response, AppError> {
+    if let Some(Extension(session)) = current_session {
+        SessionRepository::revoke_session(&pool, session.id, session.customer_id).await?;
+    }
+    Ok(signed_out_redirect("/"))
+}
+
+// profile.html hosts several forms, `extra` carries the result of the one that was posted
+async fn render_profile_page(
+    state: &AppState,
+    pool: &PgPool,
+    customer_user: &ProfileCustomer,
+    current_session: &CurrentSession,
+    extra: Value,
+) -> Result<Html<String>, AppError> {
+    let sessions = SessionRepository::list_active_sessions(pool, customer_user.id).await?;
+    let pending_email = EmailVerificationRepository::pending_email_change(
+        pool,
+        customer_user.id,
+        &customer_user.email,
+    )
+    .await?;
+    let template = state.tpl_env.get_template("profile.html")?;
+    let r = template.render(context!(
+        customer_user => customer_user,
+        sessions => sessions,
+        current_session_id => current_session.id,
+        pending_email => pending_email,
+        ..extra
+    ))?;
+    Ok(Html(r))
+}
+
+async fn start_session(
+    pool: &PgPool,
+    customer_id: i64,
+    client: &ClientInfo,
+    signing_key: &SigningKey,
+) -> Result<String, sqlx::Error> {
+    let token = SessionRepository::create_session(
+        pool,
+        customer_id,
+        client.user_agent.as_deref(),
+        client.ip_address.as_deref(),
+    )
+    .await?;
+    Ok(AuthService::create_cookie_header(&token, signing_key))
+}
+
+fn signed_out_redirect(location: &str) -> Response {
+    let mut resp = Redirect::to(location).into_response();
+    resp.headers_mut().insert(
+        "Set-Cookie",
+        AuthService::clear_cookie_header().parse().unwrap(),
+    );
+    resp.headers_mut().append(
+        "Set-Cookie",
+        AuthService::clear_csrf_cookie_header().parse().unwrap(),
+    );
     resp
 }
@@ -0,0 +1,69 @@
+use crate::errors::AppError;
+use crate::models::customer::ProfileCustomer;
+use crate::models::state::AppState;
+use crate::repository::customer_repository::CustomerError;
+use crate::repository::email_verification_repository::EmailVerificationRepository;
+use crate::services::mailer::EmailMessage;
+use axum::Extension;
+use axum::extract::{Path, State};
+use axum::response::{Html, Redirect};
+use minijinja::context;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+pub async fn get_verify_email_page(
+    State(state): State<Arc<AppState>>,
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+    Path(token): Path<String>,
+) -> Result<Html<String>, AppError> {
+    let (is_verified, is_duplicate) =
+        match EmailVerificationRepository::verify_email(&pool, &token).await {
+            Ok(_) => (true, false),
+            Err(CustomerError::NotFound) => (false, false),
+            Err(CustomerError::DuplicateEmail) => (false, true),
+            Err(e) => return Err(e.into()),
+        };
+    let template = state.tpl_env.get_template("verify-email.html")?;
+    let r = template.render(context!(
+        customer_user => customer_user,
+        is_verified => is_verified,
+        is_duplicate => is_duplicate,
+    ))?;
+    Ok(Html(r))
+}
+
+pub async fn post_resend_verification_email(
+    State(state): State<Arc<AppState>>,
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+) -> Result<Redirect, AppError> {
+    if !customer_user.is_email_verified {
+        send_verification_email(&state, &pool, customer_user.id, &customer_user.email).await?;
+    }
+    Ok(Redirect::to("/profile"))
+}
+
+// a mail failure is only logged, the customer can ask for another link from the profile page
+pub(crate) async fn send_verification_email(
+    state: &AppState,
+    pool: &PgPool,
+    customer_id: i64,
+    email: &str,
+) -> Result<(), CustomerError> {
+    let token = EmailVerificationRepository::create_token(pool, customer_id, email).await?;
+    let message = EmailMessage {
+        to: email.to_string(),
+        subject: "Confirm your email address".to_string(),
+        body: format!(
+            "Please confirm your email address by following this link within 48 hours:\r\n\
+             {}/verify-email/{}\r\n\r\n\
+             If you didn't create an account, just ignore this email.",
+            state.base_url, token
+        ),
+    };
+    if let Err(e) = state.mailer.send(&message).await {
+        tracing::error!("{}", e);
+    }
+    Ok(())
+}
@@ -1,6 +1,9 @@
+use crate::errors::AppError;
 use crate::models::customer::ProfileCustomer;
+use crate::models::money::ExchangeRate;
 use crate::models::state::AppState;
 use crate::repository::product_repository::ProductRepository;
+use crate::services::taxes::Taxes;
 use axum::Extension;
 use axum::extract::State;
 use axum::response::Html;
@@ -12,15 +15,24 @@ pub async fn home(
     State(state): State<Arc<AppState>>,
     Extension(pool): Extension<PgPool>,
     Extension(customer_user): Extension<ProfileCustomer>,
-) -> Html<String> {
-    let map_products = ProductRepository::get_latest_products_for_main(&pool, 5)
-        .await
-        .unwrap();
-    let template = state.tpl_env.get_template("index.html").unwrap();
-    let r = template
-        .render(
-            context!(latest_categories_products => map_products, customer_user => customer_user),
-        )
-        .unwrap();
-    Html(r)
+    Extension(rate): Extension<ExchangeRate>,
+) -> Result<Html<String>, AppError> {
+    let mut map_products = ProductRepository::get_latest_products_for_main(&pool, 5).await?;
+    let product_ids: Vec<i32> = map_products
+        .values()
+        .flatten()
+        .map(|product| product.id)
+        .collect();
+    let tax_rates = Taxes::for_customer(&pool, &state, &customer_user, &product_ids).await?;
+    map_products.values_mut().flatten().for_each(|product| {
+        product.convert_prices(&rate);
+        product.apply_tax(&tax_rates, state.tax_display);
+    });
+    let template = state.tpl_env.get_template("index.html")?;
+    let r = template.render(context!(
+        latest_categories_products => map_products,
+        customer_user => customer_user,
+        tax_note => tax_rates.price_note(state.tax_display),
+    ))?;
+    Ok(Html(r))
 }
@@ -1,5 +1,15 @@
 pub mod about;
+pub mod address;
+pub mod cart;
+pub mod contact;
 pub mod home;
 pub mod order;
 pub mod products;
 pub mod customer;
+pub mod password_reset;
+pub mod email_verification;
+pub mod currency;
+pub mod checkout;
+pub mod payment;
+pub mod returns;
+pub mod staff;
@@ -1,44 +1,69 @@
+use crate::errors::AppError;
 use crate::models::customer::ProfileCustomer;
-use crate::models::order::NewOrderForm;
+use crate::models::money::Money;
+use crate::models::order::OrderStatus;
 use crate::models::state::AppState;
+use crate::repository::invoice_repository::InvoiceRepository;
 use crate::repository::order_repository::OrderRepository;
-use crate::repository::product_repository::ProductRepository;
+use crate::repository::payment_repository::PaymentRepository;
+use crate::repository::return_repository::ReturnRepository;
+use crate::services::invoices::Invoices;
+use crate::services::refunds::Refunds;
+use axum::Extension;
 use axum::extract::{Path, State};
-use axum::response::Html;
-use axum::{Extension, Form};
+use axum::http::header;
+use axum::response::{Html, IntoResponse, Redirect, Response};
 use minijinja::context;
 use sqlx::PgPool;
 use std::collections::HashMap;
 use std::sync::Arc;
+use uuid::Uuid;
 
 pub async fn get_list_orders(
     Extension(pool): Extension<PgPool>,
     Extension(customer_user): Extension<ProfileCustomer>,
     State(state): State<Arc<AppState>>,
-) -> Html<String> {
-    let template = state.tpl_env.get_template("list-orders.html").unwrap();
+) -> Result<Html<String>, AppError> {
+    let template = state.tpl_env.get_template("list-orders.html")?;
 
     let result = OrderRepository::check_not_finished_order_products(&pool, customer_user.id).await;
     match result {
         Ok(order_products) => {
-            let mut orders_sums: HashMap<String, i32> =
+            let mut orders_sums: HashMap<String, Money> =
                 HashMap::with_capacity(order_products.len());
-            let mut order_statuses: HashMap<String, Option<String>> =
+            let mut order_statuses: HashMap<String, OrderStatus> =
                 HashMap::with_capacity(order_products.len());
 
             for (order_id, products) in &order_products {
-                let mut sum = 0;
                 for product in products {
-                    sum += &product.product_price;
-                    order_statuses.insert(order_id.clone(), product.order_status.clone());
+                    order_statuses.insert(order_id.clone(), product.order_status);
+                }
+                // the order-wide amounts are repeated on every line, the first one has them
+                let Some(first) = products.first() else {
+                    continue;
+                };
+                let sum = Money::sum(
+                    first.line_total.currency,
+                    products.iter().map(|product| product.line_total),
+                )
+                .and_then(|sum| first.order_discount.map_or(Ok(sum), |d| sum.checked_sub(d)))
+                .and_then(|sum| {
+                    [first.order_shipping_cost, first.order_tax]
+                        .into_iter()
+                        .flatten()
+                        .try_fold(sum, Money::checked_add)
+                });
+                match sum {
+                    Ok(sum) => {
+                        orders_sums.insert(order_id.clone(), sum);
+                    }
+                    Err(e) => tracing::error!("Order {} has no total: {}", order_id, e),
                 }
-                orders_sums.insert(order_id.clone(), sum);
             }
 
             let r = template
-                .render(context!(customer_user => customer_user, order_products => order_products, orders_sums => orders_sums, order_statuses => order_statuses))
-                .unwrap();
-            Html(r)
+                .render(context!(customer_user => customer_user, order_products => order_products, orders_sums => orders_sums, order_statuses => order_statuses))?;
+            Ok(Html(r))
         }
         Err(e) => {
             tracing::error!(
@@ -46,81 +71,89 @@ pub async fn get_list_orders(
                 e,
                 customer_user
             );
-            let r = template
-                .render(context!(customer_user => customer_user, is_error => true))
-                .unwrap();
-            Html(r)
+            let r = template.render(context!(customer_user => customer_user, is_error => true))?;
+            Ok(Html(r))
         }
     }
 }
 
-pub async fn post_add_product_to_cart(
+pub async fn get_order_by_uuid_and_customer(
+    Path(order_uuid): Path<String>,
+    State(state): State<Arc<AppState>>,
     Extension(pool): Extension<PgPool>,
     Extension(customer_user): Extension<ProfileCustomer>,
-    State(state): State<Arc<AppState>>,
-    Form(form): Form<NewOrderForm>,
-) -> Html<String> {
-    let template = state.tpl_env.get_template("orders.html").unwrap();
+) -> Result<Html<String>, AppError> {
+    render_order_page("orders.html", &order_uuid, &state, &pool, customer_user).await
+}
 
-    // retrieve product sum
-    let product_result = ProductRepository::get_product_by_id(form.product_id, &pool).await;
+pub async fn get_order_receipt(
+    Path(order_uuid): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+) -> Result<Html<String>, AppError> {
+    render_order_page("receipt.html", &order_uuid, &state, &pool, customer_user).await
+}
 
-    match product_result {
-        Ok(product) => {
-            let result = OrderRepository::create_order(
-                &pool,
-                customer_user.id,
-                form.product_id,
-                form.quantity,
-                product.price,
-            )
-            .await;
-            match result {
-                Ok(products_order) => {
-                    let template = state.tpl_env.get_template("orders.html").unwrap();
-                    let r = template
-                        .render(context!(customer_user => customer_user, products_order => products_order))
-                        .unwrap();
-                    Html(r)
-                }
-                Err(e) => {
-                    tracing::error!(
-                        "Error creating order: {:?}. Form: {:?}. User: {:?}",
-                        e,
-                        form,
-                        customer_user
-                    );
-                    let r = template
-                        .render(context!(customer_user => customer_user, is_error => true ))
-                        .unwrap();
-                    Html(r)
-                }
-            }
-        }
-        Err(e) => {
-            tracing::error!(
-                "Error retrieving product: {:?}. Form: {:?}. User: {:?}",
-                e,
-                form,
-                customer_user
-            );
-            let r = template
-                .render(context!(customer_user => customer_user, is_error => true ))
-                .unwrap();
-            return Html(r);
-        }
-    }
+pub async fn get_order_invoice(
+    Path(order_uuid): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+) -> Result<Response, AppError> {
+    let order_id = Uuid::parse_str(&order_uuid).map_err(|_| AppError::NotFound)?;
+    let order =
+        OrderRepository::get_order_by_uuid_and_customer(&pool, order_id, customer_user.id).await?;
+    let invoice = Invoices::for_order(&pool, &order, customer_user.id).await?;
+    let pdf = Invoices::render_pdf(&invoice, &order, &state.invoice_issuer, &state.locale)?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/pdf".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("inline; filename=\"invoice-{}.pdf\"", invoice.number),
+            ),
+        ],
+        pdf,
+    )
+        .into_response())
 }
 
-pub async fn get_order_by_uuid_and_customer(
+// unpaid and paid-but-unshipped orders, a paid one is refunded in full
+pub async fn post_cancel_order(
     Path(order_uuid): Path<String>,
     State(state): State<Arc<AppState>>,
+    Extension(pool): Extension<PgPool>,
     Extension(customer_user): Extension<ProfileCustomer>,
-) -> Html<String> {
-    tracing::info!("order_uuid: {}", order_uuid);
-    let template = state.tpl_env.get_template("orders.html").unwrap();
-    let r = template
-        .render(context!(customer_user => customer_user))
-        .unwrap();
-    Html(r)
+) -> Result<Redirect, AppError> {
+    let order_id = Uuid::parse_str(&order_uuid).map_err(|_| AppError::NotFound)?;
+    Refunds::cancel_order(&pool, state.payments.as_ref(), order_id, customer_user.id).await?;
+    Ok(Redirect::to("/my-orders"))
+}
+
+async fn render_order_page(
+    template_name: &str,
+    order_uuid: &str,
+    state: &AppState,
+    pool: &PgPool,
+    customer_user: ProfileCustomer,
+) -> Result<Html<String>, AppError> {
+    // a malformed uuid can't match any order, treat it the same as someone else's order
+    let order_id = Uuid::parse_str(order_uuid).map_err(|_| AppError::NotFound)?;
+    let order =
+        OrderRepository::get_order_by_uuid_and_customer(pool, order_id, customer_user.id).await?;
+
+    let returns = ReturnRepository::list_for_order(pool, order_id).await?;
+    let refunds = PaymentRepository::list_refunds(pool, order_id).await?;
+    let invoice = InvoiceRepository::find_for_order(pool, order_id).await?;
+
+    let template = state.tpl_env.get_template(template_name)?;
+    let r = template.render(context!(
+        customer_user => customer_user,
+        order => order,
+        returns => returns,
+        refunds => refunds,
+        invoice => invoice,
+    ))?;
+    Ok(Html(r))
 }
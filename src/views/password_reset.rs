@@ -0,0 +1,91 @@
+use crate::errors::AppError;
+use crate::models::customer::{ForgotPasswordForm, ResetPasswordForm};
+use crate::models::state::AppState;
+use crate::repository::customer_repository::{CustomerError, CustomerRepository};
+use crate::repository::password_reset_repository::PasswordResetRepository;
+use crate::services::mailer::EmailMessage;
+use crate::services::validation::{CustomerValidator, FormErrors};
+use axum::extract::{Path, State};
+use axum::response::Html;
+use axum::{Extension, Form};
+use minijinja::context;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+pub async fn get_forgot_password_page(
+    State(state): State<Arc<AppState>>,
+) -> Result<Html<String>, AppError> {
+    let template = state.tpl_env.get_template("forgot-password.html")?;
+    let r = template.render(context!())?;
+    Ok(Html(r))
+}
+
+pub async fn post_forgot_password_page(
+    State(state): State<Arc<AppState>>,
+    Extension(pool): Extension<PgPool>,
+    Form(form): Form<ForgotPasswordForm>,
+) -> Result<Html<String>, AppError> {
+    let template = state.tpl_env.get_template("forgot-password.html")?;
+
+    let email = form.email.trim().to_lowercase();
+    if let Err(e) = CustomerValidator::validate_email(&email) {
+        let form_errors = FormErrors::from([("email", e)]);
+        let r = template.render(context!(form_errors => form_errors, form => form))?;
+        return Ok(Html(r));
+    }
+
+    // the page looks the same whether the account exists or not, so it can't be used to probe emails
+    if let Some(customer_id) = CustomerRepository::find_customer_id_by_email(&pool, &email).await? {
+        let token = PasswordResetRepository::create_token(&pool, customer_id).await?;
+        let message = EmailMessage {
+            to: email,
+            subject: "Reset your password".to_string(),
+            body: format!(
+                "Somebody asked to reset the password of your account.\r\n\r\n\
+                 Follow this link within an hour to choose a new one:\r\n{}/reset-password/{}\r\n\r\n\
+                 If it wasn't you, just ignore this email.",
+                state.base_url, token
+            ),
+        };
+        if let Err(e) = state.mailer.send(&message).await {
+            tracing::error!("{}", e);
+        }
+    }
+
+    let r = template.render(context!(is_sent => true))?;
+    Ok(Html(r))
+}
+
+pub async fn get_reset_password_page(
+    State(state): State<Arc<AppState>>,
+    Extension(pool): Extension<PgPool>,
+    Path(token): Path<String>,
+) -> Result<Html<String>, AppError> {
+    let is_valid = PasswordResetRepository::is_token_valid(&pool, &token).await?;
+    let template = state.tpl_env.get_template("reset-password.html")?;
+    let r = template.render(context!(token => token, is_invalid => !is_valid))?;
+    Ok(Html(r))
+}
+
+pub async fn post_reset_password_page(
+    State(state): State<Arc<AppState>>,
+    Extension(pool): Extension<PgPool>,
+    Path(token): Path<String>,
+    Form(form): Form<ResetPasswordForm>,
+) -> Result<Html<String>, AppError> {
+    let template = state.tpl_env.get_template("reset-password.html")?;
+
+    if let Err(form_errors) =
+        CustomerValidator::validate_new_password(&form.password, &form.confirm_password)
+    {
+        let r = template.render(context!(token => token, form_errors => form_errors))?;
+        return Ok(Html(r));
+    }
+
+    let r = match PasswordResetRepository::reset_password(&pool, &token, &form.password).await {
+        Ok(_) => template.render(context!(is_reset_ok => true))?,
+        Err(CustomerError::NotFound) => template.render(context!(is_invalid => true))?,
+        Err(e) => return Err(e.into()),
+    };
+    Ok(Html(r))
+}
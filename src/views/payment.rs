@@ -0,0 +1,96 @@
+use crate::errors::AppError;
+use crate::models::customer::ProfileCustomer;
+use crate::models::order::OrderStatus;
+use crate::models::payment::PaymentForm;
+use crate::models::state::AppState;
+use crate::repository::order_repository::OrderRepository;
+use crate::repository::payment_repository::PaymentRepository;
+use crate::services::payments::{PaymentError, Payments};
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::{Extension, Form};
+use minijinja::context;
+use sqlx::PgPool;
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub async fn get_pay_order(
+    Path(order_uuid): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+) -> Result<Response, AppError> {
+    render_pay_page(&order_uuid, &state, &pool, customer_user, None).await
+}
+
+pub async fn post_pay_order(
+    Path(order_uuid): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+    Form(form): Form<PaymentForm>,
+) -> Result<Response, AppError> {
+    let order_id = Uuid::parse_str(&order_uuid).map_err(|_| AppError::NotFound)?;
+    let order =
+        OrderRepository::get_order_by_uuid_and_customer(&pool, order_id, customer_user.id).await?;
+
+    match Payments::pay(
+        &pool,
+        state.payments.as_ref(),
+        order_id,
+        customer_user.id,
+        order.total,
+        &form.card_number,
+    )
+    .await
+    {
+        Ok(()) => Ok(Redirect::to(&format!("/order/{}", order_uuid)).into_response()),
+        Err(e @ (PaymentError::Declined(_) | PaymentError::InProgress)) => {
+            let error = e.to_string();
+            render_pay_page(&order_uuid, &state, &pool, customer_user, Some(error)).await
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+// called by the payment provider, authenticated by its signature instead of session and csrf token
+pub async fn post_payment_webhook(
+    State(state): State<Arc<AppState>>,
+    Extension(pool): Extension<PgPool>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<StatusCode, AppError> {
+    Payments::handle_webhook(&pool, state.payments.as_ref(), &headers, &body).await?;
+    Ok(StatusCode::OK)
+}
+
+async fn render_pay_page(
+    order_uuid: &str,
+    state: &AppState,
+    pool: &PgPool,
+    customer_user: ProfileCustomer,
+    payment_error: Option<String>,
+) -> Result<Response, AppError> {
+    let order_id = Uuid::parse_str(order_uuid).map_err(|_| AppError::NotFound)?;
+    let order =
+        OrderRepository::get_order_by_uuid_and_customer(pool, order_id, customer_user.id).await?;
+    if !matches!(
+        order.status,
+        OrderStatus::Placed | OrderStatus::PaymentFailed
+    ) {
+        return Ok(Redirect::to(&format!("/order/{}", order_uuid)).into_response());
+    }
+    let payments = PaymentRepository::list_payments(pool, order_id).await?;
+
+    let template = state.tpl_env.get_template("pay.html")?;
+    let r = template.render(context!(
+        customer_user => customer_user,
+        order => order,
+        payments => payments,
+        payment_error => payment_error,
+        gateway => state.payments.name(),
+    ))?;
+    Ok(Html(r).into_response())
+}
@@ -1,20 +1,24 @@
+use crate::errors::AppError;
+use crate::models::customer::ProfileCustomer;
+use crate::models::money::ExchangeRate;
 use crate::models::products::{CategoryProducts, Pagination};
 use crate::models::state::AppState;
 use crate::repository::product_repository::ProductRepository;
+use crate::services::taxes::Taxes;
 use axum::Extension;
 use axum::extract::{Path, Query, State};
 use axum::response::Html;
 use minijinja::context;
 use sqlx::PgPool;
 use std::sync::Arc;
-use crate::models::customer::ProfileCustomer;
 
 pub async fn get_products(
     pagination: Query<Pagination>,
     State(state): State<Arc<AppState>>,
     Extension(pool): Extension<PgPool>,
     Extension(customer_user): Extension<ProfileCustomer>,
-) -> Html<String> {
+    Extension(rate): Extension<ExchangeRate>,
+) -> Result<Html<String>, AppError> {
     let pagination = pagination.0;
     let limit = 9;
     let current_page = if pagination.page < 1 {
@@ -24,10 +28,14 @@ pub async fn get_products(
     };
     let offset = (current_page - 1) * limit;
 
-    let (ctx_products, count) =
-        ProductRepository::get_products_with_pagination(&pool, offset, limit)
-            .await
-            .unwrap();
+    let (mut ctx_products, count) =
+        ProductRepository::get_products_with_pagination(&pool, offset, limit).await?;
+    let product_ids: Vec<i32> = ctx_products.iter().map(|product| product.id).collect();
+    let tax_rates = Taxes::for_customer(&pool, &state, &customer_user, &product_ids).await?;
+    ctx_products.iter_mut().for_each(|product| {
+        product.convert_prices(&rate);
+        product.apply_tax(&tax_rates, state.tax_display);
+    });
 
     let total_pages: f64 = (count as f64) / (limit as f64);
     let mut page_numbers = Vec::new();
@@ -40,31 +48,31 @@ pub async fn get_products(
 
     let path_url = "products/";
 
-    let template = state.tpl_env.get_template("products.html").unwrap();
-    let r = template
-        .render(context!(
-            customer_user => customer_user,
-            url => path_url,
-            products => ctx_products,
-            current_page => current_page,
-            total_pages => total_pages.ceil(),
-            page_numbers => page_numbers,
-            has_prev => current_page > 1,
-            has_next => current_page < (total_pages.ceil()) as i64,
-            prev_page => current_page - 1,
-            next_page => current_page + 1,
-        ))
-        .unwrap();
-    Html(r)
+    let template = state.tpl_env.get_template("products.html")?;
+    let r = template.render(context!(
+        customer_user => customer_user,
+        url => path_url,
+        products => ctx_products,
+        tax_note => tax_rates.price_note(state.tax_display),
+        current_page => current_page,
+        total_pages => total_pages.ceil(),
+        page_numbers => page_numbers,
+        has_prev => current_page > 1,
+        has_next => current_page < (total_pages.ceil()) as i64,
+        prev_page => current_page - 1,
+        next_page => current_page + 1,
+    ))?;
+    Ok(Html(r))
 }
 
 pub async fn get_products_by_category_name(
-    Path(category_name): Path<CategoryProducts>,
+    Path(requested_category): Path<CategoryProducts>,
     pagination: Query<Pagination>,
     State(state): State<Arc<AppState>>,
     Extension(pool): Extension<PgPool>,
     Extension(customer_user): Extension<ProfileCustomer>,
-) -> Html<String> {
+    Extension(rate): Extension<ExchangeRate>,
+) -> Result<Html<String>, AppError> {
     let pagination = pagination.0;
     let limit = 9;
     let current_page = if pagination.page < 1 {
@@ -74,15 +82,20 @@ pub async fn get_products_by_category_name(
     };
     let offset = (current_page - 1) * limit;
 
-    let (ctx_products, count, category_name, category_description) =
+    let (mut ctx_products, count, category_name, category_description) =
         ProductRepository::get_products_by_category_with_pagination(
-            &category_name.category_name,
+            &requested_category.category_name,
             &pool,
             offset,
             limit,
         )
-        .await
-        .unwrap();
+        .await?;
+    let product_ids: Vec<i32> = ctx_products.iter().map(|product| product.id).collect();
+    let tax_rates = Taxes::for_customer(&pool, &state, &customer_user, &product_ids).await?;
+    ctx_products.iter_mut().for_each(|product| {
+        product.convert_prices(&rate);
+        product.apply_tax(&tax_rates, state.tax_display);
+    });
 
     let total_pages: f64 = (count as f64) / (limit as f64);
     let mut page_numbers = Vec::new();
@@ -93,28 +106,31 @@ pub async fn get_products_by_category_name(
         page_numbers.push(page);
     }
 
-    let template = state.tpl_env.get_template("products.html").unwrap();
-    let mut path_url = String::from("category/");
-    let cat_name = category_name.clone().expect("men");
-    path_url.push_str(cat_name.as_str()); // TODO: clone?
+    let template = state.tpl_env.get_template("products.html")?;
+    // empty categories have no rows to read the name from, fall back to the requested one
+    let path_url = format!(
+        "category/{}",
+        category_name
+            .as_deref()
+            .unwrap_or(requested_category.category_name.as_str())
+    );
 
-    let r = template
-        .render(context!(
-            customer_user => customer_user,
-            url => path_url,
-            category_name => category_name,
-            category_description => category_description,
-            products => ctx_products,
-            current_page => current_page,
-            total_pages => total_pages.ceil(),
-            page_numbers => page_numbers,
-            has_prev => current_page > 1,
-            has_next => current_page < (total_pages.ceil()) as i64,
-            prev_page => current_page - 1,
-            next_page => current_page + 1,
-        ))
-        .unwrap();
-    Html(r)
+    let r = template.render(context!(
+        customer_user => customer_user,
+        url => path_url,
+        category_name => category_name,
+        category_description => category_description,
+        products => ctx_products,
+        tax_note => tax_rates.price_note(state.tax_display),
+        current_page => current_page,
+        total_pages => total_pages.ceil(),
+        page_numbers => page_numbers,
+        has_prev => current_page > 1,
+        has_next => current_page < (total_pages.ceil()) as i64,
+        prev_page => current_page - 1,
+        next_page => current_page + 1,
+    ))?;
+    Ok(Html(r))
 }
 
 pub async fn get_product_by_code(
@@ -122,11 +138,17 @@ pub async fn get_product_by_code(
     State(state): State<Arc<AppState>>,
     Extension(pool): Extension<PgPool>,
     Extension(customer_user): Extension<ProfileCustomer>,
-) -> Html<String> {
-    let ctx_product = ProductRepository::get_product_by_code(&code, &pool)
-        .await
-        .unwrap();
-    let template = state.tpl_env.get_template("single-product.html").unwrap();
-    let r = template.render(context!(product => ctx_product, customer_user => customer_user)).unwrap();
-    Html(r)
+    Extension(rate): Extension<ExchangeRate>,
+) -> Result<Html<String>, AppError> {
+    let mut ctx_product = ProductRepository::get_product_by_code(&code, &pool).await?;
+    let tax_rates = Taxes::for_customer(&pool, &state, &customer_user, &[ctx_product.id]).await?;
+    ctx_product.convert_prices(&rate);
+    ctx_product.apply_tax(&tax_rates, state.tax_display);
+    let template = state.tpl_env.get_template("single-product.html")?;
+    let r = template.render(context!(
+        product => ctx_product,
+        customer_user => customer_user,
+        tax_note => tax_rates.price_note(state.tax_display),
+    ))?;
+    Ok(Html(r))
 }
@@ -0,0 +1,98 @@
+use crate::errors::AppError;
+use crate::models::customer::ProfileCustomer;
+use crate::models::order::{OrderDetails, OrderStatus};
+use crate::models::returns::{ReturnReason, ReturnStatus};
+use crate::models::state::AppState;
+use crate::repository::order_repository::OrderRepository;
+use crate::repository::return_repository::ReturnRepository;
+use crate::services::refunds::{RefundError, Refunds};
+use axum::extract::{Path, State};
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::{Extension, Form};
+use minijinja::context;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub async fn get_return_request_page(
+    Path(order_uuid): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+) -> Result<Response, AppError> {
+    let order = load_order(&order_uuid, &pool, &customer_user).await?;
+    if order.status != OrderStatus::Delivered {
+        return Ok(Redirect::to(&format!("/order/{}", order_uuid)).into_response());
+    }
+    render_return_form(order, &state, &pool, customer_user, HashMap::new(), None).await
+}
+
+pub async fn post_return_request(
+    Path(order_uuid): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+    Form(fields): Form<HashMap<String, String>>,
+) -> Result<Response, AppError> {
+    let order = load_order(&order_uuid, &pool, &customer_user).await?;
+    let comment = fields
+        .get("comment")
+        .map(|comment| comment.trim())
+        .filter(|comment| !comment.is_empty());
+
+    match Refunds::request_return(&pool, &order, customer_user.id, &fields, comment).await {
+        Ok(_) => Ok(Redirect::to(&format!("/order/{}", order_uuid)).into_response()),
+        Err(RefundError::InvalidReturn(message)) => {
+            render_return_form(order, &state, &pool, customer_user, fields, Some(message)).await
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn load_order(
+    order_uuid: &str,
+    pool: &PgPool,
+    customer_user: &ProfileCustomer,
+) -> Result<OrderDetails, AppError> {
+    let order_id = Uuid::parse_str(order_uuid).map_err(|_| AppError::NotFound)?;
+    Ok(OrderRepository::get_order_by_uuid_and_customer(pool, order_id, customer_user.id).await?)
+}
+
+async fn render_return_form(
+    order: OrderDetails,
+    state: &AppState,
+    pool: &PgPool,
+    customer_user: ProfileCustomer,
+    fields: HashMap<String, String>,
+    return_error: Option<String>,
+) -> Result<Response, AppError> {
+    let order_id = Uuid::parse_str(&order.order_id).map_err(|_| AppError::NotFound)?;
+    // what earlier requests don't already take back, rejected ones give it up again
+    let mut returnable: HashMap<i32, i32> = order
+        .lines
+        .iter()
+        .map(|line| (line.id, line.quantity))
+        .collect();
+    for request in ReturnRepository::list_for_order(pool, order_id).await? {
+        if request.status == ReturnStatus::Rejected {
+            continue;
+        }
+        for line in request.lines {
+            if let Some(quantity) = returnable.get_mut(&line.order_line_id) {
+                *quantity -= line.quantity;
+            }
+        }
+    }
+
+    let template = state.tpl_env.get_template("return-request.html")?;
+    let r = template.render(context!(
+        customer_user => customer_user,
+        order => order,
+        returnable => returnable,
+        reasons => ReturnReason::options(),
+        fields => fields,
+        return_error => return_error,
+    ))?;
+    Ok(Html(r).into_response())
+}
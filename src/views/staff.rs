@@ -0,0 +1,198 @@
+use crate::errors::AppError;
+use crate::models::customer::ProfileCustomer;
+use crate::models::money::Money;
+use crate::models::order::OrderStatus;
+use crate::models::returns::{ReturnDecisionForm, ReturnRequest, ReturnStatus};
+use crate::models::state::AppState;
+use crate::repository::order_repository::OrderRepository;
+use crate::repository::payment_repository::PaymentRepository;
+use crate::repository::return_repository::ReturnRepository;
+use crate::services::payments::PaymentError;
+use crate::services::refunds::{RefundError, Refunds};
+use axum::extract::{Path, Query, State};
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::{Extension, Form};
+use minijinja::context;
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct ReturnFilter {
+    // > /staff/returns?status=approved, open requests by default
+    #[serde(default)]
+    status: String,
+}
+
+pub async fn get_staff_returns(
+    Query(filter): Query<ReturnFilter>,
+    State(state): State<Arc<AppState>>,
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+) -> Result<Html<String>, AppError> {
+    let status = ReturnStatus::parse(&filter.status).unwrap_or(ReturnStatus::Requested);
+    let returns = ReturnRepository::list_by_status(&pool, status).await?;
+
+    let template = state.tpl_env.get_template("staff-returns.html")?;
+    let r = template.render(context!(
+        customer_user => customer_user,
+        returns => returns,
+        status => status,
+    ))?;
+    Ok(Html(r))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OrderFilter {
+    // > /staff/orders?status=shipped, paid orders waiting to ship by default
+    #[serde(default)]
+    status: String,
+}
+
+pub async fn get_staff_orders(
+    Query(filter): Query<OrderFilter>,
+    State(state): State<Arc<AppState>>,
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+) -> Result<Html<String>, AppError> {
+    let status = match filter.status.as_str() {
+        "shipped" => OrderStatus::Shipped,
+        _ => OrderStatus::Paid,
+    };
+    let orders = OrderRepository::list_for_fulfilment(&pool, status).await?;
+
+    let template = state.tpl_env.get_template("staff-orders.html")?;
+    let r = template.render(context!(
+        customer_user => customer_user,
+        orders => orders,
+        status => status,
+    ))?;
+    Ok(Html(r))
+}
+
+pub async fn post_ship_order(
+    Path(order_uuid): Path<String>,
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+) -> Result<Redirect, AppError> {
+    let order_id = Uuid::parse_str(&order_uuid).map_err(|_| AppError::NotFound)?;
+    OrderRepository::fulfil(&pool, order_id, OrderStatus::Shipped, customer_user.id).await?;
+    Ok(Redirect::to("/staff/orders"))
+}
+
+pub async fn post_deliver_order(
+    Path(order_uuid): Path<String>,
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+) -> Result<Redirect, AppError> {
+    let order_id = Uuid::parse_str(&order_uuid).map_err(|_| AppError::NotFound)?;
+    OrderRepository::fulfil(&pool, order_id, OrderStatus::Delivered, customer_user.id).await?;
+    Ok(Redirect::to("/staff/orders?status=shipped"))
+}
+
+pub async fn get_staff_return(
+    Path(return_id): Path<i64>,
+    State(state): State<Arc<AppState>>,
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+) -> Result<Response, AppError> {
+    let request = ReturnRepository::get_request(&pool, return_id).await?;
+    render_return_page(request, &state, &pool, customer_user, None, None).await
+}
+
+pub async fn post_approve_return(
+    Path(return_id): Path<i64>,
+    State(state): State<Arc<AppState>>,
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+    Form(form): Form<ReturnDecisionForm>,
+) -> Result<Response, AppError> {
+    let request = ReturnRepository::get_request(&pool, return_id).await?;
+    let order_id = Uuid::parse_str(&request.order_id).map_err(|_| AppError::NotFound)?;
+    let order =
+        OrderRepository::get_order_by_uuid_and_customer(&pool, order_id, request.customer_id)
+            .await?;
+    let Some(refund) = Money::parse(&form.refund_amount, order.currency) else {
+        let error = format!("Enter the refund in {}, e.g. 12.50", order.currency);
+        return render_return_page(
+            request,
+            &state,
+            &pool,
+            customer_user,
+            Some(form),
+            Some(error),
+        )
+        .await;
+    };
+
+    match Refunds::approve_return(
+        &pool,
+        state.payments.as_ref(),
+        &order,
+        &request,
+        customer_user.id,
+        refund,
+        note(&form),
+    )
+    .await
+    {
+        Ok(()) => Ok(Redirect::to(&format!("/staff/returns/{}", return_id)).into_response()),
+        Err(RefundError::Payment(e @ PaymentError::RefundTooLarge { .. })) => {
+            let error = e.to_string();
+            render_return_page(
+                request,
+                &state,
+                &pool,
+                customer_user,
+                Some(form),
+                Some(error),
+            )
+            .await
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn post_reject_return(
+    Path(return_id): Path<i64>,
+    Extension(pool): Extension<PgPool>,
+    Extension(customer_user): Extension<ProfileCustomer>,
+    Form(form): Form<ReturnDecisionForm>,
+) -> Result<Redirect, AppError> {
+    Refunds::reject_return(&pool, return_id, customer_user.id, note(&form)).await?;
+    Ok(Redirect::to(&format!("/staff/returns/{}", return_id)))
+}
+
+fn note(form: &ReturnDecisionForm) -> Option<&str> {
+    Some(form.staff_note.trim()).filter(|note| !note.is_empty())
+}
+
+async fn render_return_page(
+    request: ReturnRequest,
+    state: &AppState,
+    pool: &PgPool,
+    customer_user: ProfileCustomer,
+    form: Option<ReturnDecisionForm>,
+    decision_error: Option<String>,
+) -> Result<Response, AppError> {
+    let order_id = Uuid::parse_str(&request.order_id).map_err(|_| AppError::NotFound)?;
+    let order =
+        OrderRepository::get_order_by_uuid_and_customer(pool, order_id, request.customer_id)
+            .await?;
+    let refunds = PaymentRepository::list_refunds(pool, order_id).await?;
+    let suggested_refund = Refunds::suggested_refund(&order, &request)?;
+
+    let template = state.tpl_env.get_template("staff-return.html")?;
+    let r = template.render(context!(
+        customer_user => customer_user,
+        request => request,
+        order => order,
+        refunds => refunds,
+        suggested_refund => suggested_refund,
+        refund_amount => form.as_ref().map_or(suggested_refund.decimal(), |form| form.refund_amount.clone()),
+        staff_note => form.map(|form| form.staff_note),
+        decision_error => decision_error,
+    ))?;
+    Ok(Html(r).into_response())
+}